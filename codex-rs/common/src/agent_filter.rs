@@ -0,0 +1,178 @@
+//! Frontend-independent filtering and ranking of agent lists.
+//!
+//! [`filter_agents`] turns what a user typed into ranked matches, so every frontend
+//! (the TUI popup, a web picker, ...) lists agents in the same order.
+
+use crate::fuzzy_match::fuzzy_match;
+use codex_protocol::protocol::AgentInfo;
+
+/// Options for [`filter_agents`].
+#[derive(Debug, Clone, Default)]
+pub struct AgentFilterOptions {
+    /// Sort the built-in agent after user agents that rank the same
+    pub builtin_last: bool,
+    /// Recently used agent names, most recent first; listed ahead of the rest when the
+    /// query has no name filter
+    pub recent: Vec<String>,
+}
+
+/// An agent matching a [`filter_agents`] query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentMatch {
+    pub name: String,
+    /// Position of the agent in the list passed to [`filter_agents`]
+    pub index: usize,
+    /// Character positions in `name` matched by the name filter; empty without one
+    pub match_indices: Vec<usize>,
+    /// Fuzzy-match score (higher is better); `0` without a name filter
+    pub score: i32,
+    /// Whether the agent was listed first because it was used recently
+    pub is_recent: bool,
+}
+
+/// Filter and rank `agents` by `query`.
+///
+/// `#tag` words keep only agents carrying every tag (ignoring case); the remaining words
+/// fuzzy-match agent names, best match first. Without a name filter, recently used agents
+/// come first (most recent first), then the rest by name.
+pub fn filter_agents(
+    query: &str,
+    agents: &[AgentInfo],
+    opts: &AgentFilterOptions,
+) -> Vec<AgentMatch> {
+    let (tags, name_query) = split_tag_filters(query);
+    let candidates = agents.iter().enumerate().filter(|(_, a)| {
+        tags.iter()
+            .all(|tag| a.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+    });
+    let builtin_rank = |a: &AgentInfo| opts.builtin_last && a.is_builtin;
+
+    if name_query.is_empty() {
+        let recent_rank = |a: &AgentInfo| opts.recent.iter().position(|name| *name == a.name);
+        let mut all: Vec<(usize, &AgentInfo)> = candidates.collect();
+        all.sort_by(|(_, a), (_, b)| match (recent_rank(a), recent_rank(b)) {
+            (Some(ra), Some(rb)) => ra.cmp(&rb),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => builtin_rank(a)
+                .cmp(&builtin_rank(b))
+                .then_with(|| a.name.cmp(&b.name)),
+        });
+        return all
+            .into_iter()
+            .map(|(index, a)| AgentMatch {
+                name: a.name.clone(),
+                index,
+                match_indices: Vec::new(),
+                score: 0,
+                is_recent: recent_rank(a).is_some(),
+            })
+            .collect();
+    }
+
+    let mut scored: Vec<(i32, Vec<usize>, usize, &AgentInfo)> = candidates
+        .filter_map(|(index, a)| {
+            fuzzy_match(&a.name, &name_query).map(|(score, indices)| (score, indices, index, a))
+        })
+        .collect();
+    scored.sort_by(|(sa, _, _, a), (sb, _, _, b)| {
+        sb.cmp(sa)
+            .then_with(|| builtin_rank(a).cmp(&builtin_rank(b)))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    scored
+        .into_iter()
+        .map(|(score, match_indices, index, a)| AgentMatch {
+            name: a.name.clone(),
+            index,
+            match_indices,
+            score,
+            is_recent: false,
+        })
+        .collect()
+}
+
+/// Split a query into its `#tag` filters and the remaining name fragment.
+fn split_tag_filters(query: &str) -> (Vec<&str>, String) {
+    let mut tags = Vec::new();
+    let mut words = Vec::new();
+    for word in query.split_whitespace() {
+        match word.strip_prefix('#') {
+            Some(tag) if !tag.is_empty() => tags.push(tag),
+            Some(_) => {}
+            None => words.push(word),
+        }
+    }
+    (tags, words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codex_protocol::protocol::AgentSource;
+    use pretty_assertions::assert_eq;
+
+    fn agent(name: &str, tags: &[&str]) -> AgentInfo {
+        AgentInfo {
+            name: name.to_string(),
+            is_builtin: name == "general",
+            tags: tags.iter().map(|tag| (*tag).to_string()).collect(),
+            source: AgentSource::Project,
+            ..Default::default()
+        }
+    }
+
+    fn agents() -> Vec<AgentInfo> {
+        vec![
+            agent("general", &[]),
+            agent("researcher", &["research"]),
+            agent("code-reviewer", &["review", "rust"]),
+            agent("doc-reviewer", &["review", "docs"]),
+        ]
+    }
+
+    fn names(matches: &[AgentMatch]) -> Vec<&str> {
+        matches.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn empty_query_lists_recent_agents_then_by_name() {
+        let opts = AgentFilterOptions {
+            builtin_last: true,
+            recent: vec!["doc-reviewer".to_string()],
+        };
+        let matches = filter_agents("", &agents(), &opts);
+
+        assert_eq!(
+            names(&matches),
+            vec!["doc-reviewer", "code-reviewer", "researcher", "general"]
+        );
+        assert_eq!(matches[0].index, 3);
+        assert!(matches[0].is_recent);
+        assert!(!matches[1].is_recent);
+    }
+
+    #[test]
+    fn name_query_ranks_by_score_with_match_indices() {
+        let matches = filter_agents("rev", &agents(), &AgentFilterOptions::default());
+
+        assert_eq!(names(&matches), vec!["code-reviewer", "doc-reviewer"]);
+        assert_eq!(matches[0].match_indices, vec![5, 6, 7]);
+        assert!(matches[0].score > 0);
+        assert_eq!(matches[0].score, matches[1].score);
+    }
+
+    #[test]
+    fn tags_filter_before_name_matching() {
+        let opts = AgentFilterOptions::default();
+        assert_eq!(
+            names(&filter_agents("#REVIEW #rust", &agents(), &opts)),
+            vec!["code-reviewer"]
+        );
+        assert_eq!(
+            names(&filter_agents("doc #review", &agents(), &opts)),
+            vec!["doc-reviewer"]
+        );
+        assert!(filter_agents("#deploy", &agents(), &opts).is_empty());
+    }
+}
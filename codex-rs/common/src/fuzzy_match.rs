@@ -1,7 +1,20 @@
-/// Simple case-insensitive subsequence matcher used for fuzzy filtering.
+/// Score for every matched character.
+const MATCH_SCORE: i32 = 1;
+/// Bonus when a matched character directly follows the previous match.
+const CONTIGUOUS_BONUS: i32 = 10;
+/// Bonus when a matched character starts a word (`code-reviewer`, `codeReviewer`).
+const WORD_BOUNDARY_BONUS: i32 = 8;
+/// Extra bonus when the match starts at the very beginning of the haystack.
+const PREFIX_BONUS: i32 = 10;
+/// Penalty per skipped character between two matched characters.
+const GAP_PENALTY: i32 = 1;
+
+/// Case-insensitive subsequence matcher used for fuzzy filtering.
 ///
-/// Returns the indices (character positions) of the matched characters in the
-/// ORIGINAL `haystack` string and a score where smaller is better.
+/// Returns the best score (higher is better) together with the indices (character
+/// positions) of the matched characters in the ORIGINAL `haystack` string. Matches
+/// starting the haystack or a word, and runs of adjacent characters, score higher;
+/// characters skipped between matches cost a little.
 ///
 /// Unicode correctness: we perform the match on a lowercased copy of the
 /// haystack and needle but maintain a mapping from each character in the
@@ -9,14 +22,15 @@
 /// This ensures the returned indices can be safely used with
 /// `str::chars().enumerate()` consumers for highlighting, even when
 /// lowercasing expands certain characters (e.g., ß → ss, İ → i̇).
-pub fn fuzzy_match(haystack: &str, needle: &str) -> Option<(Vec<usize>, i32)> {
+pub fn fuzzy_match(haystack: &str, needle: &str) -> Option<(i32, Vec<usize>)> {
     if needle.is_empty() {
-        return Some((Vec::new(), i32::MAX));
+        return Some((0, Vec::new()));
     }
 
+    let original: Vec<char> = haystack.chars().collect();
     let mut lowered_chars: Vec<char> = Vec::new();
     let mut lowered_to_orig_char_idx: Vec<usize> = Vec::new();
-    for (orig_idx, ch) in haystack.chars().enumerate() {
+    for (orig_idx, ch) in original.iter().enumerate() {
         for lc in ch.to_lowercase() {
             lowered_chars.push(lc);
             lowered_to_orig_char_idx.push(orig_idx);
@@ -24,57 +38,86 @@ pub fn fuzzy_match(haystack: &str, needle: &str) -> Option<(Vec<usize>, i32)> {
     }
 
     let lowered_needle: Vec<char> = needle.to_lowercase().chars().collect();
+    if lowered_needle.len() > lowered_chars.len() {
+        return None;
+    }
 
-    let mut result_orig_indices: Vec<usize> = Vec::with_capacity(lowered_needle.len());
-    let mut last_lower_pos: Option<usize> = None;
-    let mut cur = 0usize;
-    for &nc in lowered_needle.iter() {
-        let mut found_at: Option<usize> = None;
-        while cur < lowered_chars.len() {
-            if lowered_chars[cur] == nc {
-                found_at = Some(cur);
-                cur += 1;
-                break;
-            }
-            cur += 1;
+    // Only the first lowered character of an original character can start a word.
+    let position_bonus = |pos: usize| {
+        let orig = lowered_to_orig_char_idx[pos];
+        let starts_char = pos == 0 || lowered_to_orig_char_idx[pos - 1] != orig;
+        let at_boundary = starts_char
+            && (orig == 0
+                || matches!(original[orig - 1], '-' | '_' | ' ' | '.' | '/' | ':')
+                || (original[orig - 1].is_lowercase() && original[orig].is_uppercase()));
+        let mut bonus = MATCH_SCORE;
+        if at_boundary {
+            bonus += WORD_BOUNDARY_BONUS;
         }
-        let pos = found_at?;
-        result_orig_indices.push(lowered_to_orig_char_idx[pos]);
-        last_lower_pos = Some(pos);
-    }
-
-    let first_lower_pos = if result_orig_indices.is_empty() {
-        0usize
-    } else {
-        let target_orig = result_orig_indices[0];
-        lowered_to_orig_char_idx
-            .iter()
-            .position(|&oi| oi == target_orig)
-            .unwrap_or(0)
+        if pos == 0 {
+            bonus += PREFIX_BONUS;
+        }
+        bonus
     };
-    // last defaults to first for single-hit; score = extra span between first/last hit
-    // minus needle len (≥0).
-    // Strongly reward prefix matches by subtracting 100 when the first hit is at index 0.
-    let last_lower_pos = last_lower_pos.unwrap_or(first_lower_pos);
-    let window =
-        (last_lower_pos as i32 - first_lower_pos as i32 + 1) - (lowered_needle.len() as i32);
-    let mut score = window.max(0);
-    if first_lower_pos == 0 {
-        score -= 100;
-    }
-
-    result_orig_indices.sort_unstable();
+
+    // best[i][j]: best score matching needle[..=i] with needle[i] at lowered position j;
+    // prev[i][j]: where needle[i - 1] was matched on that best path.
+    let (hay, needle) = (&lowered_chars, &lowered_needle);
+    let mut best: Vec<Vec<Option<i32>>> = vec![vec![None; hay.len()]; needle.len()];
+    let mut prev: Vec<Vec<usize>> = vec![vec![0; hay.len()]; needle.len()];
+    for (j, &c) in hay.iter().enumerate() {
+        if c == needle[0] {
+            best[0][j] = Some(position_bonus(j));
+        }
+    }
+    for i in 1..needle.len() {
+        for j in i..hay.len() {
+            if hay[j] != needle[i] {
+                continue;
+            }
+            let mut candidate: Option<(i32, usize)> = None;
+            for (k, score) in best[i - 1].iter().enumerate().take(j).skip(i - 1) {
+                let Some(score) = *score else {
+                    continue;
+                };
+                let gap = (j - k - 1) as i32;
+                let link = if gap == 0 {
+                    CONTIGUOUS_BONUS
+                } else {
+                    -gap * GAP_PENALTY
+                };
+                if candidate.is_none_or(|(best_score, _)| score + link > best_score) {
+                    candidate = Some((score + link, k));
+                }
+            }
+            if let Some((score, k)) = candidate {
+                best[i][j] = Some(score + position_bonus(j));
+                prev[i][j] = k;
+            }
+        }
+    }
+
+    let last = needle.len() - 1;
+    let (mut j, score) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|s| (j, s)))
+        .max_by(|(ja, a), (jb, b)| a.cmp(b).then_with(|| jb.cmp(ja)))?;
+    let mut result_orig_indices: Vec<usize> = vec![0; needle.len()];
+    for i in (0..needle.len()).rev() {
+        result_orig_indices[i] = lowered_to_orig_char_idx[j];
+        if i > 0 {
+            j = prev[i][j];
+        }
+    }
+
     result_orig_indices.dedup();
-    Some((result_orig_indices, score))
+    Some((score, result_orig_indices))
 }
 
 /// Convenience wrapper to get only the indices for a fuzzy match.
 pub fn fuzzy_indices(haystack: &str, needle: &str) -> Option<Vec<usize>> {
-    fuzzy_match(haystack, needle).map(|(mut idx, _)| {
-        idx.sort_unstable();
-        idx.dedup();
-        idx
-    })
+    fuzzy_match(haystack, needle).map(|(_, idx)| idx)
 }
 
 #[cfg(test)]
@@ -83,24 +126,24 @@ mod tests {
 
     #[test]
     fn ascii_basic_indices() {
-        let (idx, score) = match fuzzy_match("hello", "hl") {
+        let (score, idx) = match fuzzy_match("hello", "hl") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
         assert_eq!(idx, vec![0, 2]);
-        // 'h' at 0, 'l' at 2 -> window 1; start-of-string bonus applies (-100)
-        assert_eq!(score, -99);
+        // 'h' starts the string (1 + 8 + 10); 'l' after a one-character gap (1 - 1)
+        assert_eq!(score, 19);
     }
 
     #[test]
     fn unicode_dotted_i_istanbul_highlighting() {
-        let (idx, score) = match fuzzy_match("İstanbul", "is") {
+        let (score, idx) = match fuzzy_match("İstanbul", "is") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
         assert_eq!(idx, vec![0, 1]);
-        // Matches at lowered positions 0 and 2 -> window 1; start-of-string bonus applies
-        assert_eq!(score, -99);
+        // Matches at lowered positions 0 and 2 -> the combining dot is a one-character gap
+        assert_eq!(score, 19);
     }
 
     #[test]
@@ -110,68 +153,92 @@ mod tests {
 
     #[test]
     fn prefer_contiguous_match_over_spread() {
-        let (_idx_a, score_a) = match fuzzy_match("abc", "abc") {
+        let (score_a, _idx_a) = match fuzzy_match("abc", "abc") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
-        let (_idx_b, score_b) = match fuzzy_match("a-b-c", "abc") {
+        let (score_b, _idx_b) = match fuzzy_match("a-b-c", "abc") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
-        // Contiguous window -> 0; start-of-string bonus -> -100
-        assert_eq!(score_a, -100);
-        // Spread over 5 chars for 3-letter needle -> window 2; with bonus -> -98
-        assert_eq!(score_b, -98);
-        assert!(score_a < score_b);
+        // Contiguous run: 19 for 'a', then 1 + 10 for each adjacent character
+        assert_eq!(score_a, 41);
+        // Word starts after gaps: 19 for 'a', then 1 + 8 - 1 for each later character
+        assert_eq!(score_b, 35);
+        assert!(score_a > score_b);
     }
 
     #[test]
     fn start_of_string_bonus_applies() {
-        let (_idx_a, score_a) = match fuzzy_match("file_name", "file") {
+        let (score_a, _idx_a) = match fuzzy_match("file_name", "file") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
-        let (_idx_b, score_b) = match fuzzy_match("my_file_name", "file") {
+        let (score_b, _idx_b) = match fuzzy_match("my_file_name", "file") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
-        // Start-of-string contiguous -> window 0; bonus -> -100
-        assert_eq!(score_a, -100);
-        // Non-prefix contiguous -> window 0; no bonus -> 0
-        assert_eq!(score_b, 0);
-        assert!(score_a < score_b);
+        // Start-of-string contiguous match
+        assert_eq!(score_a, 52);
+        // Same run at a word start, without the start-of-string bonus
+        assert_eq!(score_b, 42);
+        assert!(score_a > score_b);
     }
 
     #[test]
-    fn empty_needle_matches_with_max_score_and_no_indices() {
-        let (idx, score) = match fuzzy_match("anything", "") {
+    fn empty_needle_matches_everything_without_indices() {
+        let (score, idx) = match fuzzy_match("anything", "") {
             Some(v) => v,
             None => panic!("empty needle should match"),
         };
         assert!(idx.is_empty());
-        assert_eq!(score, i32::MAX);
+        assert_eq!(score, 0);
+    }
+
+    #[test]
+    fn non_subsequences_do_not_match() {
+        assert!(fuzzy_match("reviewer", "xyz").is_none());
+        assert!(fuzzy_match("rev", "review").is_none());
+        assert!(fuzzy_match("", "r").is_none());
     }
 
     #[test]
     fn case_insensitive_matching_basic() {
-        let (idx, score) = match fuzzy_match("FooBar", "foO") {
+        let (score, idx) = match fuzzy_match("FooBar", "foO") {
             Some(v) => v,
             None => panic!("expected a match"),
         };
         assert_eq!(idx, vec![0, 1, 2]);
-        // Contiguous prefix match (case-insensitive) -> window 0 with bonus
-        assert_eq!(score, -100);
+        // Contiguous prefix match (case-insensitive)
+        assert_eq!(score, 41);
+        assert_eq!(fuzzy_indices("CodeReviewer", "cr"), Some(vec![0, 4]));
+    }
+
+    #[test]
+    fn scores_rank_prefix_then_word_boundary_then_scattered() {
+        let score = |haystack: &str, needle: &str| match fuzzy_match(haystack, needle) {
+            Some((score, _)) => score,
+            None => panic!("expected a match"),
+        };
+        // Contiguous prefix beats the same letters at a word boundary...
+        assert!(score("review", "rev") > score("code-review", "rev"));
+        // ...which beats the letters scattered mid-word.
+        assert!(score("code-review", "rev") > score("graph-evaluator", "rev"));
+        // Word starts are preferred when picking which characters to highlight.
+        assert_eq!(fuzzy_indices("code-reviewer", "cdr"), Some(vec![0, 2, 5]));
+        // Repeated characters are only highlighted where they matched.
+        assert_eq!(fuzzy_indices("reviewer", "rr"), Some(vec![0, 7]));
     }
 
     #[test]
     fn indices_are_deduped_for_multichar_lowercase_expansion() {
         let needle = "\u{0069}\u{0307}"; // "i" + combining dot above
-        let (idx, score) = match fuzzy_match("İ", needle) {
+        let (score, idx) = match fuzzy_match("İ", needle) {
             Some(v) => v,
             None => panic!("expected a match"),
         };
         assert_eq!(idx, vec![0]);
-        // Lowercasing 'İ' expands to two chars; contiguous prefix -> window 0 with bonus
-        assert_eq!(score, -100);
+        // Lowercasing 'İ' expands to two chars; the dot continues the match contiguously
+        assert_eq!(score, 30);
     }
 }
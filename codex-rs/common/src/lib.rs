@@ -29,6 +29,8 @@ mod config_summary;
 pub use config_summary::create_config_summary_entries;
 // Shared fuzzy matcher (used by TUI selection popups and other UI filtering)
 pub mod fuzzy_match;
+// Shared agent list filtering and ranking for agent pickers
+pub mod agent_filter;
 // Shared model presets used by TUI and MCP server
 pub mod model_presets;
 // Shared approval presets (AskForApproval + Sandbox) used by TUI and MCP server
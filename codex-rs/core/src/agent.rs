@@ -4,17 +4,34 @@
 //! specialized through custom system prompts while inheriting tools and permissions
 //! from the current workspace context.
 
+use crate::bash::try_parse_bash;
+use crate::bash::try_parse_word_only_commands_sequence;
+use crate::config_types::ShellEnvironmentPolicy;
+use crate::error::CodexErr;
 use crate::error::Result;
+use crate::path_utils::resolve_within;
+use crate::protocol::AgentSource;
 use crate::protocol::SandboxPolicy;
 use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
 use serde::Deserialize;
 use serde::Serialize;
+use sha1::Digest;
+use sha1::Sha1;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+use wildmatch::WildMatch;
+
+pub mod executor;
 
 /// Configuration for a single agent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AgentConfig {
     /// The system prompt that defines the agent's behavior
     /// Required if prompt_file is not provided
@@ -26,7 +43,14 @@ pub struct AgentConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prompt_file: Option<String>,
 
-    /// Optional: Override tools (usually inherits from context)
+    /// Optional: Short description shown in agent listings (derived from the prompt if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Optional: Override tools (usually inherits from context). `None` means the agent
+    /// inherits every tool available to the calling context; `Some` limits it to the
+    /// listed tools, or, when every entry is `!`-prefixed, removes those from the
+    /// inherited tools
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<String>>,
 
@@ -41,14 +65,227 @@ pub struct AgentConfig {
     /// Optional: Override permissions (usually inherits from context)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub permissions: Option<String>,
+
+    /// Optional: Override sampling temperature (0.0 - 2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Optional: Override nucleus sampling `top_p` (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
+    /// Optional: Name of a parent agent to inherit unset fields from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+
+    /// Optional: How this agent's prompt combines with an inherited prompt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_mode: Option<PromptMode>,
+
+    /// Optional: How this agent's `tools` combine with inherited tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools_mode: Option<ToolsMode>,
+
+    /// Optional: Command prefixes (e.g. `"git push"`) this agent may run; all others are denied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_commands: Option<Vec<String>>,
+
+    /// Optional: Command prefixes (e.g. `"rm"`) this agent may never run; checked before
+    /// `allowed_commands`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denied_commands: Option<Vec<String>>,
+
+    /// Optional: Seconds a single agent turn may run before it is abandoned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// Optional: How many times a failed turn is retried (at most [`MAX_AGENT_RETRIES`])
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+
+    /// Optional: Cap on the number of tokens the model may produce per response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+
+    /// Optional: Environment variables set for commands this agent runs; they override
+    /// inherited values
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<HashMap<String, String>>,
+
+    /// Optional: Tags for grouping agents (e.g. `"review"`); `#tag` filters the agent popup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    /// Optional: Namespace the agent is listed under (e.g. `"team"`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// Optional: Set to `false` to hide the agent from listings while keeping its config
+    /// (not inherited through `extends`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// Optional: Other names the agent can be looked up by (case-insensitive, not inherited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+
+    /// Optional: Directory the agent runs in, relative to the project root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+
+    /// Optional: Shell command run (under the agent's sandbox) after the agent's turn;
+    /// its output is appended to the agent's result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after_hook: Option<String>,
+
+    /// Optional: Run `after_hook` even when the agent's turn fails (defaults to `false`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub always_run_hook: Option<bool>,
+
+    /// Optional: Glob patterns (`*`, `?`) matched against the project directory's path;
+    /// the agent is only loaded in projects matching one of them (not inherited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub only_in: Option<Vec<String>>,
+
+    /// Optional: Guidance appended after the base instructions. Without `prompt` or
+    /// `prompt_file`, the session's base instructions are kept and this is added to them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_suffix: Option<String>,
+
+    /// Optional: Shell command run instead of a model turn, making this a command agent.
+    /// The task is passed on stdin and the command's stdout is the result. Can't be
+    /// combined with a prompt, and isn't inherited through `extends`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+}
+
+/// Group [`AgentRegistry::agents_by_group`] files agents without a `group` under.
+pub const UNGROUPED_AGENTS: &str = "ungrouped";
+
+/// Turn timeout used when an agent doesn't set `timeout_secs`.
+pub const DEFAULT_AGENT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Upper bound accepted for `max_retries`.
+pub const MAX_AGENT_RETRIES: u32 = 5;
+
+/// Built-in tool names an agent's `tools` override may refer to.
+pub const KNOWN_AGENT_TOOLS: &[&str] = &[
+    "shell",
+    "local_shell",
+    "unified_exec",
+    crate::exec_command::EXEC_COMMAND_TOOL_NAME,
+    crate::exec_command::WRITE_STDIN_TOOL_NAME,
+    "apply_patch",
+    "update_plan",
+    "view_image",
+    "web_search",
+];
+
+/// How a child agent's prompt combines with the prompt of the agent it `extends`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptMode {
+    /// Append the child prompt after the parent prompt
+    Append,
+    /// Use the child prompt instead of the parent prompt
+    #[default]
+    Replace,
+}
+
+/// How a child agent's `tools` combine with the tools of the agent it `extends`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolsMode {
+    /// Use the parent tools followed by any child tools not already listed
+    Merge,
+    /// Use the child tools instead of the parent tools
+    #[default]
+    Replace,
+    /// Ignore the parent tools and inherit every tool of the calling context; set by
+    /// `tools = "inherit"`
+    Inherit,
+}
+
+/// What running an agent does, as returned by [`AgentConfig::kind`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AgentKind {
+    /// A model turn driven by the agent's prompt
+    #[default]
+    Llm,
+    /// A shell command, run under the agent's sandbox, that gets the task on stdin and
+    /// whose stdout is the agent's result; set by `run`
+    Command { run: String },
+}
+
+/// How [`AgentRegistry::list_agent_details_sorted`] orders agents.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AgentSortOrder {
+    /// The built-in agent first, then ungrouped agents, then by group and name
+    #[default]
+    BuiltinFirst,
+    /// By name only
+    Alphabetical,
+    /// The named agents first, most recently used first, then the rest in
+    /// [`Self::BuiltinFirst`] order
+    RecentlyUsed(Vec<String>),
+}
+
+/// `tools` as written in an agents file: a list of tool names, or the `"inherit"`
+/// sentinel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ToolsSetting {
+    Inherit,
+    List(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for ToolsSetting {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Keyword(String),
+            List(Vec<String>),
+        }
+
+        match Written::deserialize(deserializer)? {
+            Written::List(tools) => Ok(ToolsSetting::List(tools)),
+            Written::Keyword(keyword) if keyword.trim().eq_ignore_ascii_case("inherit") => {
+                Ok(ToolsSetting::Inherit)
+            }
+            Written::Keyword(keyword) => Err(serde::de::Error::custom(format!(
+                "tools must be a list of tool names or \"inherit\", got \"{keyword}\""
+            ))),
+        }
+    }
 }
 
 impl AgentConfig {
-    /// Validate that the config has either prompt or prompt_file
+    /// Start building an agent config field by field; see [`AgentConfigBuilder`].
+    pub fn builder() -> AgentConfigBuilder {
+        AgentConfigBuilder::default()
+    }
+
+    /// Validate that the config has either prompt or prompt_file, or a `run` command
+    /// for command agents (agents that `extend` another agent may inherit the prompt
+    /// instead)
     pub fn validate(&self) -> anyhow::Result<()> {
-        if self.prompt.is_none() && self.prompt_file.is_none() {
+        let has_prompt =
+            self.prompt.is_some() || self.prompt_file.is_some() || self.prompt_suffix.is_some();
+        if let Some(run) = &self.run {
+            if run.trim().is_empty() {
+                return Err(anyhow::anyhow!("'run' must not be empty"));
+            }
+            if has_prompt {
+                return Err(anyhow::anyhow!(
+                    "Agent configuration should have either a prompt or a 'run' command, not both"
+                ));
+            }
+        } else if !has_prompt && self.extends.is_none() {
             return Err(anyhow::anyhow!(
-                "Agent configuration must have either 'prompt' or 'prompt_file'"
+                "Agent configuration must have either 'prompt' or 'prompt_file' (or a 'prompt_suffix'), or a 'run' command"
             ));
         }
         if self.prompt.is_some() && self.prompt_file.is_some() {
@@ -56,9 +293,187 @@ impl AgentConfig {
                 "Agent configuration should have either 'prompt' or 'prompt_file', not both"
             ));
         }
+        if let Some(temperature) = self.temperature {
+            validate_temperature(temperature)?;
+        }
+        if let Some(top_p) = self.top_p {
+            validate_top_p(top_p)?;
+        }
+        for key in self.env.iter().flat_map(HashMap::keys) {
+            if key.is_empty() {
+                return Err(anyhow::anyhow!("env keys must not be empty"));
+            }
+            if key.contains('=') {
+                return Err(anyhow::anyhow!("env key '{key}' must not contain '='"));
+            }
+        }
+        if let Some(tools) = &self.tools
+            && tools
+                .iter()
+                .any(|tool| tool.starts_with(EXCLUDED_TOOL_PREFIX))
+            && !is_exclusion_list(tools)
+        {
+            return Err(anyhow::anyhow!(
+                "tools cannot mix excluded ('!name') and listed tool names"
+            ));
+        }
+        if self.timeout_secs == Some(0) {
+            return Err(anyhow::anyhow!("timeout_secs must be greater than 0"));
+        }
+        if self.max_output_tokens == Some(0) {
+            return Err(anyhow::anyhow!("max_output_tokens must be greater than 0"));
+        }
+        if let Some(max_retries) = self.max_retries
+            && max_retries > MAX_AGENT_RETRIES
+        {
+            return Err(anyhow::anyhow!(
+                "max_retries must be at most {MAX_AGENT_RETRIES}, got {max_retries}"
+            ));
+        }
         Ok(())
     }
 
+    /// Reject a `tools` override that names a tool outside `known`.
+    pub fn validate_tools(&self, known: &HashSet<String>) -> anyhow::Result<()> {
+        let unknown = self.unknown_tools(known);
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("unknown tools: {}", unknown.join(", ")))
+        }
+    }
+
+    fn unknown_tools(&self, known: &HashSet<String>) -> Vec<String> {
+        self.tools
+            .iter()
+            .flatten()
+            .filter(|tool| !known.contains(tool.trim_start_matches(EXCLUDED_TOOL_PREFIX)))
+            .cloned()
+            .collect()
+    }
+
+    /// Tools the agent can use given the `inherited` tools of the calling context:
+    /// without `tools` it keeps them all, an exclusion list (`["!shell"]`) removes the
+    /// named tools from them, and a plain list replaces them.
+    pub fn effective_tools(&self, inherited: &[String]) -> Vec<String> {
+        match &self.tools {
+            None => inherited.to_vec(),
+            Some(tools) if is_exclusion_list(tools) => inherited
+                .iter()
+                .filter(|tool| {
+                    !tools
+                        .iter()
+                        .any(|t| excluded_tool(t) == Some(tool.as_str()))
+                })
+                .cloned()
+                .collect(),
+            Some(tools) => tools.clone(),
+        }
+    }
+
+    /// Whether the agent may use `tool`, assuming the calling context offers it.
+    fn allows_tool(&self, tool: &str) -> bool {
+        match &self.tools {
+            None => true,
+            Some(tools) if is_exclusion_list(tools) => {
+                !tools.iter().any(|t| excluded_tool(t) == Some(tool))
+            }
+            Some(tools) => tools.iter().any(|t| t == tool),
+        }
+    }
+
+    /// Guidance appended after the base instructions, trimmed; `None` when unset or blank.
+    pub fn prompt_suffix(&self) -> Option<&str> {
+        self.prompt_suffix
+            .as_deref()
+            .map(str::trim)
+            .filter(|suffix| !suffix.is_empty())
+    }
+
+    /// Whether the agent should be listed; defaults to `true`.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Environment variables this agent sets for its commands; empty when unset.
+    pub fn env_overrides(&self) -> HashMap<String, String> {
+        self.env.clone().unwrap_or_default()
+    }
+
+    /// How long a single turn of this agent may run.
+    pub fn timeout(&self) -> Duration {
+        self.timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_AGENT_TIMEOUT)
+    }
+
+    /// How many times a failed turn is retried; zero when unset.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(0)
+    }
+
+    /// The agent's group, ignoring blank names.
+    pub fn group_name(&self) -> Option<&str> {
+        self.group
+            .as_deref()
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+    }
+
+    /// Output token cap for the agent's responses; `None` keeps the model default.
+    pub fn max_output_tokens(&self) -> Option<u32> {
+        self.max_output_tokens
+    }
+
+    /// The agent's `after_hook` command, ignoring blank commands.
+    pub fn after_hook(&self) -> Option<&str> {
+        self.after_hook
+            .as_deref()
+            .map(str::trim)
+            .filter(|hook| !hook.is_empty())
+    }
+
+    /// Stable hash of the config's fields, for caching work derived from it.
+    ///
+    /// `extends`, `prompt_mode` and `tools_mode` are skipped: they only steer inheritance,
+    /// which registry configs have already had applied. Prompt files are loaded into
+    /// `prompt`, so editing a prompt file changes the hash once the agent is reloaded.
+    /// The hash doesn't depend on `env`'s iteration order and is the same across runs.
+    pub fn config_hash(&self) -> u64 {
+        let env: Option<BTreeMap<&String, &String>> =
+            self.env.as_ref().map(|env| env.iter().collect());
+        let fields = AgentConfig {
+            env: None,
+            extends: None,
+            prompt_mode: None,
+            tools_mode: None,
+            ..self.clone()
+        };
+        let mut hasher = Sha1::new();
+        // Serializing plain data to JSON can't fail.
+        hasher.update(serde_json::to_vec(&fields).unwrap_or_default());
+        hasher.update(serde_json::to_vec(&env).unwrap_or_default());
+        let digest = hasher.finalize();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_be_bytes(bytes)
+    }
+
+    /// What running the agent does: a model turn, or the `run` command for command agents.
+    pub fn kind(&self) -> AgentKind {
+        match self.run.as_deref().map(str::trim) {
+            Some(run) if !run.is_empty() => AgentKind::Command {
+                run: run.to_string(),
+            },
+            _ => AgentKind::Llm,
+        }
+    }
+
+    /// Whether `after_hook` also runs after a failed turn; defaults to `false`.
+    pub fn always_run_hook(&self) -> bool {
+        self.always_run_hook.unwrap_or(false)
+    }
+
     /// Get the effective prompt, loading from file if necessary
     pub fn get_prompt(&mut self, agents_dir: Option<&Path>) -> anyhow::Result<String> {
         if let Some(prompt) = &self.prompt {
@@ -72,9 +487,7 @@ impl AgentConfig {
                 PathBuf::from(prompt_file)
             };
 
-            let prompt_content = std::fs::read_to_string(&full_path).map_err(|e| {
-                anyhow::anyhow!("Cannot read prompt file '{}': {}", full_path.display(), e)
-            })?;
+            let prompt_content = read_prompt_file(&full_path, DEFAULT_MAX_PROMPT_FILE_BYTES)?;
 
             // Cache the loaded prompt
             self.prompt = Some(prompt_content.clone());
@@ -84,6 +497,54 @@ impl AgentConfig {
         }
     }
 
+    /// Re-read the prompt file, replacing the cached prompt. Inline prompts are left
+    /// untouched, and the previous prompt is kept if the file can't be read.
+    pub fn reload_prompt(&mut self, agents_dir: Option<&Path>) -> anyhow::Result<()> {
+        if self.prompt_file.is_none() {
+            return Ok(());
+        }
+        let previous = self.prompt.take();
+        if let Err(e) = self.get_prompt(agents_dir) {
+            self.prompt = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Directory the agent runs in: `working_dir` resolved against `project_root`, or
+    /// `project_root` itself when unset. The directory must exist inside the project.
+    pub fn working_dir_abs(&self, project_root: &Path) -> anyhow::Result<PathBuf> {
+        let Some(dir) = self.working_dir.as_deref() else {
+            return Ok(project_root.to_path_buf());
+        };
+        resolve_within(project_root, dir, &[project_root.to_path_buf()])
+            .filter(|path| path.is_dir())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "working_dir '{dir}' must be an existing directory inside the project root"
+                )
+            })
+    }
+
+    /// Whether the agent is available in `project_dir`: always when `only_in` is unset
+    /// or empty, otherwise when the directory's path matches one of its patterns.
+    pub fn is_available_in(&self, project_dir: &Path) -> bool {
+        let Some(patterns) = self
+            .only_in
+            .as_ref()
+            .filter(|patterns| !patterns.is_empty())
+        else {
+            return true;
+        };
+        let path = project_dir
+            .canonicalize()
+            .unwrap_or_else(|_| project_dir.to_path_buf());
+        let path = path.to_string_lossy();
+        patterns
+            .iter()
+            .any(|pattern| WildMatch::new(pattern.trim()).matches(&path))
+    }
+
     /// Return the sandbox policy override configured for this agent, if any.
     pub fn permissions_policy(&self) -> anyhow::Result<Option<SandboxPolicy>> {
         let Some(raw) = self.permissions.as_ref() else {
@@ -95,11 +556,21 @@ impl AgentConfig {
             return Ok(None);
         }
 
-        parse_permissions_policy(trimmed)
+        parse_sandbox_permissions(trimmed)
             .map(Some)
             .map_err(|e| anyhow::anyhow!("invalid permissions override '{trimmed}': {e}"))
     }
 
+    /// The sandbox policy the agent runs with inside a `session` policy: its requested
+    /// policy restricted to what the session allows, or the session policy when it
+    /// requests none. An agent can narrow its access but never widen it.
+    pub fn effective_policy(&self, session: &SandboxPolicy) -> SandboxPolicy {
+        match self.permissions_policy().ok().flatten() {
+            Some(requested) => restrict_sandbox_policy(&requested, session),
+            None => session.clone(),
+        }
+    }
+
     /// Return the requested model override, trimmed and validated.
     pub fn model_override(&self) -> Option<String> {
         self.model.as_ref().and_then(|m| {
@@ -116,523 +587,5615 @@ impl AgentConfig {
     pub fn reasoning_effort_override(&self) -> Option<ReasoningEffortConfig> {
         self.reasoning_effort
     }
-}
-
-/// Registry of available agents and their configurations
-pub struct AgentRegistry {
-    agents: HashMap<String, AgentConfig>,
-    #[allow(dead_code)]
-    agents_dir: Option<PathBuf>,
-}
-
-impl AgentRegistry {
-    /// Validate that a prompt file path doesn't escape allowed directories
-    fn validate_prompt_path(base_dir: &Path, prompt_file: &str) -> anyhow::Result<PathBuf> {
-        let path = if prompt_file.starts_with('/') {
-            PathBuf::from(prompt_file)
-        } else {
-            base_dir.join(prompt_file)
-        };
 
-        // Canonicalize to resolve ../ and symlinks
-        let canonical = match path.canonicalize() {
-            Ok(p) => p,
-            Err(_) => {
-                return Err(anyhow::anyhow!(
-                    "Security error: Prompt file must be within project .codex or ~/.codex directory"
-                ));
-            }
-        };
+    /// Return the requested sampling temperature override, if in range.
+    pub fn temperature_override(&self) -> Option<f32> {
+        self.temperature
+            .filter(|t| validate_temperature(*t).is_ok())
+    }
 
-        // Get the home/.codex directory (personal root)
-        let home_codex = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine home directory"))?
-            .join(".codex");
+    /// Return the requested `top_p` override, if in range.
+    pub fn top_p_override(&self) -> Option<f32> {
+        self.top_p.filter(|p| validate_top_p(*p).is_ok())
+    }
 
-        // Security check: path must be within the provided base directory (or its children)
-        // or within the personal ~/.codex directory
-        let base_canonical = base_dir
-            .canonicalize()
-            .unwrap_or_else(|_| base_dir.to_path_buf());
-        if !canonical.starts_with(&base_canonical) && !canonical.starts_with(&home_codex) {
-            return Err(anyhow::anyhow!(
-                "Security error: Prompt file must be within project .codex or ~/.codex directory"
-            ));
+    /// This agent's `allowed_commands`/`denied_commands`, or `None` when it sets neither.
+    pub fn command_restrictions(&self) -> Option<CommandRestrictions> {
+        if self.allowed_commands.is_none() && self.denied_commands.is_none() {
+            return None;
         }
+        Some(CommandRestrictions {
+            allowed: self.allowed_commands.clone(),
+            denied: self.denied_commands.clone(),
+        })
+    }
 
-        Ok(canonical)
+    /// Whether this agent may run `argv`; see [`CommandRestrictions::allows`]. With
+    /// neither list set every command is allowed.
+    pub fn command_is_allowed(&self, argv: &[String]) -> bool {
+        self.command_restrictions()
+            .is_none_or(|restrictions| restrictions.allows(argv))
     }
 
-    /// Create a new agent registry, loading project-level then user-level configurations if available
-    pub fn new() -> Result<Self> {
-        let mut agents = HashMap::new();
+    /// Expand `${VAR}` references in `model`, `prompt`, `permissions` and `env` values
+    /// with [`interpolate_env_vars`]. Returns the names of variables `lookup` didn't know.
+    fn interpolate_env(&mut self, lookup: &impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut missing = Vec::new();
+        for value in [&mut self.model, &mut self.prompt, &mut self.permissions]
+            .into_iter()
+            .flatten()
+            .chain(self.env.iter_mut().flat_map(HashMap::values_mut))
+        {
+            *value = interpolate_env_vars(value, lookup, &mut missing);
+        }
+        missing
+    }
 
-        // Add the single default "general" agent
-        agents.insert(
-            "general".to_string(),
-            AgentConfig {
-                prompt: Some("You are a helpful AI assistant. Complete the given task efficiently and accurately.".to_string()),
-                prompt_file: None,
-                tools: None,
-                model: None,
-                reasoning_effort: None,
-                permissions: None,
+    /// Fill in any fields this agent leaves unset from its (already resolved) parent.
+    fn inherit_from(mut self, parent: &AgentConfig) -> AgentConfig {
+        match (self.prompt.take(), parent.prompt.as_ref()) {
+            (Some(own), Some(inherited))
+                if self.prompt_mode.unwrap_or_default() == PromptMode::Append =>
+            {
+                self.prompt = Some(format!("{}\n\n{own}", inherited.trim_end()));
             }
-        );
-
-        // Load project-level then user-level agents, with project taking precedence
-        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let project_root = cwd.join(".codex");
-        let home_root = Self::get_agents_directory();
-
-        fn load_agents_from(root: &Path) -> HashMap<String, AgentConfig> {
-            let mut out = HashMap::new();
-            let path = root.join("agents.toml");
-            if !path.exists() {
-                return out;
+            (Some(own), _) => self.prompt = Some(own),
+            // Only inherit the prompt when this agent doesn't point at its own file, and
+            // isn't a command agent.
+            (None, inherited) if self.prompt_file.is_none() && self.run.is_none() => {
+                self.prompt = inherited.cloned();
+                self.prompt_file = parent.prompt_file.clone();
             }
-            let Ok(content) = std::fs::read_to_string(&path) else {
-                return out;
-            };
-            let Ok(mut parsed) = toml::from_str::<HashMap<String, AgentConfig>>(&content) else {
-                return out;
-            };
-            for (name, mut config) in parsed.clone() {
-                if let Err(e) = config.validate() {
-                    tracing::warn!("Invalid agent config for '{}': {}", name, e);
-                    parsed.remove(&name);
-                    continue;
-                }
-                if let Some(prompt_file) = &config.prompt_file {
-                    if let Ok(safe_path) = AgentRegistry::validate_prompt_path(root, prompt_file) {
-                        if let Ok(prompt) = std::fs::read_to_string(&safe_path) {
-                            config.prompt = Some(prompt);
-                        }
-                    }
-                }
-                if let Some(model) = config.model.as_mut() {
-                    if model.trim().is_empty() {
-                        config.model = None;
-                    } else {
-                        *model = model.trim().to_string();
-                    }
+            (None, _) => {}
+        }
+        match (self.tools.take(), parent.tools.as_ref()) {
+            // Exclusions apply to the parent's tools, or add to the parent's exclusions.
+            (Some(own), Some(inherited)) if is_exclusion_list(&own) => {
+                if is_exclusion_list(inherited) {
+                    let mut excluded = inherited.clone();
+                    excluded.extend(own.into_iter().filter(|tool| !inherited.contains(tool)));
+                    self.tools = Some(excluded);
+                } else {
+                    self.tools = Some(
+                        inherited
+                            .iter()
+                            .filter(|tool| {
+                                !own.iter().any(|t| excluded_tool(t) == Some(tool.as_str()))
+                            })
+                            .cloned()
+                            .collect(),
+                    );
                 }
-                // Ensure any permissions override is valid; fall back to inherited on error.
-                if config.permissions.is_some() {
-                    if let Err(e) = config.permissions_policy() {
-                        tracing::warn!(
-                            "Invalid permissions override for agent '{}': {e}. Falling back to inherited permissions.",
-                            name
-                        );
-                        config.permissions = None;
+            }
+            // Merging onto an exclusion list lifts the exclusions the child lists again.
+            (Some(own), Some(inherited))
+                if self.tools_mode.unwrap_or_default() == ToolsMode::Merge
+                    && is_exclusion_list(inherited) =>
+            {
+                let excluded: Vec<String> = inherited
+                    .iter()
+                    .filter(|t| excluded_tool(t).is_some_and(|tool| !own.iter().any(|o| o == tool)))
+                    .cloned()
+                    .collect();
+                self.tools = (!excluded.is_empty()).then_some(excluded);
+            }
+            (Some(own), Some(inherited))
+                if self.tools_mode.unwrap_or_default() == ToolsMode::Merge =>
+            {
+                let mut merged: Vec<String> = Vec::new();
+                for tool in inherited.iter().chain(&own) {
+                    if !merged.contains(tool) {
+                        merged.push(tool.clone());
                     }
                 }
-                parsed.insert(name.clone(), config);
-            }
-            for (k, v) in parsed {
-                out.insert(k, v);
+                self.tools = Some(merged);
             }
-            out
+            (Some(own), _) => self.tools = Some(own),
+            (None, _) if self.tools_mode == Some(ToolsMode::Inherit) => {}
+            (None, inherited) => self.tools = inherited.cloned(),
         }
-
-        let mut merged: HashMap<String, AgentConfig> = HashMap::new();
-        for (k, v) in load_agents_from(&project_root) {
-            merged.insert(k, v);
+        if self.model.is_none() {
+            self.model = parent.model.clone();
         }
-        if let Some(ref home) = home_root {
-            for (k, v) in load_agents_from(home) {
-                merged.entry(k).or_insert(v);
-            }
+        if self.reasoning_effort.is_none() {
+            self.reasoning_effort = parent.reasoning_effort;
         }
-        let agents_dir = if project_root.exists() {
-            Some(project_root)
-        } else {
-            home_root
-        };
-        for (name, cfg) in merged {
-            agents.insert(name, cfg);
+        if self.permissions.is_none() {
+            self.permissions = parent.permissions.clone();
+        }
+        if self.temperature.is_none() {
+            self.temperature = parent.temperature;
+        }
+        if self.top_p.is_none() {
+            self.top_p = parent.top_p;
+        }
+        if self.allowed_commands.is_none() {
+            self.allowed_commands = parent.allowed_commands.clone();
+        }
+        if self.denied_commands.is_none() {
+            self.denied_commands = parent.denied_commands.clone();
+        }
+        if self.timeout_secs.is_none() {
+            self.timeout_secs = parent.timeout_secs;
+        }
+        if self.max_retries.is_none() {
+            self.max_retries = parent.max_retries;
+        }
+        if self.max_output_tokens.is_none() {
+            self.max_output_tokens = parent.max_output_tokens;
+        }
+        if self.env.is_none() {
+            self.env = parent.env.clone();
+        }
+        if self.tags.is_none() {
+            self.tags = parent.tags.clone();
+        }
+        if self.group.is_none() {
+            self.group = parent.group.clone();
+        }
+        if self.working_dir.is_none() {
+            self.working_dir = parent.working_dir.clone();
+        }
+        if self.after_hook.is_none() {
+            self.after_hook = parent.after_hook.clone();
         }
+        if self.always_run_hook.is_none() {
+            self.always_run_hook = parent.always_run_hook;
+        }
+        if self.prompt_suffix.is_none() {
+            self.prompt_suffix = parent.prompt_suffix.clone();
+        }
+        self
+    }
+}
 
-        Ok(Self { agents, agents_dir })
+/// Fluent builder for [`AgentConfig`]. Every setter fills the field of the same name;
+/// fields that are never set stay `None`.
+#[derive(Debug, Clone, Default)]
+pub struct AgentConfigBuilder {
+    config: AgentConfig,
+}
+
+impl AgentConfigBuilder {
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.config.prompt = Some(prompt.into());
+        self
     }
 
-    /// Get the agents directory path (~/.codex)
-    fn get_agents_directory() -> Option<PathBuf> {
-        std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .ok()
-            .map(|home| PathBuf::from(home).join(".codex"))
+    pub fn prompt_file(mut self, prompt_file: impl Into<String>) -> Self {
+        self.config.prompt_file = Some(prompt_file.into());
+        self
     }
 
-    /// Get an agent configuration by name
-    #[allow(dead_code)]
-    pub fn get_agent(&self, name: &str) -> Option<&AgentConfig> {
-        self.agents.get(name)
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.config.description = Some(description.into());
+        self
     }
 
-    /// Get the system prompt for an agent (falls back to "general" if not found)
-    pub fn get_system_prompt(&self, agent_name: &str) -> String {
-        self.agents
-            .get(agent_name)
-            .or_else(|| self.agents.get("general"))
-            .and_then(|config| config.prompt.clone())
-            .unwrap_or_else(|| "You are a helpful AI assistant.".to_string())
+    pub fn tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.tools = Some(tools.into_iter().map(Into::into).collect());
+        self
     }
 
-    /// List all available agents
-    #[allow(dead_code)]
-    pub fn list_agents(&self) -> Vec<String> {
-        self.agents.keys().cloned().collect()
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.config.model = Some(model.into());
+        self
     }
 
-    /// Get detailed information about all agents
-    pub fn list_agent_details(&self) -> Vec<crate::protocol::AgentInfo> {
-        let mut agents = Vec::new();
+    pub fn reasoning_effort(mut self, reasoning_effort: ReasoningEffortConfig) -> Self {
+        self.config.reasoning_effort = Some(reasoning_effort);
+        self
+    }
 
-        for (name, config) in &self.agents {
-            let description = if let Some(ref prompt) = config.prompt {
-                self.extract_description(prompt)
-            } else {
-                "Agent with file-based prompt".to_string()
-            };
-            agents.push(crate::protocol::AgentInfo {
-                name: name.clone(),
-                description,
-                is_builtin: name == "general",
-            });
-        }
+    pub fn permissions(mut self, permissions: impl Into<String>) -> Self {
+        self.config.permissions = Some(permissions.into());
+        self
+    }
 
-        agents.sort_by(|a, b| {
-            // Built-in agents first, then alphabetical
-            match (a.is_builtin, b.is_builtin) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.name.cmp(&b.name),
-            }
-        });
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.config.temperature = Some(temperature);
+        self
+    }
 
-        agents
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.config.top_p = Some(top_p);
+        self
     }
 
-    /// Return the sandbox override configured for the provided agent, if any.
-    pub fn permissions_policy(&self, agent_name: &str) -> Option<SandboxPolicy> {
-        self.agents
-            .get(agent_name)
-            .and_then(|cfg| cfg.permissions_policy().ok().flatten())
+    pub fn extends(mut self, extends: impl Into<String>) -> Self {
+        self.config.extends = Some(extends.into());
+        self
     }
 
-    /// Return the model override configured for the provided agent, if any.
-    pub fn model_override(&self, agent_name: &str) -> Option<String> {
-        self.agents
-            .get(agent_name)
-            .and_then(|cfg| cfg.model_override())
+    pub fn prompt_mode(mut self, prompt_mode: PromptMode) -> Self {
+        self.config.prompt_mode = Some(prompt_mode);
+        self
     }
 
-    /// Return the reasoning effort override for the provided agent, if any.
-    pub fn reasoning_effort_override(&self, agent_name: &str) -> Option<ReasoningEffortConfig> {
-        self.agents
-            .get(agent_name)
-            .and_then(|cfg| cfg.reasoning_effort_override())
+    pub fn tools_mode(mut self, tools_mode: ToolsMode) -> Self {
+        self.config.tools_mode = Some(tools_mode);
+        self
     }
 
-    /// Extract brief description from prompt
-    fn extract_description(&self, prompt: &str) -> String {
-        // Take first line or first sentence as description
-        let first_line = prompt.lines().next().unwrap_or("");
-        let desc = if let Some(pos) = first_line.find('.') {
-            &first_line[..=pos]
+    pub fn allowed_commands<I, S>(mut self, allowed_commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.allowed_commands = Some(allowed_commands.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn denied_commands<I, S>(mut self, denied_commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.denied_commands = Some(denied_commands.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn timeout_secs(mut self, timeout_secs: u64) -> Self {
+        self.config.timeout_secs = Some(timeout_secs);
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.config.max_retries = Some(max_retries);
+        self
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.config.group = Some(group.into());
+        self
+    }
+
+    pub fn max_output_tokens(mut self, max_output_tokens: u32) -> Self {
+        self.config.max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// Add one environment variable; may be called repeatedly.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config
+            .env
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    pub fn tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.tags = Some(tags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.config.enabled = Some(enabled);
+        self
+    }
+
+    pub fn aliases<I, S>(mut self, aliases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.aliases = Some(aliases.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn working_dir(mut self, working_dir: impl Into<String>) -> Self {
+        self.config.working_dir = Some(working_dir.into());
+        self
+    }
+
+    pub fn only_in<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.only_in = Some(patterns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn after_hook(mut self, after_hook: impl Into<String>) -> Self {
+        self.config.after_hook = Some(after_hook.into());
+        self
+    }
+
+    pub fn always_run_hook(mut self, always_run_hook: bool) -> Self {
+        self.config.always_run_hook = Some(always_run_hook);
+        self
+    }
+
+    pub fn prompt_suffix(mut self, prompt_suffix: impl Into<String>) -> Self {
+        self.config.prompt_suffix = Some(prompt_suffix.into());
+        self
+    }
+
+    pub fn run(mut self, run: impl Into<String>) -> Self {
+        self.config.run = Some(run.into());
+        self
+    }
+
+    /// Finish the config, failing if it doesn't pass [`AgentConfig::validate`].
+    pub fn build(self) -> anyhow::Result<AgentConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Resolve `extends` chains so every agent carries its inherited fields.
+/// Agents that are part of (or depend on) an inheritance cycle, or that extend an
+/// unknown agent, are dropped with a warning and recorded in `dropped` as
+/// `(agent_name, reason)`.
+fn resolve_inheritance(
+    agents: HashMap<String, AgentConfig>,
+    dropped: &mut Vec<(String, String)>,
+) -> HashMap<String, AgentConfig> {
+    fn resolve(
+        name: &str,
+        raw: &HashMap<String, AgentConfig>,
+        resolved: &mut HashMap<String, Option<AgentConfig>>,
+        stack: &mut Vec<String>,
+        dropped: &mut Vec<(String, String)>,
+    ) -> Option<AgentConfig> {
+        if let Some(done) = resolved.get(name) {
+            return done.clone();
+        }
+        if stack.iter().any(|n| n == name) {
+            stack.push(name.to_string());
+            let reason = format!("inheritance cycle detected ({})", stack.join(" -> "));
+            stack.pop();
+            tracing::warn!("Agent '{name}' dropped: {reason}");
+            dropped.push((name.to_string(), reason));
+            return None;
+        }
+        let config = raw.get(name)?.clone();
+        let result = match config.extends.clone() {
+            None => Some(config),
+            Some(parent_name) => {
+                stack.push(name.to_string());
+                let parent = if raw.contains_key(&parent_name) {
+                    resolve(&parent_name, raw, resolved, stack, dropped)
+                } else {
+                    None
+                };
+                stack.pop();
+                if parent.is_none() && !dropped.iter().any(|(n, _)| n == name) {
+                    let reason = if raw.contains_key(&parent_name) {
+                        format!("parent agent '{parent_name}' could not be resolved")
+                    } else {
+                        format!("extends unknown agent '{parent_name}'")
+                    };
+                    tracing::warn!("Agent '{name}' dropped: {reason}");
+                    dropped.push((name.to_string(), reason));
+                }
+                parent.map(|parent| config.inherit_from(&parent))
+            }
+        };
+        resolved.insert(name.to_string(), result.clone());
+        result
+    }
+
+    let mut names: Vec<&String> = agents.keys().collect();
+    names.sort();
+    let mut resolved: HashMap<String, Option<AgentConfig>> = HashMap::new();
+    for name in names {
+        resolve(name, &agents, &mut resolved, &mut Vec::new(), dropped);
+    }
+    resolved
+        .into_iter()
+        .filter_map(|(name, config)| config.map(|c| (name, c)))
+        .collect()
+}
+
+/// Longest agent description shown in listings, in characters.
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// Shorten `description` to [`MAX_DESCRIPTION_CHARS`], marking truncation with an ellipsis.
+fn truncate_description(description: &str) -> String {
+    if description.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return description.to_string();
+    }
+    let truncated: String = description
+        .chars()
+        .take(MAX_DESCRIPTION_CHARS - 1)
+        .collect();
+    format!("{}…", truncated.trim_end())
+}
+
+/// Number of prompt lines included in [`crate::protocol::AgentInfo::prompt_preview`].
+const PROMPT_PREVIEW_LINES: usize = 10;
+
+/// The first [`PROMPT_PREVIEW_LINES`] lines of `prompt`, without leading blank lines.
+fn prompt_preview(prompt: &str) -> String {
+    prompt
+        .trim_start()
+        .lines()
+        .take(PROMPT_PREVIEW_LINES)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// An agent's `allowed_commands` and `denied_commands` prefixes, checked before every
+/// shell command its conversation runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandRestrictions {
+    pub allowed: Option<Vec<String>>,
+    pub denied: Option<Vec<String>>,
+}
+
+impl CommandRestrictions {
+    /// Whether `argv` may run. Denied prefixes win over allowed ones; when `allowed` is
+    /// set, the command must match one of its prefixes.
+    ///
+    /// `bash -lc "<script>"` is checked command by command; a script that cannot be split
+    /// into plain commands is only allowed when no restrictions are configured.
+    pub fn allows(&self, argv: &[String]) -> bool {
+        if self.allowed.is_none() && self.denied.is_none() {
+            return true;
+        }
+        if let [bash, flag, script] = argv
+            && bash == "bash"
+            && flag == "-lc"
+        {
+            return try_parse_bash(script)
+                .and_then(|tree| try_parse_word_only_commands_sequence(&tree, script))
+                .is_some_and(|commands| {
+                    !commands.is_empty()
+                        && commands.iter().all(|command| self.allows_single(command))
+                });
+        }
+        self.allows_single(argv)
+    }
+
+    fn allows_single(&self, argv: &[String]) -> bool {
+        let matches_any = |prefixes: &Vec<String>| {
+            prefixes
+                .iter()
+                .any(|prefix| command_has_prefix(argv, prefix))
+        };
+        if self.denied.as_ref().is_some_and(matches_any) {
+            return false;
+        }
+        self.allowed.as_ref().is_none_or(matches_any)
+    }
+}
+
+/// Whether `argv` starts with the whitespace-separated words of `prefix`.
+/// An empty prefix matches nothing.
+fn command_has_prefix(argv: &[String], prefix: &str) -> bool {
+    let words: Vec<&str> = prefix.split_whitespace().collect();
+    !words.is_empty()
+        && argv.len() >= words.len()
+        && argv.iter().zip(&words).all(|(arg, word)| arg == word)
+}
+
+/// Prefix marking a `tools` entry as removed from the inherited tools (`"!shell"`).
+const EXCLUDED_TOOL_PREFIX: char = '!';
+
+/// The tool name an `!`-prefixed `tools` entry excludes.
+fn excluded_tool(entry: &str) -> Option<&str> {
+    entry.strip_prefix(EXCLUDED_TOOL_PREFIX)
+}
+
+/// Whether `tools` lists only exclusions, meaning "every inherited tool except these".
+fn is_exclusion_list(tools: &[String]) -> bool {
+    !tools.is_empty() && tools.iter().all(|tool| excluded_tool(tool).is_some())
+}
+
+fn validate_temperature(value: f32) -> anyhow::Result<()> {
+    if !(0.0..=2.0).contains(&value) {
+        return Err(anyhow::anyhow!(
+            "temperature must be between 0.0 and 2.0, got {value}"
+        ));
+    }
+    Ok(())
+}
+
+fn validate_top_p(value: f32) -> anyhow::Result<()> {
+    if !(0.0..=1.0).contains(&value) {
+        return Err(anyhow::anyhow!(
+            "top_p must be between 0.0 and 1.0, got {value}"
+        ));
+    }
+    Ok(())
+}
+
+/// Parse a `reasoning_effort` value, ignoring case and surrounding whitespace.
+fn parse_reasoning_effort(value: &str) -> anyhow::Result<ReasoningEffortConfig> {
+    match value.trim().to_lowercase().as_str() {
+        "minimal" => Ok(ReasoningEffortConfig::Minimal),
+        "low" => Ok(ReasoningEffortConfig::Low),
+        "medium" => Ok(ReasoningEffortConfig::Medium),
+        "high" => Ok(ReasoningEffortConfig::High),
+        _ => Err(anyhow::anyhow!(
+            "reasoning_effort must be one of minimal, low, medium or high, got '{value}'"
+        )),
+    }
+}
+
+/// A problem found while loading agent definitions, surfaced so users can see why an
+/// agent is missing or behaves differently than configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentLoadError {
+    /// Agent the problem applies to, or `None` when the whole file failed to load
+    pub agent: Option<String>,
+    /// The agents file the definition came from
+    pub path: PathBuf,
+    pub message: String,
+    /// When this definition was ignored because another file defines the same agent,
+    /// the file whose definition is used instead
+    pub shadowed_by: Option<PathBuf>,
+}
+
+impl AgentLoadError {
+    /// `agent` in `path` is hidden by the definition in `winner`.
+    fn shadowed(agent: String, path: PathBuf, winner: PathBuf) -> Self {
+        Self {
+            message: format!(
+                "duplicate definition; the one in '{}' takes precedence",
+                winner.display()
+            ),
+            agent: Some(agent),
+            path,
+            shadowed_by: Some(winner),
+        }
+    }
+}
+
+impl std::fmt::Display for AgentLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.agent {
+            Some(agent) => write!(f, "{} [{agent}]: {}", self.path.display(), self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// Name of the agent that ships with Codex.
+const BUILTIN_AGENT_NAME: &str = "general";
+
+/// System prompt of the built-in "general" agent, also used when no agent prompt can be
+/// resolved. A `[general]` entry in an agents file replaces it.
+pub const DEFAULT_GENERAL_PROMPT: &str =
+    "You are a helpful AI assistant. Complete the given task efficiently and accurately.";
+
+/// Environment variable that disables the built-in agent like `[settings] disable_builtin`.
+const DISABLE_BUILTIN_AGENT_ENV_VAR: &str = "CODEX_DISABLE_BUILTIN_AGENT";
+
+/// Registry-wide options from the `[settings]` table of an agents file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct AgentSettings {
+    /// Omit the built-in "general" agent when user agents are defined
+    #[serde(default)]
+    pub disable_builtin: bool,
+    /// Largest prompt file, in bytes, that agents in this file may load
+    /// (defaults to [`DEFAULT_MAX_PROMPT_FILE_BYTES`])
+    #[serde(default)]
+    pub max_prompt_file_bytes: Option<u64>,
+    /// Agent to pre-select in this project instead of "general"
+    #[serde(default)]
+    pub default_agent: Option<String>,
+    /// Model to use for agents that set `reasoning_effort` (the key, e.g. `high`) but no
+    /// `model`
+    #[serde(default)]
+    pub effort_model_map: HashMap<String, String>,
+    /// Models agents may request; an agent naming any other model falls back to the
+    /// inherited one. Empty allows every model
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// Trim trailing whitespace from prompt lines and collapse runs of three or more
+    /// blank lines to two
+    #[serde(default)]
+    pub normalize_prompts: bool,
+    /// How agent names in this file are cased (see [`AgentNameCase`])
+    #[serde(default)]
+    pub agent_name_case: Option<AgentNameCase>,
+    /// Lowest reasoning effort agents run with; lower requests are raised to it
+    #[serde(default, deserialize_with = "deserialize_effort_bound")]
+    pub min_effort: Option<ReasoningEffortConfig>,
+    /// Highest reasoning effort agents run with; higher requests are lowered to it
+    #[serde(default, deserialize_with = "deserialize_effort_bound")]
+    pub max_effort: Option<ReasoningEffortConfig>,
+    /// Most agents one turn runs at the same time (defaults to
+    /// [`DEFAULT_MAX_CONCURRENT_AGENTS`])
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+/// Read `min_effort`/`max_effort` with the same leniency as an agent's
+/// `reasoning_effort`.
+fn deserialize_effort_bound<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<ReasoningEffortConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_reasoning_effort(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Position of `effort` from least (`minimal`) to most (`high`) reasoning.
+fn effort_rank(effort: ReasoningEffortConfig) -> u8 {
+    match effort {
+        ReasoningEffortConfig::Minimal => 0,
+        ReasoningEffortConfig::Low => 1,
+        ReasoningEffortConfig::Medium => 2,
+        ReasoningEffortConfig::High => 3,
+    }
+}
+
+/// How agent names are cased when an agents file is loaded. Names are always trimmed,
+/// and two names in one file that differ only in case are reported, keeping the first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentNameCase {
+    /// Keep names as written; lookups still ignore case
+    #[default]
+    Preserve,
+    /// Lowercase every name
+    Lower,
+}
+
+impl AgentNameCase {
+    /// Trim `name` and apply this case policy to it.
+    fn normalize(self, name: &str) -> String {
+        match self {
+            AgentNameCase::Preserve => name.trim().to_string(),
+            AgentNameCase::Lower => name.trim().to_lowercase(),
+        }
+    }
+}
+
+impl AgentSettings {
+    /// Combine settings from two files; a flag enabled in either wins, and for values
+    /// set in both, `self` wins.
+    fn merge(self, other: AgentSettings) -> AgentSettings {
+        AgentSettings {
+            disable_builtin: self.disable_builtin || other.disable_builtin,
+            max_prompt_file_bytes: self.max_prompt_file_bytes.or(other.max_prompt_file_bytes),
+            default_agent: self.default_agent.or(other.default_agent),
+            effort_model_map: {
+                let mut map = other.effort_model_map;
+                map.extend(self.effort_model_map);
+                map
+            },
+            allowed_models: if self.allowed_models.is_empty() {
+                other.allowed_models
+            } else {
+                self.allowed_models
+            },
+            normalize_prompts: self.normalize_prompts || other.normalize_prompts,
+            agent_name_case: self.agent_name_case.or(other.agent_name_case),
+            min_effort: self.min_effort.or(other.min_effort),
+            max_effort: self.max_effort.or(other.max_effort),
+            max_concurrent: self.max_concurrent.or(other.max_concurrent),
+        }
+    }
+}
+
+/// Prompt files larger than this are rejected rather than sent to the model.
+pub const DEFAULT_MAX_PROMPT_FILE_BYTES: u64 = 1024 * 1024;
+
+/// How many agents one turn runs at the same time unless `[settings] max_concurrent`
+/// says otherwise.
+pub const DEFAULT_MAX_CONCURRENT_AGENTS: usize = 4;
+
+/// Tidy a prompt written by hand: trailing whitespace (including tabs) is trimmed from
+/// every line and runs of three or more blank lines are collapsed to two.
+fn normalize_prompt(prompt: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut blank_run = 0;
+    for line in prompt.split('\n') {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_run += 1;
+            if blank_run > 2 {
+                continue;
+            }
         } else {
-            first_line
+            blank_run = 0;
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Look up `var` in the process environment, for expanding `${VAR}` in agents files.
+fn process_env(var: &str) -> Option<String> {
+    std::env::var(var).ok()
+}
+
+/// Expand `${VAR}` references in `value` using `lookup`. `$${VAR}` yields a literal
+/// `${VAR}`; an unterminated `${` is kept as is. Variables `lookup` doesn't know expand
+/// to an empty string and are appended to `missing`.
+fn interpolate_env_vars(
+    value: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+    missing: &mut Vec<String>,
+) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(escaped) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+        } else if let Some(reference) = tail.strip_prefix("${")
+            && let Some(end) = reference.find('}')
+        {
+            let var = &reference[..end];
+            match lookup(var) {
+                Some(expanded) => out.push_str(&expanded),
+                None => missing.push(var.to_string()),
+            }
+            rest = &reference[end + 1..];
+        } else {
+            out.push('$');
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Read a prompt file, rejecting files over `max_bytes` before reading them and files
+/// that aren't valid UTF-8.
+fn read_prompt_file(path: &Path, max_bytes: u64) -> anyhow::Result<String> {
+    let len = std::fs::metadata(path)
+        .map_err(|e| prompt_read_error(path, e))?
+        .len();
+    check_prompt_file_len(path, len, max_bytes)?;
+    let bytes = std::fs::read(path).map_err(|e| prompt_read_error(path, e))?;
+    decode_prompt_file(path, bytes)
+}
+
+/// Async counterpart of [`read_prompt_file`], reading through `tokio::fs`.
+async fn read_prompt_file_async(path: &Path, max_bytes: u64) -> anyhow::Result<String> {
+    let len = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| prompt_read_error(path, e))?
+        .len();
+    check_prompt_file_len(path, len, max_bytes)?;
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(|e| prompt_read_error(path, e))?;
+    decode_prompt_file(path, bytes)
+}
+
+fn prompt_read_error(path: &Path, e: std::io::Error) -> anyhow::Error {
+    anyhow::anyhow!("Cannot read prompt file '{}': {e}", path.display())
+}
+
+fn check_prompt_file_len(path: &Path, len: u64, max_bytes: u64) -> anyhow::Result<()> {
+    if len > max_bytes {
+        return Err(anyhow::anyhow!(
+            "Prompt file '{}' is {len} bytes, over the {max_bytes}-byte limit",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn decode_prompt_file(path: &Path, bytes: Vec<u8>) -> anyhow::Result<String> {
+    String::from_utf8(bytes)
+        .map_err(|_| anyhow::anyhow!("Prompt file '{}' is not valid UTF-8", path.display()))
+}
+
+/// Contents of an agents file: agent tables plus the reserved `[settings]` and
+/// `[models]` tables.
+#[derive(Debug, Default)]
+struct AgentsFile {
+    settings: AgentSettings,
+    /// Model aliases from `[models]`, e.g. `fast = "gpt-4o-mini"`
+    models: HashMap<String, String>,
+    agents: HashMap<String, AgentConfig>,
+    /// File each agent was loaded from
+    sources: HashMap<String, PathBuf>,
+    /// Agent names in the order they were defined
+    order: Vec<String>,
+}
+
+/// A prompt file an agent still needs loaded, found while parsing an agents file.
+#[derive(Debug)]
+struct PendingPrompt {
+    agent: String,
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+impl AgentsFile {
+    /// Set `pending.agent`'s prompt to the contents of its prompt file, or drop the agent
+    /// when the file couldn't be read.
+    fn apply_prompt_file(
+        &mut self,
+        path: &Path,
+        pending: PendingPrompt,
+        contents: anyhow::Result<String>,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) {
+        let name = pending.agent;
+        match contents {
+            Ok(prompt) => {
+                if let Some(config) = self.agents.get_mut(&name) {
+                    config.prompt = Some(prompt);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Skipping agent '{name}': {e}");
+                self.agents.remove(&name);
+                self.order.retain(|agent| *agent != name);
+                diagnostics.push(AgentLoadError {
+                    agent: Some(name),
+                    path: path.to_path_buf(),
+                    message: e.to_string(),
+                    shadowed_by: None,
+                });
+            }
+        }
+    }
+
+    /// Add the agents of `file` (read from `path`) that aren't already defined; earlier
+    /// files win, and the agents they shadow are reported.
+    fn absorb(&mut self, file: AgentsFile, path: &Path, diagnostics: &mut Vec<AgentLoadError>) {
+        self.settings = std::mem::take(&mut self.settings).merge(file.settings);
+        for (alias, model) in file.models {
+            self.models.entry(alias).or_insert(model);
+        }
+        let mut agents = file.agents;
+        for name in file.order {
+            let Some(config) = agents.remove(&name) else {
+                continue;
+            };
+            if let Some(winner) = find_ignoring_case(&self.sources, &name) {
+                diagnostics.push(AgentLoadError::shadowed(
+                    name,
+                    path.to_path_buf(),
+                    winner.clone(),
+                ));
+                continue;
+            }
+            self.sources.insert(name.clone(), path.to_path_buf());
+            self.order.push(name.clone());
+            self.agents.insert(name, config);
+        }
+    }
+}
+
+/// An agents file as written, before validation.
+#[derive(Debug, Deserialize)]
+struct RawAgentsFile {
+    #[serde(default)]
+    settings: AgentSettings,
+    #[serde(default)]
+    models: HashMap<String, String>,
+    #[serde(flatten)]
+    agents: OrderedAgents,
+}
+
+/// An agent table as written. `reasoning_effort` is kept as a string so values like
+/// `"High "` can be parsed leniently instead of failing the whole file, and `tools` may
+/// be the `"inherit"` sentinel.
+#[derive(Debug, Deserialize)]
+struct RawAgentConfig {
+    #[serde(default)]
+    reasoning_effort: Option<String>,
+    #[serde(default)]
+    tools: Option<ToolsSetting>,
+    #[serde(flatten)]
+    config: AgentConfig,
+}
+
+/// Agent tables in the order they appear in the file.
+#[derive(Debug, Default)]
+struct OrderedAgents(Vec<(String, RawAgentConfig)>);
+
+impl<'de> Deserialize<'de> for OrderedAgents {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct OrderedAgentsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for OrderedAgentsVisitor {
+            type Value = OrderedAgents;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a map of agent names to agent configurations")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut agents = Vec::new();
+                while let Some(entry) = map.next_entry::<String, RawAgentConfig>()? {
+                    agents.push(entry);
+                }
+                Ok(OrderedAgents(agents))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedAgentsVisitor)
+    }
+}
+
+/// Serialization formats accepted for agent definition files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AgentsFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// Agent definition files read from each `.codex` root. Earlier files win when two
+/// define the same agent.
+const AGENT_FILES: &[(&str, AgentsFormat)] = &[
+    ("agents.toml", AgentsFormat::Toml),
+    ("agents.yaml", AgentsFormat::Yaml),
+    ("agents.json", AgentsFormat::Json),
+];
+
+/// Deserialize the contents of an agents file written in `format`.
+fn parse_agents_file(content: &str, format: AgentsFormat) -> anyhow::Result<RawAgentsFile> {
+    Ok(match format {
+        AgentsFormat::Toml => {
+            let mut raw: RawAgentsFile = toml::from_str(content)?;
+            // `toml` hands tables over sorted by name; recover the order they were written in.
+            if let Ok(doc) = content.parse::<toml_edit::DocumentMut>() {
+                let position: HashMap<&str, usize> = doc
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, _))| (name, i))
+                    .collect();
+                raw.agents
+                    .0
+                    .sort_by_key(|(name, _)| position.get(name.as_str()).copied());
+            }
+            raw
+        }
+        AgentsFormat::Yaml => serde_yaml::from_str(content)?,
+        AgentsFormat::Json => serde_json::from_str(content)?,
+    })
+}
+
+/// Registry of available agents and their configurations
+pub struct AgentRegistry {
+    agents: HashMap<String, AgentConfig>,
+    /// Whether the built-in "general" agent was added to `agents`
+    builtin_included: bool,
+    /// Lowercased agent names and aliases mapped to the agent they resolve to
+    index: HashMap<String, String>,
+    #[allow(dead_code)]
+    agents_dir: Option<PathBuf>,
+    /// Project-level `.codex` directory agents were loaded from
+    project_root: PathBuf,
+    /// Personal `~/.codex` directory agents were loaded from
+    home_root: Option<PathBuf>,
+    /// Agents file each loaded agent came from; the built-in agent has no entry
+    sources: HashMap<String, PathBuf>,
+    /// Which root each loaded agent came from; the built-in agent has no entry
+    origins: HashMap<String, AgentSource>,
+    /// Validated `[settings] default_agent`
+    default_agent: Option<String>,
+    /// `[settings] effort_model_map`, keyed by lowercase reasoning effort
+    effort_model_map: HashMap<String, String>,
+    /// `[settings] allowed_models`; empty allows every model
+    allowed_models: Vec<String>,
+    /// `[settings] min_effort` and `max_effort`, as `(floor, ceiling)`
+    effort_bounds: (Option<ReasoningEffortConfig>, Option<ReasoningEffortConfig>),
+    /// `[settings] max_concurrent`, at least one
+    max_concurrent: usize,
+    /// Agent names in definition order: the built-in agent, then project agents, then
+    /// home agents
+    order: Vec<String>,
+    /// How often each agent was invoked this session
+    usage: AgentUsageStats,
+}
+
+/// Fully-resolved view of an agent after inheritance, validation, and defaults are
+/// applied, as returned by [`AgentRegistry::resolve`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedAgent {
+    pub name: String,
+    pub prompt: String,
+    pub model: Option<String>,
+    pub reasoning_effort: Option<ReasoningEffortConfig>,
+    /// Sandbox override; `None` inherits the parent session's policy
+    pub permissions: Option<SandboxPolicy>,
+    /// Allowed tools; `None` inherits the parent session's tools
+    pub tools: Option<Vec<String>>,
+    /// Agents file the definition came from; `None` for the built-in agent
+    pub source: Option<PathBuf>,
+    /// [`AgentConfig::config_hash`] of the agent's config, for keying caches
+    pub config_hash: u64,
+}
+
+/// How long the agents file watcher waits for a burst of writes to settle before reloading.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Handle returned by [`AgentRegistry::watch`]; dropping it stops watching.
+pub struct AgentRegistryWatcher {
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl AgentRegistry {
+    /// Directories prompt files may live in by default: `base_dir` (the `.codex` root the
+    /// agents file was loaded from) and the personal Codex home.
+    fn default_prompt_roots(base_dir: &Path) -> Vec<PathBuf> {
+        let mut roots = vec![base_dir.to_path_buf()];
+        match crate::config::find_codex_home() {
+            Ok(home) => roots.push(home),
+            Err(e) => tracing::warn!("Cannot determine Codex home directory: {e}"),
+        }
+        roots
+    }
+
+    /// Validate that a prompt file path doesn't escape `allowed_roots`. Relative paths are
+    /// resolved against `base_dir`.
+    fn validate_prompt_path(
+        base_dir: &Path,
+        prompt_file: &str,
+        allowed_roots: &[PathBuf],
+    ) -> anyhow::Result<PathBuf> {
+        resolve_within(base_dir, prompt_file, allowed_roots).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Security error: Prompt file must be within an allowed prompt directory"
+            )
+        })
+    }
+
+    /// Create a new agent registry, loading project-level then user-level configurations if available
+    pub fn new() -> Result<Self> {
+        let (registry, _diagnostics) = Self::new_with_diagnostics()?;
+        Ok(registry)
+    }
+
+    /// Like [`Self::new`], but also returns every problem found while loading so callers
+    /// can show users why an agent was skipped or adjusted.
+    pub fn new_with_diagnostics() -> Result<(Self, Vec<AgentLoadError>)> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let project_root = cwd.join(".codex");
+        let home_root = Self::get_agents_directory();
+
+        let mut diagnostics = Vec::new();
+        let registry = Self::load_from_roots(project_root, home_root, &mut diagnostics);
+        Ok((registry, diagnostics))
+    }
+
+    /// Load agents from the given project and home `.codex` roots, with project taking
+    /// precedence. Unreadable or malformed files are skipped so one bad file doesn't hide
+    /// every agent.
+    fn load_from_roots(
+        project_root: PathBuf,
+        home_root: Option<PathBuf>,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> Self {
+        let mut load = |root: &Path| {
+            // Lenient loading records bad files in `diagnostics` instead of failing.
+            Self::load_agents_from(root, diagnostics, false).unwrap_or_default()
         };
+        let project = load(&project_root);
+        let home = home_root.as_deref().map(load).unwrap_or_default();
+        Self::from_loaded(project_root, home_root, project, home, diagnostics)
+    }
 
-        // Clean up common prefixes
-        desc.trim_start_matches("You are a ")
-            .trim_start_matches("You are an ")
-            .trim_start_matches("You are ")
-            .trim()
-            .to_string()
+    /// Like [`Self::new`], but reads agents files and prompt files with `tokio::fs` so
+    /// loading doesn't block the async runtime.
+    pub async fn load_async() -> Result<Self> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let project_root = cwd.join(".codex");
+        let home_root = Self::get_agents_directory();
+
+        let mut diagnostics = Vec::new();
+        let registry = Self::load_from_roots_async(project_root, home_root, &mut diagnostics).await;
+        Ok(registry)
+    }
+
+    /// Async counterpart of [`Self::load_from_roots`].
+    async fn load_from_roots_async(
+        project_root: PathBuf,
+        home_root: Option<PathBuf>,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> Self {
+        let project = Self::load_agents_from_async(&project_root, diagnostics).await;
+        let home = match home_root.as_deref() {
+            Some(home) => Self::load_agents_from_async(home, diagnostics).await,
+            None => AgentsFile::default(),
+        };
+        Self::from_loaded(project_root, home_root, project, home, diagnostics)
+    }
+
+    /// Like [`Self::load_from_roots`], but an agents file that exists and cannot be read or
+    /// parsed is an error rather than being skipped.
+    fn try_load_from_roots(
+        project_root: PathBuf,
+        home_root: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let mut diagnostics = Vec::new();
+        let project = Self::load_agents_from(&project_root, &mut diagnostics, true)?;
+        let home = match home_root.as_deref() {
+            Some(home) => Self::load_agents_from(home, &mut diagnostics, true)?,
+            None => AgentsFile::default(),
+        };
+        Ok(Self::from_loaded(
+            project_root,
+            home_root,
+            project,
+            home,
+            &mut diagnostics,
+        ))
+    }
+
+    /// Build a registry from `agents` without reading any files, e.g. to test code that
+    /// consumes a registry or to embed agents defined in code. The agents are treated as
+    /// project agents: `extends` is resolved and they are added on top of the built-in
+    /// agent, as for agents loaded from disk.
+    ///
+    /// The registry has no project root, so agents that set `working_dir` fail to run
+    /// instead of resolving it against the process's current directory.
+    pub fn from_agents(agents: HashMap<String, AgentConfig>) -> Self {
+        let mut order: Vec<String> = agents.keys().cloned().collect();
+        order.sort();
+        let origins = order
+            .iter()
+            .map(|name| (name.clone(), AgentSource::Project))
+            .collect();
+        let project = AgentsFile {
+            agents,
+            order,
+            ..Default::default()
+        };
+        let mut registry = Self::from_loaded(
+            PathBuf::new(),
+            None,
+            project,
+            AgentsFile::default(),
+            &mut Vec::new(),
+        );
+        registry.origins = origins;
+        registry
+    }
+
+    /// Merge loaded project and home agents on top of the built-in agent.
+    /// The built-in agent is left out when `disable_builtin` is set (in either file or via
+    /// [`DISABLE_BUILTIN_AGENT_ENV_VAR`]) and at least one user agent is defined.
+    fn from_loaded(
+        project_root: PathBuf,
+        home_root: Option<PathBuf>,
+        project: AgentsFile,
+        home: AgentsFile,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> Self {
+        let settings = project.settings.merge(home.settings);
+        // Project aliases take precedence over home aliases
+        let mut model_aliases = home.models;
+        model_aliases.extend(project.models);
+        let disable_builtin = settings.disable_builtin || builtin_disabled_by_env();
+        let project_agents = project.agents;
+        let home_agents = home.agents;
+        let builtin_included =
+            !disable_builtin || (project_agents.is_empty() && home_agents.is_empty());
+
+        let mut agents = HashMap::new();
+        if builtin_included {
+            // Add the single default "general" agent; a loaded `[general]` replaces it below
+            agents.insert(
+                BUILTIN_AGENT_NAME.to_string(),
+                AgentConfig {
+                    prompt: Some(DEFAULT_GENERAL_PROMPT.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut origins: HashMap<String, AgentSource> = project
+            .sources
+            .keys()
+            .map(|name| (name.clone(), AgentSource::Project))
+            .collect();
+        let mut sources = project.sources;
+        let mut order: Vec<String> = builtin_included
+            .then(|| BUILTIN_AGENT_NAME.to_string())
+            .into_iter()
+            .chain(project.order)
+            .collect();
+
+        // Project agents take precedence over home agents
+        let mut merged = project_agents;
+        let mut home_agents = home_agents;
+        for k in home.order {
+            let Some(v) = home_agents.remove(&k) else {
+                continue;
+            };
+            let home_path = home.sources.get(&k).cloned().unwrap_or_default();
+            if find_ignoring_case(&merged, &k).is_some() {
+                let project_path = find_ignoring_case(&sources, &k)
+                    .cloned()
+                    .unwrap_or_default();
+                diagnostics.push(AgentLoadError::shadowed(k, home_path, project_path));
+                continue;
+            }
+            sources.insert(k.clone(), home_path);
+            origins.insert(k.clone(), AgentSource::Home);
+            order.push(k.clone());
+            merged.insert(k, v);
+        }
+        let agents_dir = if project_root.exists() {
+            Some(project_root.clone())
+        } else {
+            home_root.clone()
+        };
+        for (name, cfg) in merged {
+            agents.insert(name, cfg);
+        }
+        let mut dropped = Vec::new();
+        let mut agents = resolve_inheritance(agents, &mut dropped);
+        // `.codex` lives directly under the project directory `only_in` is matched against.
+        let project_dir = project_root.parent().unwrap_or(&project_root);
+        agents.retain(|name, config| {
+            let available = config.is_available_in(project_dir);
+            if !available {
+                tracing::debug!(
+                    "Agent '{name}' is not available in {}; skipping it",
+                    project_dir.display()
+                );
+            }
+            available
+        });
+        if settings.normalize_prompts {
+            for prompt in agents
+                .values_mut()
+                .filter_map(|config| config.prompt.as_mut())
+            {
+                *prompt = normalize_prompt(prompt);
+            }
+        }
+        // Expand `[models]` aliases (case-sensitive); other model names pass through.
+        for config in agents.values_mut() {
+            if let Some(model) = config.model.as_mut()
+                && let Some(expanded) = model_aliases.get(model.as_str())
+            {
+                *model = expanded.trim().to_string();
+            }
+        }
+        let allowed_models: Vec<String> = settings
+            .allowed_models
+            .iter()
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty())
+            .collect();
+        if !allowed_models.is_empty() {
+            let mut names: Vec<String> = agents.keys().cloned().collect();
+            names.sort();
+            for name in names {
+                let Some(config) = agents.get_mut(&name) else {
+                    continue;
+                };
+                let Some(model) = config.model_override() else {
+                    continue;
+                };
+                if allowed_models.contains(&model) {
+                    continue;
+                }
+                tracing::warn!("Agent '{name}' requests disallowed model '{model}'; ignoring it");
+                config.model = None;
+                diagnostics.push(AgentLoadError {
+                    path: sources.get(&name).cloned().unwrap_or_default(),
+                    agent: Some(name),
+                    message: format!(
+                        "model '{model}' is not in allowed_models; using the inherited model"
+                    ),
+                    shadowed_by: None,
+                });
+            }
+        }
+        let index = build_lookup_index(&agents, &mut dropped);
+        let mut seen = HashSet::new();
+        order.retain(|name| agents.contains_key(name) && seen.insert(name.clone()));
+        diagnostics.extend(dropped.into_iter().map(|(name, message)| AgentLoadError {
+            path: sources.get(&name).cloned().unwrap_or_default(),
+            agent: Some(name),
+            message,
+            shadowed_by: None,
+        }));
+
+        let mut registry = Self {
+            agents,
+            builtin_included,
+            index,
+            agents_dir,
+            project_root,
+            home_root,
+            sources,
+            origins,
+            default_agent: None,
+            effort_model_map: settings
+                .effort_model_map
+                .into_iter()
+                .map(|(effort, model)| (effort.trim().to_lowercase(), model.trim().to_string()))
+                .filter(|(_, model)| !model.is_empty())
+                .collect(),
+            allowed_models,
+            effort_bounds: (None, None),
+            max_concurrent: settings
+                .max_concurrent
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_AGENTS)
+                .max(1),
+            order,
+            usage: AgentUsageStats::default(),
+        };
+        match (settings.min_effort, settings.max_effort) {
+            (Some(min), Some(max)) if effort_rank(min) > effort_rank(max) => {
+                tracing::warn!("min_effort '{min}' is above max_effort '{max}'; ignoring both");
+                diagnostics.push(AgentLoadError {
+                    agent: None,
+                    path: registry.project_root.clone(),
+                    message: format!(
+                        "min_effort '{min}' is above max_effort '{max}'; ignoring both"
+                    ),
+                    shadowed_by: None,
+                });
+            }
+            bounds => registry.effort_bounds = bounds,
+        }
+        if let Some(default_agent) = settings.default_agent {
+            match registry.canonical_name(&default_agent) {
+                Some(name) => registry.default_agent = Some(name.to_string()),
+                None => {
+                    tracing::warn!("Unknown default agent '{default_agent}'; using general");
+                    diagnostics.push(AgentLoadError {
+                        agent: Some(default_agent),
+                        path: registry.project_root.clone(),
+                        message: format!(
+                            "default_agent does not name a known agent; using '{BUILTIN_AGENT_NAME}'"
+                        ),
+                        shadowed_by: None,
+                    });
+                }
+            }
+        }
+        registry
+    }
+
+    /// Load and validate the agents defined in the [`AGENT_FILES`] under `root`, with
+    /// earlier files taking precedence. Missing files yield no agents. An unreadable or
+    /// malformed file is an error when `strict`, and is otherwise recorded in
+    /// `diagnostics` and skipped. Per-agent problems are always recorded in `diagnostics`.
+    fn load_agents_from(
+        root: &Path,
+        diagnostics: &mut Vec<AgentLoadError>,
+        strict: bool,
+    ) -> anyhow::Result<AgentsFile> {
+        let mut combined = AgentsFile::default();
+        for (file_name, format) in AGENT_FILES {
+            let path = root.join(file_name);
+            if !path.exists() {
+                continue;
+            }
+            match Self::load_agents_file(&path, *format, diagnostics) {
+                Ok(file) => combined.absorb(file, &path, diagnostics),
+                Err(e) if strict => return Err(e),
+                Err(e) => {
+                    tracing::warn!("{e}");
+                    diagnostics.push(AgentLoadError {
+                        agent: None,
+                        path,
+                        message: e.to_string(),
+                        shadowed_by: None,
+                    });
+                }
+            }
+        }
+        Ok(combined)
+    }
+
+    /// Async counterpart of lenient [`Self::load_agents_from`]: agents and prompt files
+    /// are read through `tokio::fs`.
+    async fn load_agents_from_async(
+        root: &Path,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> AgentsFile {
+        let mut combined = AgentsFile::default();
+        for (file_name, format) in AGENT_FILES {
+            let path = root.join(file_name);
+            if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                continue;
+            }
+            match Self::load_agents_file_async(&path, *format, diagnostics).await {
+                Ok(file) => combined.absorb(file, &path, diagnostics),
+                Err(e) => {
+                    tracing::warn!("{e}");
+                    diagnostics.push(AgentLoadError {
+                        agent: None,
+                        path,
+                        message: e.to_string(),
+                        shadowed_by: None,
+                    });
+                }
+            }
+        }
+        combined
+    }
+
+    /// Check that every file-based agent can read its prompt file, for CI health checks.
+    /// Returns `(agent, path, error)` for each prompt file that is missing, unreadable,
+    /// or outside the allowed prompt directories, in definition order.
+    pub fn check_prompt_files(&self) -> Vec<(String, PathBuf, std::io::Error)> {
+        let mut problems = Vec::new();
+        for (name, config) in self.iter_in_order() {
+            let Some(prompt_file) = config.prompt_file.as_deref() else {
+                continue;
+            };
+            let root = self
+                .sources
+                .get(name)
+                .and_then(|source| source.parent())
+                .unwrap_or(&self.project_root);
+            let path = root.join(prompt_file);
+            let result = match resolve_within(root, prompt_file, &Self::default_prompt_roots(root))
+            {
+                Some(safe_path) => std::fs::read_to_string(safe_path).map(drop),
+                None if !path.exists() => Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "prompt file does not exist",
+                )),
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "prompt file is outside the allowed prompt directories",
+                )),
+            };
+            if let Err(e) = result {
+                problems.push((name.to_string(), path, e));
+            }
+        }
+        problems
+    }
+
+    /// Check a single agents file without loading it into a registry, for linting.
+    /// Returns every problem found: the file failing to read or parse, and per-agent
+    /// issues such as missing prompt files, invalid permissions, or unknown tools.
+    /// Cross-file checks such as `extends` targets and duplicates are not performed.
+    pub fn validate_file(path: &Path) -> Vec<AgentLoadError> {
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => AgentsFormat::Toml,
+            Some("yaml" | "yml") => AgentsFormat::Yaml,
+            Some("json") => AgentsFormat::Json,
+            _ => {
+                return vec![AgentLoadError {
+                    agent: None,
+                    path: path.to_path_buf(),
+                    message: "agents files must end in .toml, .yaml, or .json".to_string(),
+                    shadowed_by: None,
+                }];
+            }
+        };
+        let mut diagnostics = Vec::new();
+        if let Err(e) = Self::load_agents_file(path, format, &mut diagnostics) {
+            diagnostics.push(AgentLoadError {
+                agent: None,
+                path: path.to_path_buf(),
+                message: e.to_string(),
+                shadowed_by: None,
+            });
+        }
+        diagnostics
+    }
+
+    /// Load and validate the agents defined in a single agents file.
+    fn load_agents_file(
+        path: &Path,
+        format: AgentsFormat,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> anyhow::Result<AgentsFile> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read '{}': {e}", path.display()))?;
+        let (mut file, prompts) =
+            Self::parse_agents_content(path, &content, format, &process_env, diagnostics)?;
+        for pending in prompts {
+            let contents = read_prompt_file(&pending.path, pending.max_bytes);
+            file.apply_prompt_file(path, pending, contents, diagnostics);
+        }
+        Ok(file)
+    }
+
+    /// Async counterpart of [`Self::load_agents_file`], reading the agents file and its
+    /// prompt files through `tokio::fs`.
+    async fn load_agents_file_async(
+        path: &Path,
+        format: AgentsFormat,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> anyhow::Result<AgentsFile> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Cannot read '{}': {e}", path.display()))?;
+        let (mut file, prompts) =
+            Self::parse_agents_content(path, &content, format, &process_env, diagnostics)?;
+        for pending in prompts {
+            let contents = read_prompt_file_async(&pending.path, pending.max_bytes).await;
+            file.apply_prompt_file(path, pending, contents, diagnostics);
+        }
+        Ok(file)
+    }
+
+    /// Parse and validate the agents in `content`, read from `path`, expanding `${VAR}`
+    /// references through `env`. Prompt files are not read here; they are returned for
+    /// the caller to load.
+    fn parse_agents_content(
+        path: &Path,
+        content: &str,
+        format: AgentsFormat,
+        env: &impl Fn(&str) -> Option<String>,
+        diagnostics: &mut Vec<AgentLoadError>,
+    ) -> anyhow::Result<(AgentsFile, Vec<PendingPrompt>)> {
+        let root = path.parent().unwrap_or(Path::new("."));
+        let mut prompts = Vec::new();
+        let mut out = HashMap::new();
+        let mut order = Vec::new();
+        let mut report = |agent: &str, message: String| {
+            diagnostics.push(AgentLoadError {
+                agent: Some(agent.to_string()),
+                path: path.to_path_buf(),
+                message,
+                shadowed_by: None,
+            });
+        };
+        let RawAgentsFile {
+            settings,
+            models,
+            agents: OrderedAgents(parsed),
+        } = parse_agents_file(content, format)
+            .map_err(|e| anyhow::anyhow!("Cannot parse '{}': {e}", path.display()))?;
+        let known_tools: HashSet<String> = KNOWN_AGENT_TOOLS
+            .iter()
+            .map(|tool| (*tool).to_string())
+            .collect();
+        let name_case = settings.agent_name_case.unwrap_or_default();
+        // Lowercased names already taken in this file, mapped to the name that won.
+        let mut taken: HashMap<String, String> = HashMap::new();
+        for (
+            written_name,
+            RawAgentConfig {
+                reasoning_effort,
+                tools,
+                mut config,
+            },
+        ) in parsed
+        {
+            let name = name_case.normalize(&written_name);
+            if name.is_empty() {
+                tracing::warn!("Skipping agent with an empty name in '{}'", path.display());
+                report(&written_name, "agent name must not be empty".to_string());
+                continue;
+            }
+            if let Some(winner) = taken.get(&name.to_lowercase()) {
+                tracing::warn!(
+                    "Agent '{written_name}' has the same name as '{winner}' defined earlier; ignoring it"
+                );
+                report(
+                    &written_name,
+                    format!(
+                        "agent name collides with '{winner}' defined earlier in this file; ignoring it"
+                    ),
+                );
+                continue;
+            }
+            taken.insert(name.to_lowercase(), name.clone());
+            match tools {
+                Some(ToolsSetting::List(tools)) => config.tools = Some(tools),
+                Some(ToolsSetting::Inherit) => config.tools_mode = Some(ToolsMode::Inherit),
+                None => {}
+            }
+            if let Some(effort) = reasoning_effort {
+                match parse_reasoning_effort(&effort) {
+                    Ok(effort) => config.reasoning_effort = Some(effort),
+                    Err(e) => {
+                        tracing::warn!(
+                            "Invalid reasoning_effort for agent '{name}': {e}. Falling back to inherited reasoning effort."
+                        );
+                        report(
+                            &name,
+                            format!("{e}; falling back to inherited reasoning effort"),
+                        );
+                    }
+                }
+            }
+            for var in config.interpolate_env(env) {
+                tracing::warn!("Agent '{name}' references unset environment variable '{var}'");
+                report(
+                    &name,
+                    format!(
+                        "environment variable '{var}' is not set; expanding it to an empty string"
+                    ),
+                );
+            }
+            // Unknown tool names are dropped so a typo doesn't discard the whole agent.
+            if let Err(e) = config.validate_tools(&known_tools) {
+                tracing::warn!("Dropping tools for agent '{name}': {e}");
+                report(&name, format!("{e}; dropping them"));
+                let unknown = config.unknown_tools(&known_tools);
+                if let Some(tools) = config.tools.as_mut() {
+                    tools.retain(|tool| !unknown.contains(tool));
+                }
+            }
+            // Out-of-range sampling overrides fall back to inherited rather than
+            // discarding the whole agent.
+            if let Some(temperature) = config.temperature
+                && let Err(e) = validate_temperature(temperature)
+            {
+                tracing::warn!(
+                    "Invalid temperature override for agent '{name}': {e}. Falling back to inherited temperature."
+                );
+                report(&name, format!("{e}; falling back to inherited temperature"));
+                config.temperature = None;
+            }
+            if let Some(top_p) = config.top_p
+                && let Err(e) = validate_top_p(top_p)
+            {
+                tracing::warn!(
+                    "Invalid top_p override for agent '{name}': {e}. Falling back to inherited top_p."
+                );
+                report(&name, format!("{e}; falling back to inherited top_p"));
+                config.top_p = None;
+            }
+            if let Err(e) = config.validate() {
+                tracing::warn!("Invalid agent config for '{name}': {e}");
+                report(&name, e.to_string());
+                continue;
+            }
+            if config.prompt_suffix.is_some()
+                && (config.prompt.is_some() || config.prompt_file.is_some())
+            {
+                tracing::warn!("Agent '{name}' sets both a full prompt and prompt_suffix");
+                report(
+                    &name,
+                    "sets both a full prompt and prompt_suffix; the suffix is appended to the prompt rather than the base instructions".to_string(),
+                );
+            }
+            let safe_prompt_path = config.prompt_file.as_deref().map(|prompt_file| {
+                AgentRegistry::validate_prompt_path(
+                    root,
+                    prompt_file,
+                    &AgentRegistry::default_prompt_roots(root),
+                )
+            });
+            // A prompt file that can't be found is reported but keeps the agent, which
+            // is then listed as broken.
+            if let (Some(prompt_file), Some(Err(e))) = (&config.prompt_file, &safe_prompt_path) {
+                let message = if root.join(prompt_file).exists() {
+                    e.to_string()
+                } else {
+                    format!("prompt file '{prompt_file}' does not exist")
+                };
+                tracing::warn!("Agent '{name}': {message}");
+                report(&name, message);
+            }
+            if let Some(Ok(safe_path)) = safe_prompt_path {
+                prompts.push(PendingPrompt {
+                    agent: name.clone(),
+                    path: safe_path,
+                    max_bytes: settings
+                        .max_prompt_file_bytes
+                        .unwrap_or(DEFAULT_MAX_PROMPT_FILE_BYTES),
+                });
+            }
+            if let Some(model) = config.model.as_mut() {
+                if model.trim().is_empty() {
+                    config.model = None;
+                } else {
+                    *model = model.trim().to_string();
+                }
+            }
+            // Ensure any permissions override is valid; fall back to inherited on error.
+            if config.permissions.is_some()
+                && let Err(e) = config.permissions_policy()
+            {
+                tracing::warn!(
+                    "Invalid permissions override for agent '{name}': {e}. Falling back to inherited permissions."
+                );
+                report(&name, format!("{e}; falling back to inherited permissions"));
+                config.permissions = None;
+            }
+            order.push(name.clone());
+            out.insert(name, config);
+        }
+        let file = AgentsFile {
+            settings,
+            models,
+            agents: out,
+            sources: HashMap::new(),
+            order,
+        };
+        Ok((file, prompts))
+    }
+
+    /// Watch the project and home agents files and hand a freshly loaded registry to
+    /// `on_change` whenever either changes. Bursts of writes are debounced into a single
+    /// reload, and a file that fails to parse keeps the previous registry in place (no
+    /// callback is made). Watching stops when the returned watcher is dropped.
+    pub fn watch<F>(&self, on_change: F) -> anyhow::Result<AgentRegistryWatcher>
+    where
+        F: Fn(AgentRegistry) + Send + 'static,
+    {
+        let (tx, rx) = std::sync::mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx)?;
+
+        // Watch the directories rather than the files: the file may not exist yet, and many
+        // editors save by replacing the file.
+        let roots: Vec<PathBuf> = std::iter::once(self.project_root.clone())
+            .chain(self.home_root.clone())
+            .collect();
+        let mut watching = false;
+        for root in &roots {
+            match notify::Watcher::watch(&mut watcher, root, notify::RecursiveMode::NonRecursive) {
+                Ok(()) => watching = true,
+                Err(e) => tracing::debug!("Not watching '{}' for agents: {e}", root.display()),
+            }
+        }
+        if !watching {
+            return Err(anyhow::anyhow!("No agents directory exists to watch"));
+        }
+
+        let project_root = self.project_root.clone();
+        let home_root = self.home_root.clone();
+        std::thread::spawn(move || {
+            // Reloading reads the file, so access events must not trigger another reload.
+            let touches_agents_file = |event: &notify::Result<notify::Event>| {
+                event.as_ref().is_ok_and(|event| {
+                    !matches!(event.kind, notify::EventKind::Access(_))
+                        && event.paths.iter().any(|p| {
+                            p.file_name().is_some_and(|n| {
+                                AGENT_FILES.iter().any(|(file_name, _)| n == *file_name)
+                            })
+                        })
+                })
+            };
+            // The loop ends once the watcher (and with it the sender) is dropped.
+            while let Ok(event) = rx.recv() {
+                if !touches_agents_file(&event) {
+                    continue;
+                }
+                // Swallow the rest of the burst before reloading.
+                while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+                match Self::try_load_from_roots(project_root.clone(), home_root.clone()) {
+                    Ok(registry) => on_change(registry),
+                    Err(e) => tracing::warn!("Keeping previous agents after reload failed: {e}"),
+                }
+            }
+        });
+
+        Ok(AgentRegistryWatcher { _watcher: watcher })
+    }
+
+    /// Get the agents directory path (~/.codex)
+    /// Personal agents live in the Codex home, which honors `CODEX_HOME`.
+    fn get_agents_directory() -> Option<PathBuf> {
+        crate::config::find_codex_home().ok()
+    }
+
+    /// Get an agent configuration by name or alias, ignoring case
+    pub fn get_agent(&self, name: &str) -> Option<&AgentConfig> {
+        self.canonical_name(name)
+            .and_then(|canonical| self.agents.get(canonical))
+    }
+
+    /// The name `name` refers to: itself when it's an exact agent name, otherwise the
+    /// agent it matches case-insensitively or as an alias.
+    fn canonical_name<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        if self.agents.contains_key(name) {
+            Some(name)
+        } else {
+            self.index.get(&name.to_lowercase()).map(String::as_str)
+        }
+    }
+
+    /// Agent to pre-select: the `[settings] default_agent` when it names a known agent,
+    /// otherwise "general".
+    pub fn default_agent_name(&self) -> &str {
+        self.default_agent.as_deref().unwrap_or(BUILTIN_AGENT_NAME)
+    }
+
+    /// Agent last used in `project_root`, as recorded by [`save_last_used_agent`].
+    /// Returns its canonical name, or `None` when nothing is stored or the stored agent
+    /// no longer exists.
+    pub fn last_used_agent(&self, project_root: &Path) -> Option<String> {
+        let stored = load_project_state(project_root).last_agent?;
+        self.canonical_name(&stored).map(str::to_string)
+    }
+
+    /// Invocation counts for this registry's agents.
+    pub fn usage(&self) -> &AgentUsageStats {
+        &self.usage
+    }
+
+    /// Count an invocation of the agent `name` refers to, under its canonical name.
+    /// Unknown agents aren't counted.
+    pub fn record_invocation(&self, name: &str) {
+        if let Some(name) = self.canonical_name(name) {
+            self.usage.record_invocation(name);
+        }
+    }
+
+    /// Resolve `name` into the effective configuration a run of that agent would use.
+    pub fn resolve(&self, name: &str) -> Option<ResolvedAgent> {
+        let name = self.canonical_name(name)?;
+        let config = self.agents.get(name)?;
+        Some(ResolvedAgent {
+            name: name.to_string(),
+            prompt: self.get_system_prompt(name),
+            model: config.model_override(),
+            reasoning_effort: config.reasoning_effort_override(),
+            permissions: config.permissions_policy().ok().flatten(),
+            tools: config.tools.clone(),
+            source: self.sources.get(name).cloned(),
+            config_hash: config.config_hash(),
+        })
+    }
+
+    /// Re-read the prompt file of `agent_name` so edits made during a session take effect.
+    /// Agents with inline prompts are left unchanged.
+    pub fn reload_agent_prompt(&mut self, agent_name: &str) -> anyhow::Result<()> {
+        let name = self
+            .canonical_name(agent_name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown agent '{agent_name}'"))?
+            .to_string();
+        let Some(prompt_file) = self
+            .agents
+            .get(&name)
+            .and_then(|config| config.prompt_file.clone())
+        else {
+            return Ok(());
+        };
+        // Resolve against whichever root the file lives under, with the same
+        // containment check used at load time.
+        let roots: Vec<PathBuf> = std::iter::once(self.project_root.clone())
+            .chain(self.home_root.clone())
+            .collect();
+        let mut last_err = None;
+        for root in roots {
+            match Self::validate_prompt_path(
+                &root,
+                &prompt_file,
+                &Self::default_prompt_roots(&root),
+            ) {
+                Ok(_) => {
+                    if let Some(config) = self.agents.get_mut(&name) {
+                        config.reload_prompt(Some(&root))?;
+                    }
+                    return Ok(());
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("No prompt root for agent '{name}'")))
+    }
+
+    /// Get the system prompt for an agent. Unknown agents fall back to "general", or to
+    /// the alphabetically first agent when the built-in agent is disabled.
+    pub fn get_system_prompt(&self, agent_name: &str) -> String {
+        match self.get_system_prompt_parts(agent_name) {
+            (Some(base), Some(suffix)) => format!("{}\n\n{suffix}", base.trim_end()),
+            (Some(base), None) => base,
+            (None, Some(suffix)) => suffix,
+            (None, None) => DEFAULT_GENERAL_PROMPT.to_string(),
+        }
+    }
+
+    /// The agent's prompt split into the base prompt it overrides the session's base
+    /// instructions with (`None` keeps them) and the suffix appended after the base.
+    /// Unknown agents fall back like [`Self::get_system_prompt`].
+    pub fn get_system_prompt_parts(&self, agent_name: &str) -> (Option<String>, Option<String>) {
+        let Some(config) = self
+            .get_agent(agent_name)
+            .or_else(|| self.agents.get(BUILTIN_AGENT_NAME))
+            .or_else(|| {
+                self.agents
+                    .iter()
+                    .min_by(|(a, _), (b, _)| a.cmp(b))
+                    .map(|(_, config)| config)
+            })
+        else {
+            return (Some(DEFAULT_GENERAL_PROMPT.to_string()), None);
+        };
+        let suffix = config.prompt_suffix().map(str::to_string);
+        let base = match (&config.prompt, &suffix) {
+            (Some(prompt), _) => Some(prompt.clone()),
+            (None, Some(_)) => None,
+            (None, None) => Some(DEFAULT_GENERAL_PROMPT.to_string()),
+        };
+        (base, suffix)
+    }
+
+    /// Agents that should be offered to users. Disabled agents are hidden, except the
+    /// built-in agent, which can only be removed with `[settings] disable_builtin`.
+    fn listed_agents(&self) -> impl Iterator<Item = (&String, &AgentConfig)> {
+        self.agents
+            .iter()
+            .filter(|(name, config)| self.is_listed(name, config))
+    }
+
+    /// Whether an agent shows up in listings: it is enabled, or it is the included
+    /// built-in agent.
+    fn is_listed(&self, name: &str, config: &AgentConfig) -> bool {
+        config.is_enabled() || (self.builtin_included && name == BUILTIN_AGENT_NAME)
+    }
+
+    /// Names of listed agents that can use `tool`, in definition order. Tools are
+    /// resolved through `extends`; agents without a `tools` list (`None`) inherit every
+    /// tool of the calling context and so always match.
+    pub fn agents_with_tool(&self, tool: &str) -> Vec<&str> {
+        self.iter_in_order()
+            .filter(|(name, config)| self.is_listed(name, config))
+            .filter(|(_, config)| config.allows_tool(tool))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Iterate agents in the order they were defined, starting with the built-in agent.
+    pub fn iter_in_order(&self) -> impl Iterator<Item = (&str, &AgentConfig)> {
+        self.order
+            .iter()
+            .filter_map(|name| self.agents.get(name).map(|config| (name.as_str(), config)))
+    }
+
+    /// Number of listed agents, counting the built-in agent when it is included.
+    /// Disabled agents are not counted.
+    pub fn len(&self) -> usize {
+        self.listed_agents().count()
+    }
+
+    /// Whether no agents are listed at all (not even the built-in agent).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of listed agents excluding the built-in agent.
+    pub fn custom_len(&self) -> usize {
+        self.listed_agents()
+            .filter(|(name, _)| !(self.builtin_included && *name == BUILTIN_AGENT_NAME))
+            .count()
+    }
+
+    /// List all available agents
+    pub fn list_agents(&self) -> Vec<String> {
+        self.listed_agents().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Get detailed information about all agents, built-in agent first
+    pub fn list_agent_details(&self) -> Vec<crate::protocol::AgentInfo> {
+        self.list_agent_details_sorted(&AgentSortOrder::default())
+    }
+
+    /// Like [`Self::list_agent_details`], but ordered by `order`.
+    pub fn list_agent_details_sorted(
+        &self,
+        order: &AgentSortOrder,
+    ) -> Vec<crate::protocol::AgentInfo> {
+        let mut agents = Vec::new();
+
+        for (name, config) in self.listed_agents() {
+            agents.push(crate::protocol::AgentInfo {
+                name: name.clone(),
+                description: self.describe(config),
+                is_builtin: self.builtin_included && name == BUILTIN_AGENT_NAME,
+                prompt_preview: config.prompt.as_deref().map(prompt_preview),
+                tags: config.tags.clone().unwrap_or_default(),
+                model: config.model_override(),
+                permissions_summary: config
+                    .permissions_policy()
+                    .ok()
+                    .flatten()
+                    .as_ref()
+                    .map(permissions_policy_to_string),
+                is_broken: is_broken(config),
+                group: config.group_name().map(str::to_string),
+                source: self.agent_source(name),
+            });
+        }
+
+        agents.sort_by(|a, b| {
+            // Built-in agents first, then ungrouped agents, then by group and name
+            match (a.is_builtin, b.is_builtin) {
+                (true, false) => std::cmp::Ordering::Less,
+                (false, true) => std::cmp::Ordering::Greater,
+                _ => a.group.cmp(&b.group).then_with(|| a.name.cmp(&b.name)),
+            }
+        });
+        match order {
+            AgentSortOrder::BuiltinFirst => {}
+            AgentSortOrder::Alphabetical => agents.sort_by(|a, b| a.name.cmp(&b.name)),
+            // Stable sort: agents not in `recent` keep the built-in-first order.
+            AgentSortOrder::RecentlyUsed(recent) => agents.sort_by_key(|info| {
+                recent
+                    .iter()
+                    .position(|name| *name == info.name)
+                    .unwrap_or(recent.len())
+            }),
+        }
+
+        agents
+    }
+
+    /// Where the definition of `name` was loaded from; agents without a recorded origin
+    /// are built in.
+    pub fn agent_source(&self, name: &str) -> AgentSource {
+        self.canonical_name(name)
+            .and_then(|name| self.origins.get(name))
+            .cloned()
+            .unwrap_or(AgentSource::Builtin)
+    }
+
+    /// Listed agents keyed by group, in [`Self::list_agent_details`] order within each
+    /// group. Agents without a group (including the built-in agent) are under
+    /// [`UNGROUPED_AGENTS`].
+    pub fn agents_by_group(&self) -> BTreeMap<String, Vec<crate::protocol::AgentInfo>> {
+        let mut groups = BTreeMap::<String, Vec<_>>::new();
+        for info in self.list_agent_details() {
+            let group = info
+                .group
+                .as_deref()
+                .unwrap_or(UNGROUPED_AGENTS)
+                .to_string();
+            groups.entry(group).or_default().push(info);
+        }
+        groups
+    }
+
+    /// Export every agent, including disabled ones, in definition order as a JSON array of
+    /// resolved configurations. With `redact_env`, environment variable values are
+    /// replaced by `"<redacted>"`.
+    pub fn to_json(&self, redact_env: bool) -> serde_json::Value {
+        let agents = self
+            .iter_in_order()
+            .map(|(name, config)| {
+                let env: serde_json::Map<String, serde_json::Value> = config
+                    .env_overrides()
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let value = if redact_env {
+                            "<redacted>".to_string()
+                        } else {
+                            value
+                        };
+                        (key, serde_json::Value::String(value))
+                    })
+                    .collect();
+                serde_json::json!({
+                    "name": name,
+                    "description": self.describe(config),
+                    "builtin": self.builtin_included && name == BUILTIN_AGENT_NAME,
+                    "enabled": config.is_enabled(),
+                    "model": config.model_override(),
+                    "reasoning_effort": config.reasoning_effort_override(),
+                    "permissions": config
+                        .permissions_policy()
+                        .ok()
+                        .flatten()
+                        .as_ref()
+                        .map(permissions_policy_to_string),
+                    "tools": config.tools,
+                    "env": env,
+                    "source": self.sources.get(name),
+                })
+            })
+            .collect();
+        serde_json::Value::Array(agents)
+    }
+
+    /// Serialize every non-builtin agent, in definition order, as the contents of an
+    /// `agents.toml`. Inheritance is already resolved, so `extends`, `prompt_mode`, and
+    /// `tools_mode` are dropped; agents loaded from a `prompt_file` keep pointing at it
+    /// rather than inlining its contents.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let mut sections = Vec::new();
+        for (name, config) in self.iter_in_order() {
+            if self.builtin_included && name == BUILTIN_AGENT_NAME {
+                continue;
+            }
+            let mut config = config.clone();
+            if config.prompt_file.is_some() {
+                config.prompt = None;
+            }
+            config.extends = None;
+            config.prompt_mode = None;
+            config.tools_mode = None;
+            sections.push(toml::to_string(&BTreeMap::from([(name, config)]))?);
+        }
+        Ok(sections.join("\n"))
+    }
+
+    /// Return the sandbox override configured for the provided agent, if any.
+    pub fn permissions_policy(&self, agent_name: &str) -> Option<SandboxPolicy> {
+        self.get_agent(agent_name)
+            .and_then(|cfg| cfg.permissions_policy().ok().flatten())
+    }
+
+    /// Return the model override configured for the provided agent, if any.
+    pub fn model_override(&self, agent_name: &str) -> Option<String> {
+        self.get_agent(agent_name)
+            .and_then(|cfg| cfg.model_override())
+    }
+
+    /// Most agents one turn runs at the same time (`[settings] max_concurrent`).
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// Whether `[settings] allowed_models` permits `model`; an empty list allows every
+    /// model.
+    pub fn model_is_allowed(&self, model: &str) -> bool {
+        self.allowed_models.is_empty() || self.allowed_models.iter().any(|m| m == model.trim())
+    }
+
+    /// Model an agent should run with when it has one: its explicit `model`, otherwise
+    /// the `[settings] effort_model_map` entry for its `reasoning_effort` (if allowed).
+    pub fn mapped_model(&self, agent_name: &str) -> Option<String> {
+        let config = self.get_agent(agent_name)?;
+        config.model_override().or_else(|| {
+            let effort = config.reasoning_effort?.to_string();
+            self.effort_model_map
+                .get(&effort)
+                .filter(|model| self.model_is_allowed(model))
+                .cloned()
+        })
+    }
+
+    /// Model an agent runs with: its explicit `model`, then the `effort_model_map` entry
+    /// for its `reasoning_effort`, then `context_default`.
+    pub fn resolved_model(&self, agent_name: &str, context_default: &str) -> String {
+        self.mapped_model(agent_name)
+            .unwrap_or_else(|| context_default.to_string())
+    }
+
+    /// Return the reasoning effort override for the provided agent, if any.
+    pub fn reasoning_effort_override(&self, agent_name: &str) -> Option<ReasoningEffortConfig> {
+        self.get_agent(agent_name)
+            .and_then(|cfg| cfg.reasoning_effort_override())
+    }
+
+    /// Reasoning effort `agent_name` runs with: its override, or `context_default`,
+    /// clamped into the `[settings] min_effort`..`max_effort` band.
+    pub fn clamped_reasoning_effort(
+        &self,
+        agent_name: &str,
+        context_default: ReasoningEffortConfig,
+    ) -> ReasoningEffortConfig {
+        let effort = self
+            .reasoning_effort_override(agent_name)
+            .unwrap_or(context_default);
+        self.clamp_effort(agent_name, effort)
+    }
+
+    /// Move `effort` into the `[settings] min_effort`..`max_effort` band, warning when it
+    /// changes.
+    fn clamp_effort(
+        &self,
+        agent_name: &str,
+        effort: ReasoningEffortConfig,
+    ) -> ReasoningEffortConfig {
+        let clamped = match self.effort_bounds {
+            (Some(min), _) if effort_rank(effort) < effort_rank(min) => min,
+            (_, Some(max)) if effort_rank(effort) > effort_rank(max) => max,
+            _ => effort,
+        };
+        if clamped != effort {
+            tracing::warn!(
+                "Agent '{agent_name}' requests reasoning effort '{effort}'; using '{clamped}' to stay within the allowed range"
+            );
+        }
+        clamped
+    }
+
+    /// Return the sampling temperature override for the provided agent, if any.
+    pub fn temperature_override(&self, agent_name: &str) -> Option<f32> {
+        self.get_agent(agent_name)
+            .and_then(|cfg| cfg.temperature_override())
+    }
+
+    /// Return the `top_p` override for the provided agent, if any.
+    pub fn top_p_override(&self, agent_name: &str) -> Option<f32> {
+        self.get_agent(agent_name)
+            .and_then(|cfg| cfg.top_p_override())
+    }
+
+    /// Whether the provided agent may run `argv`. Unknown agents have no restrictions.
+    pub fn command_is_allowed(&self, agent_name: &str, argv: &[String]) -> bool {
+        self.get_agent(agent_name)
+            .is_none_or(|cfg| cfg.command_is_allowed(argv))
+    }
+
+    /// Listing description: the explicit `description`, else one derived from the prompt.
+    fn describe(&self, config: &AgentConfig) -> String {
+        let explicit = config
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty());
+        let description = if let Some(description) = explicit {
+            truncate_description(description)
+        } else if let Some(ref prompt) = config.prompt {
+            self.extract_description(prompt)
+        } else if let Some(suffix) = config.prompt_suffix() {
+            self.extract_description(suffix)
+        } else if let AgentKind::Command { run } = config.kind() {
+            truncate_description(&format!("Runs `{run}`"))
+        } else if is_broken(config) {
+            return BROKEN_PROMPT_MARKER.to_string();
+        } else {
+            "Agent with file-based prompt".to_string()
+        };
+        if is_broken(config) {
+            format!("{BROKEN_PROMPT_MARKER} · {description}")
+        } else {
+            description
+        }
+    }
+
+    /// Extract brief description from prompt
+    fn extract_description(&self, prompt: &str) -> String {
+        // Take the first line of prose, skipping blank lines, headings, blockquotes, and
+        // code fences; fall back to the first non-blank line without its markers.
+        let mut in_fence = false;
+        let prose = prompt.lines().map(str::trim).find(|line| {
+            if line.starts_with("```") || line.starts_with("~~~") {
+                in_fence = !in_fence;
+                return false;
+            }
+            !in_fence && !line.is_empty() && !line.starts_with(['#', '>'])
+        });
+        let first_line = prose
+            .or_else(|| {
+                prompt
+                    .lines()
+                    .map(|line| line.trim().trim_start_matches(['#', '>', ' ']))
+                    .find(|line| !line.is_empty() && !line.starts_with("```"))
+            })
+            .unwrap_or("");
+
+        // Take first line or first sentence as description
+        let desc = if let Some(pos) = first_line.find('.') {
+            &first_line[..=pos]
+        } else {
+            first_line
+        };
+
+        // Clean up common prefixes
+        desc.trim_start_matches("You are a ")
+            .trim_start_matches("You are an ")
+            .trim_start_matches("You are ")
+            .trim()
+            .to_string()
+    }
+
+    /// Get the environment variables an agent sets for its commands
+    pub fn env_overrides(&self, agent_name: &str) -> HashMap<String, String> {
+        self.get_agent(agent_name)
+            .map(AgentConfig::env_overrides)
+            .unwrap_or_default()
+    }
+
+    /// Agent nesting depth recorded in `metadata`; 0 for the top-level conversation.
+    pub fn agent_depth(metadata: &HashMap<String, String>) -> usize {
+        metadata
+            .get(AGENT_DEPTH_KEY)
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Check if a context at the depth recorded in `metadata` may spawn another agent
+    /// without nesting deeper than `max_depth`.
+    pub fn spawn_allowed(metadata: &HashMap<String, String>, max_depth: usize) -> bool {
+        Self::agent_depth(metadata) < max_depth
+    }
+
+    /// Record one more level of agent nesting in `metadata`, as done for every agent run
+    pub fn increment_depth(metadata: &mut HashMap<String, String>) {
+        let depth = Self::agent_depth(metadata).saturating_add(1);
+        metadata.insert(AGENT_DEPTH_KEY.to_string(), depth.to_string());
+    }
+}
+
+/// Runs agent tasks in parallel while capping how many run at once.
+pub struct AgentScheduler<B> {
+    executor: Arc<executor::AgentExecutor<B>>,
+    /// One permit per run allowed at the same time
+    permits: Semaphore,
+}
+
+impl<B: executor::AgentBackend> AgentScheduler<B> {
+    /// `max_concurrent` is clamped to at least one.
+    pub fn new(executor: Arc<executor::AgentExecutor<B>>, max_concurrent: usize) -> Self {
+        Self {
+            executor,
+            permits: Semaphore::new(max_concurrent.max(1)),
+        }
+    }
+
+    /// Run `task` with `agent_name`, first waiting until fewer than `max_concurrent` runs
+    /// started through this scheduler are in flight.
+    pub async fn run(
+        &self,
+        agent_name: &str,
+        task: String,
+        parent_metadata: &HashMap<String, String>,
+        cancel: CancellationToken,
+    ) -> Result<String> {
+        // The semaphore is never closed, so acquiring only fails if it were.
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|_| CodexErr::InternalAgentDied)?;
+        self.executor
+            .execute(agent_name, task, parent_metadata, cancel)
+            .await
+    }
+
+    /// Run every `(agent_name, task)` pair and return the results in input order.
+    /// A failing agent does not stop the rest of the batch.
+    pub async fn run_batch(
+        &self,
+        tasks: Vec<(String, String)>,
+        parent_metadata: &HashMap<String, String>,
+        cancel: CancellationToken,
+    ) -> Vec<Result<String>> {
+        let runs = tasks.into_iter().map(|(agent_name, task)| {
+            let cancel = cancel.clone();
+            async move { self.run(&agent_name, task, parent_metadata, cancel).await }
+        });
+        futures::future::join_all(runs).await
+    }
+}
+
+/// Map every agent's lowercased name and aliases to the agent's name. Primary names are
+/// indexed before aliases; when two agents claim the same key the first (in name order)
+/// keeps it and the collision is recorded in `dropped`.
+fn build_lookup_index(
+    agents: &HashMap<String, AgentConfig>,
+    dropped: &mut Vec<(String, String)>,
+) -> HashMap<String, String> {
+    let mut names: Vec<&String> = agents.keys().collect();
+    names.sort();
+
+    let mut index: HashMap<String, String> = HashMap::new();
+    for name in &names {
+        index
+            .entry(name.to_lowercase())
+            .or_insert_with(|| (*name).clone());
+    }
+    for name in names {
+        for alias in agents[name].aliases.iter().flatten() {
+            let key = alias.trim().to_lowercase();
+            if key.is_empty() {
+                continue;
+            }
+            match index.get(&key) {
+                Some(owner) if owner != name => {
+                    let reason =
+                        format!("alias '{alias}' is already used by agent '{owner}'; ignoring it");
+                    tracing::warn!("Agent '{name}': {reason}");
+                    dropped.push((name.clone(), reason));
+                }
+                Some(_) => {}
+                None => {
+                    index.insert(key, name.clone());
+                }
+            }
+        }
+    }
+    index
+}
+
+/// Value of the entry in `map` whose key matches `name` ignoring case.
+fn find_ignoring_case<'a, V>(map: &'a HashMap<String, V>, name: &str) -> Option<&'a V> {
+    map.get(name).or_else(|| {
+        let name = name.to_lowercase();
+        map.iter()
+            .find(|(key, _)| key.to_lowercase() == name)
+            .map(|(_, value)| value)
+    })
+}
+
+fn builtin_disabled_by_env() -> bool {
+    std::env::var(DISABLE_BUILTIN_AGENT_ENV_VAR)
+        .is_ok_and(|value| matches!(value.trim(), "1" | "true"))
+}
+
+/// Every spelling [`parse_sandbox_permissions`] accepts, ignoring case. Workspace-write
+/// values may also be followed by `:<root>,<root>` to add absolute writable roots.
+pub const PERMISSION_VALUES: &[&str] = &[
+    "read-only",
+    "readonly",
+    "workspace-write",
+    "workspacewrite",
+    "workspace-write+network",
+    "workspace-write-network",
+    "workspace-write:network",
+    "workspacewrite+network",
+    "danger-full-access",
+    "dangerfullaccess",
+];
+
+/// Parse a `permissions` value (one of [`PERMISSION_VALUES`]) into the sandbox policy
+/// Codex runs with.
+pub fn parse_sandbox_permissions(value: &str) -> anyhow::Result<SandboxPolicy> {
+    let value = value.trim();
+    // `workspace-write:<root>,<root>` appends extra writable roots; paths keep their case.
+    let (mode, extra_roots) = match value.split_once(':') {
+        Some((mode, roots))
+            if is_workspace_write_mode(&mode.to_ascii_lowercase())
+                && !roots.trim().eq_ignore_ascii_case("network") =>
+        {
+            (mode, Some(roots))
+        }
+        _ => (value, None),
+    };
+    let normalized = mode.trim().to_ascii_lowercase();
+    let mut policy = match normalized.as_str() {
+        "read-only" | "readonly" => SandboxPolicy::ReadOnly,
+        "danger-full-access" | "dangerfullaccess" => SandboxPolicy::DangerFullAccess,
+        "workspace-write" | "workspacewrite" => SandboxPolicy::new_workspace_write_policy(),
+        "workspace-write+network"
+        | "workspace-write-network"
+        | "workspace-write:network"
+        | "workspacewrite+network" => {
+            let mut policy = SandboxPolicy::new_workspace_write_policy();
+            if let SandboxPolicy::WorkspaceWrite { network_access, .. } = &mut policy {
+                *network_access = true;
+            }
+            policy
+        }
+        other => return Err(anyhow::anyhow!("unknown permissions value '{other}'")),
+    };
+    if let Some(extra_roots) = extra_roots
+        && let SandboxPolicy::WorkspaceWrite { writable_roots, .. } = &mut policy
+    {
+        for root in extra_roots
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+        {
+            let root = PathBuf::from(root);
+            if !root.is_absolute() {
+                return Err(anyhow::anyhow!(
+                    "writable root '{}' must be an absolute path",
+                    root.display()
+                ));
+            }
+            writable_roots.push(root);
+        }
+    }
+    Ok(policy)
+}
+
+/// Layer an agent's environment variables over `policy`, so they win over both
+/// inherited variables and the session's own `set` entries.
+pub(crate) fn apply_env_overrides(
+    policy: &mut ShellEnvironmentPolicy,
+    overrides: HashMap<String, String>,
+) {
+    policy.r#set.extend(overrides);
+}
+
+/// Intersect an agent's `requested` sandbox policy with the `session` policy, keeping
+/// only access both allow. Logs a warning when the request asked for more than the
+/// session grants.
+pub fn restrict_sandbox_policy(
+    requested: &SandboxPolicy,
+    session: &SandboxPolicy,
+) -> SandboxPolicy {
+    let restricted = match (requested, session) {
+        (SandboxPolicy::ReadOnly, _) | (_, SandboxPolicy::ReadOnly) => SandboxPolicy::ReadOnly,
+        (SandboxPolicy::DangerFullAccess, session) => session.clone(),
+        (requested, SandboxPolicy::DangerFullAccess) => requested.clone(),
+        (
+            SandboxPolicy::WorkspaceWrite {
+                writable_roots,
+                network_access,
+                exclude_tmpdir_env_var,
+                exclude_slash_tmp,
+            },
+            SandboxPolicy::WorkspaceWrite {
+                writable_roots: session_roots,
+                network_access: session_network,
+                exclude_tmpdir_env_var: session_exclude_tmpdir,
+                exclude_slash_tmp: session_exclude_slash_tmp,
+            },
+        ) => SandboxPolicy::WorkspaceWrite {
+            writable_roots: writable_roots
+                .iter()
+                .filter(|root| {
+                    session_roots
+                        .iter()
+                        .any(|allowed| root.starts_with(allowed))
+                })
+                .cloned()
+                .collect(),
+            network_access: *network_access && *session_network,
+            exclude_tmpdir_env_var: *exclude_tmpdir_env_var || *session_exclude_tmpdir,
+            exclude_slash_tmp: *exclude_slash_tmp || *session_exclude_slash_tmp,
+        },
+    };
+    if restricted != *requested {
+        tracing::warn!(
+            "Agent requested sandbox '{}' but the session allows '{}'; using '{}'",
+            permissions_policy_to_string(requested),
+            permissions_policy_to_string(session),
+            permissions_policy_to_string(&restricted),
+        );
+    }
+    restricted
+}
+
+/// Render `policy` in the canonical `permissions` syntax understood by
+/// `parse_sandbox_permissions`, including any extra writable roots.
+pub fn permissions_policy_to_string(policy: &SandboxPolicy) -> String {
+    match policy {
+        SandboxPolicy::DangerFullAccess => "danger-full-access".to_string(),
+        SandboxPolicy::ReadOnly => "read-only".to_string(),
+        SandboxPolicy::WorkspaceWrite {
+            writable_roots,
+            network_access,
+            ..
+        } => {
+            let mode = if *network_access {
+                "workspace-write+network"
+            } else {
+                "workspace-write"
+            };
+            if writable_roots.is_empty() {
+                mode.to_string()
+            } else {
+                let roots = writable_roots
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{mode}:{roots}")
+            }
+        }
+    }
+}
+
+/// Description shown for an agent whose `prompt_file` could not be read.
+const BROKEN_PROMPT_MARKER: &str = "⚠ prompt file missing";
+
+/// An agent is broken when it names a `prompt_file` that couldn't be loaded.
+fn is_broken(config: &AgentConfig) -> bool {
+    config.prompt.is_none() && config.prompt_file.is_some()
+}
+
+/// Metadata key holding how many agents deep a context is.
+const AGENT_DEPTH_KEY: &str = "agent_depth";
+
+/// How deep agents may nest by default: the conversation can start agents, but agents
+/// cannot start agents of their own.
+pub const DEFAULT_MAX_AGENT_DEPTH: usize = 1;
+
+/// Per-project state file, relative to the project root.
+const PROJECT_STATE_FILE: &str = ".codex/state.json";
+
+/// Per-project UI state persisted between sessions.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProjectState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_agent: Option<String>,
+}
+
+/// Read `<project_root>/.codex/state.json`. A missing or unreadable file yields the
+/// default state.
+fn load_project_state(project_root: &Path) -> ProjectState {
+    let path = project_root.join(PROJECT_STATE_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(serialized) => serde_json::from_str(&serialized).unwrap_or_else(|e| {
+            tracing::warn!("failed to parse {}: {e}", path.display());
+            ProjectState::default()
+        }),
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("failed to read {}: {e}", path.display());
+            }
+            ProjectState::default()
+        }
+    }
+}
+
+/// Remember `agent_name` as the agent last used in `project_root`, so
+/// [`AgentRegistry::last_used_agent`] can restore it next session.
+pub fn save_last_used_agent(project_root: &Path, agent_name: &str) -> std::io::Result<()> {
+    let mut state = load_project_state(project_root);
+    state.last_agent = Some(agent_name.to_string());
+    let path = project_root.join(PROJECT_STATE_FILE);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let serialized = serde_json::to_string_pretty(&state).map_err(std::io::Error::other)?;
+    std::fs::write(path, serialized)
+}
+
+/// Agent usage file, relative to the Codex home directory.
+const AGENT_STATS_FILE: &str = "agent_stats.json";
+
+/// In-memory count of how often each agent was invoked, which can be flushed into a
+/// running total on disk (`$CODEX_HOME/agent_stats.json` by default).
+#[derive(Debug, Default)]
+pub struct AgentUsageStats {
+    counts: std::sync::Mutex<UsageCounts>,
+}
+
+#[derive(Debug, Default)]
+struct UsageCounts {
+    /// Invocations recorded since this value was created
+    session: HashMap<String, u64>,
+    /// Invocations not yet added to the stats file
+    unflushed: HashMap<String, u64>,
+}
+
+impl AgentUsageStats {
+    /// Count one invocation of `name`.
+    pub fn record_invocation(&self, name: &str) {
+        let mut counts = self.lock();
+        *counts.session.entry(name.to_string()).or_default() += 1;
+        *counts.unflushed.entry(name.to_string()).or_default() += 1;
+    }
+
+    /// Invocations per agent recorded so far.
+    pub fn stats(&self) -> HashMap<String, u64> {
+        self.lock().session.clone()
+    }
+
+    /// Default stats file: `$CODEX_HOME/agent_stats.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        crate::config::find_codex_home()
+            .ok()
+            .map(|home| home.join(AGENT_STATS_FILE))
+    }
+
+    /// Running totals stored at `path`; empty when the file is missing or unreadable.
+    pub fn load_totals(path: &Path) -> HashMap<String, u64> {
+        match std::fs::read_to_string(path) {
+            Ok(serialized) => serde_json::from_str(&serialized).unwrap_or_else(|e| {
+                tracing::warn!("failed to parse {}: {e}", path.display());
+                HashMap::new()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("failed to read {}: {e}", path.display());
+                }
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Add the invocations recorded since the last flush to the totals at `path`.
+    pub fn flush(&self, path: &Path) -> std::io::Result<()> {
+        let mut counts = self.lock();
+        if counts.unflushed.is_empty() {
+            return Ok(());
+        }
+        let mut totals: BTreeMap<String, u64> = Self::load_totals(path).into_iter().collect();
+        for (name, count) in &counts.unflushed {
+            *totals.entry(name.clone()).or_default() += count;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let serialized = serde_json::to_string_pretty(&totals).map_err(std::io::Error::other)?;
+        std::fs::write(path, serialized)?;
+        counts.unflushed.clear();
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UsageCounts> {
+        // The counts stay consistent even if a holder panicked.
+        self.counts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+fn is_workspace_write_mode(mode: &str) -> bool {
+    matches!(
+        mode,
+        "workspace-write"
+            | "workspacewrite"
+            | "workspace-write+network"
+            | "workspace-write-network"
+            | "workspacewrite+network"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_default_agent_exists() {
+        let registry = AgentRegistry::new().unwrap();
+        assert!(registry.get_agent("general").is_some());
+    }
+
+    #[test]
+    fn last_used_agent_round_trips_through_project_state() {
+        let project = TempDir::new().unwrap();
+        write_agents(
+            &project.path().join(".codex"),
+            "[reviewer]\nprompt = \"Review code\"\n",
+        );
+        let registry =
+            AgentRegistry::load_from_roots(project.path().join(".codex"), None, &mut Vec::new());
+
+        save_last_used_agent(project.path(), "reviewer").unwrap();
+        assert_eq!(
+            registry.last_used_agent(project.path()),
+            Some("reviewer".to_string())
+        );
+
+        save_last_used_agent(project.path(), "general").unwrap();
+        assert_eq!(
+            registry.last_used_agent(project.path()),
+            Some("general".to_string())
+        );
+    }
+
+    #[test]
+    fn last_used_agent_is_kept_per_project() {
+        let project_a = TempDir::new().unwrap();
+        let project_b = TempDir::new().unwrap();
+        let registry =
+            AgentRegistry::load_from_roots(project_a.path().join(".codex"), None, &mut Vec::new());
+
+        save_last_used_agent(project_a.path(), "general").unwrap();
+        assert_eq!(
+            registry.last_used_agent(project_a.path()),
+            Some("general".to_string())
+        );
+        assert_eq!(registry.last_used_agent(project_b.path()), None);
+    }
+
+    #[test]
+    fn last_used_agent_ignores_removed_agents() {
+        let project = TempDir::new().unwrap();
+        save_last_used_agent(project.path(), "retired").unwrap();
+        let registry =
+            AgentRegistry::load_from_roots(project.path().join(".codex"), None, &mut Vec::new());
+        assert_eq!(registry.last_used_agent(project.path()), None);
+    }
+
+    #[test]
+    fn last_used_agent_without_state_file_is_none() {
+        let project = TempDir::new().unwrap();
+        let registry =
+            AgentRegistry::load_from_roots(project.path().join(".codex"), None, &mut Vec::new());
+        assert_eq!(registry.last_used_agent(project.path()), None);
+        assert!(!project.path().join(PROJECT_STATE_FILE).exists());
+    }
+
+    #[test]
+    fn usage_stats_count_invocations() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"Review.\"\naliases = [\"rev\"]\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        registry.record_invocation("general");
+        registry.record_invocation("reviewer");
+        registry.record_invocation("REV");
+        registry.record_invocation("no-such-agent");
+        registry.record_invocation("general");
+
+        assert_eq!(
+            registry.usage().stats(),
+            HashMap::from([("general".to_string(), 2), ("reviewer".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn usage_stats_flush_adds_to_the_stats_file() {
+        let home = TempDir::new().unwrap();
+        let path = home.path().join(AGENT_STATS_FILE);
+        fs::write(&path, r#"{"general": 5}"#).unwrap();
+
+        let usage = AgentUsageStats::default();
+        usage.record_invocation("general");
+        usage.record_invocation("reviewer");
+        usage.flush(&path).unwrap();
+        // Nothing new to add, so a second flush leaves the totals alone.
+        usage.flush(&path).unwrap();
+        usage.record_invocation("reviewer");
+        usage.flush(&path).unwrap();
+
+        assert_eq!(
+            AgentUsageStats::load_totals(&path),
+            HashMap::from([("general".to_string(), 6), ("reviewer".to_string(), 2)])
+        );
+        assert_eq!(
+            usage.stats(),
+            HashMap::from([("general".to_string(), 1), ("reviewer".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn test_agent_recursion_prevention() {
+        let mut metadata = HashMap::new();
+        assert!(AgentRegistry::spawn_allowed(
+            &metadata,
+            DEFAULT_MAX_AGENT_DEPTH
+        ));
+
+        AgentRegistry::increment_depth(&mut metadata);
+        assert!(!AgentRegistry::spawn_allowed(
+            &metadata,
+            DEFAULT_MAX_AGENT_DEPTH
+        ));
+    }
+
+    #[test]
+    fn spawn_depth_is_limited_by_max_depth() {
+        let mut metadata = HashMap::new();
+        assert_eq!(AgentRegistry::agent_depth(&metadata), 0);
+        assert!(AgentRegistry::spawn_allowed(&metadata, 1));
+        assert!(!AgentRegistry::spawn_allowed(&metadata, 0));
+
+        AgentRegistry::increment_depth(&mut metadata);
+        assert_eq!(AgentRegistry::agent_depth(&metadata), 1);
+        assert!(!AgentRegistry::spawn_allowed(&metadata, 1));
+        assert!(AgentRegistry::spawn_allowed(&metadata, 2));
+
+        AgentRegistry::increment_depth(&mut metadata);
+        assert_eq!(metadata.get("agent_depth").map(String::as_str), Some("2"));
+        assert!(!AgentRegistry::spawn_allowed(&metadata, 2));
+    }
+
+    #[test]
+    fn test_path_traversal_prevention() {
+        // Create a temporary directory structure
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path();
+
+        // Create a safe file
+        let safe_dir = base_dir.join("prompts");
+        fs::create_dir(&safe_dir).unwrap();
+        let safe_file = safe_dir.join("test.txt");
+        fs::write(&safe_file, "safe content").unwrap();
+
+        let roots = AgentRegistry::default_prompt_roots(base_dir);
+
+        // Test that normal paths work
+        let result = AgentRegistry::validate_prompt_path(base_dir, "prompts/test.txt", &roots);
+        assert!(result.is_ok());
+
+        // Test that path traversal is blocked
+        let result = AgentRegistry::validate_prompt_path(base_dir, "../../../etc/passwd", &roots);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Security error"));
+
+        // Test that absolute paths outside allowed dirs are blocked
+        let result = AgentRegistry::validate_prompt_path(base_dir, "/etc/passwd", &roots);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn custom_prompt_root_is_allowed() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path().join("project/.codex");
+        let shared = temp_dir.path().join("shared/prompts");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        let shared_file = shared.join("reviewer.md");
+        fs::write(&shared_file, "Shared reviewer").unwrap();
+        let shared_file = shared_file.to_string_lossy().to_string();
+
+        // Outside the default roots, the shared prompt is rejected.
+        let defaults = AgentRegistry::default_prompt_roots(&base_dir);
+        assert!(AgentRegistry::validate_prompt_path(&base_dir, &shared_file, &defaults).is_err());
+
+        let roots = vec![base_dir.clone(), shared.clone()];
+        let resolved =
+            AgentRegistry::validate_prompt_path(&base_dir, &shared_file, &roots).unwrap();
+        assert_eq!(resolved, shared.canonicalize().unwrap().join("reviewer.md"));
+
+        // Traversal is still blocked with extra roots configured.
+        let result = AgentRegistry::validate_prompt_path(&base_dir, "../../etc/passwd", &roots);
+        assert!(result.unwrap_err().to_string().contains("Security error"));
+    }
+
+    #[test]
+    fn test_agent_config_validation() {
+        // Test config with prompt is valid
+        let config = AgentConfig {
+            prompt: Some("Test prompt".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        // Test config with prompt_file is valid
+        let config = AgentConfig {
+            prompt_file: Some("test.txt".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        // Test config with neither prompt nor prompt_file is invalid
+        let config = AgentConfig::default();
+        assert!(config.validate().is_err());
+
+        // Test config with both prompt and prompt_file is invalid
+        let config = AgentConfig {
+            prompt: Some("Test prompt".to_string()),
+            prompt_file: Some("test.txt".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn command_agents_need_a_run_command_and_no_prompt() {
+        let linter = AgentConfig::builder().run("cargo clippy").build().unwrap();
+        assert_eq!(
+            linter.kind(),
+            AgentKind::Command {
+                run: "cargo clippy".to_string()
+            }
+        );
+        assert_eq!(
+            AgentConfig::builder()
+                .prompt("Review")
+                .build()
+                .unwrap()
+                .kind(),
+            AgentKind::Llm
+        );
+
+        let both = AgentConfig::builder()
+            .run("cargo clippy")
+            .prompt("Lint the code")
+            .build();
+        assert!(both.unwrap_err().to_string().contains("not both"));
+        let blank = AgentConfig::builder().run("  ").build();
+        assert!(
+            blank
+                .unwrap_err()
+                .to_string()
+                .contains("'run' must not be empty")
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[base]\nprompt = \"Base prompt\"\n\n[linter]\nextends = \"base\"\nrun = \"cargo clippy\"\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        let linter = registry.get_agent("linter").unwrap();
+        assert_eq!(linter.prompt, None);
+        assert_eq!(registry.describe(linter), "Runs `cargo clippy`");
+    }
+
+    #[test]
+    fn test_agent_config_get_prompt() {
+        // Test getting prompt from inline prompt
+        let mut config = AgentConfig {
+            prompt: Some("Inline prompt".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.get_prompt(None).unwrap(), "Inline prompt");
+
+        // Test getting prompt from file
+        let temp_dir = TempDir::new().unwrap();
+        let prompt_file = temp_dir.path().join("test_prompt.txt");
+        fs::write(&prompt_file, "File-based prompt").unwrap();
+
+        let mut config = AgentConfig {
+            prompt_file: Some("test_prompt.txt".to_string()),
+            ..Default::default()
+        };
+
+        let prompt = config.get_prompt(Some(temp_dir.path())).unwrap();
+        assert_eq!(prompt, "File-based prompt");
+
+        // Check that prompt is cached
+        assert_eq!(config.prompt, Some("File-based prompt".to_string()));
+    }
+
+    #[test]
+    fn reload_prompt_picks_up_file_edits() {
+        let temp_dir = TempDir::new().unwrap();
+        let prompt_file = temp_dir.path().join("reviewer.md");
+        fs::write(&prompt_file, "Old prompt").unwrap();
+        let mut config = AgentConfig {
+            prompt_file: Some("reviewer.md".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_prompt(Some(temp_dir.path())).unwrap(),
+            "Old prompt"
+        );
+
+        fs::write(&prompt_file, "New prompt").unwrap();
+        assert_eq!(
+            config.get_prompt(Some(temp_dir.path())).unwrap(),
+            "Old prompt"
+        );
+        config.reload_prompt(Some(temp_dir.path())).unwrap();
+        assert_eq!(
+            config.get_prompt(Some(temp_dir.path())).unwrap(),
+            "New prompt"
+        );
+
+        // A failed re-read keeps the last good prompt.
+        fs::remove_file(&prompt_file).unwrap();
+        assert!(config.reload_prompt(Some(temp_dir.path())).is_err());
+        assert_eq!(config.prompt.as_deref(), Some("New prompt"));
+    }
+
+    #[test]
+    fn working_dir_resolves_inside_project_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        fs::create_dir_all(project.join("web")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("outside")).unwrap();
+        let with_dir = |dir: &str| AgentConfig {
+            prompt: Some("Frontend".to_string()),
+            working_dir: Some(dir.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            with_dir("web").working_dir_abs(&project).unwrap(),
+            project.join("web").canonicalize().unwrap()
+        );
+        let err = with_dir("../outside")
+            .working_dir_abs(&project)
+            .unwrap_err();
+        assert!(err.to_string().contains("inside the project root"), "{err}");
+        assert!(with_dir("missing").working_dir_abs(&project).is_err());
+        assert_eq!(
+            AgentConfig::default().working_dir_abs(&project).unwrap(),
+            project
+        );
+    }
+
+    #[test]
+    fn prompt_suffix_alone_keeps_the_base_instructions() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[terse]\nprompt_suffix = \"  Keep answers short.  \"\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(
+            registry.get_system_prompt_parts("terse"),
+            (None, Some("Keep answers short.".to_string()))
+        );
+        assert_eq!(registry.get_system_prompt("terse"), "Keep answers short.");
+        assert_eq!(description_of(&registry, "terse"), "Keep answers short.");
+    }
+
+    #[test]
+    fn prompt_suffix_follows_a_full_prompt_with_a_warning() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"Review code.\"\nprompt_suffix = \"Be brief.\"\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert_eq!(
+            registry.get_system_prompt_parts("reviewer"),
+            (
+                Some("Review code.".to_string()),
+                Some("Be brief.".to_string())
+            )
+        );
+        assert_eq!(
+            registry.get_system_prompt("reviewer"),
+            "Review code.\n\nBe brief."
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("reviewer"));
+        assert!(
+            diagnostics[0]
+                .message
+                .contains("sets both a full prompt and prompt_suffix"),
+            "{diagnostics:?}"
+        );
+    }
+
+    #[test]
+    fn only_in_limits_agents_to_matching_projects() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join("webapp/.codex");
+        write_agents(
+            &project_root,
+            "[frontend]\nprompt = \"F\"\nonly_in = [\"*/other\", \"*/webapp\"]\n\n[backend]\nprompt = \"B\"\nonly_in = [\"*/server\"]\n\n[anywhere]\nprompt = \"A\"\n\n[unrestricted]\nprompt = \"U\"\nonly_in = []\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(registry.get_agent("frontend").is_some());
+        assert!(registry.get_agent("backend").is_none());
+        assert!(registry.get_agent("anywhere").is_some());
+        assert!(registry.get_agent("unrestricted").is_some());
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn reload_prompt_is_a_no_op_for_inline_prompts() {
+        let mut config = AgentConfig {
+            prompt: Some("Inline prompt".to_string()),
+            ..Default::default()
+        };
+        config.reload_prompt(None).unwrap();
+        assert_eq!(config.prompt.as_deref(), Some("Inline prompt"));
+    }
+
+    #[test]
+    fn registry_reloads_agent_prompt() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt_file = \"reviewer.md\"\n\n[inline]\nprompt = \"Inline\"\n",
+        );
+        fs::write(project_root.join("reviewer.md"), "Old prompt").unwrap();
+        let mut registry =
+            AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
+        assert_eq!(registry.get_system_prompt("reviewer"), "Old prompt");
+
+        fs::write(project_root.join("reviewer.md"), "New prompt").unwrap();
+        registry.reload_agent_prompt("Reviewer").unwrap();
+        assert_eq!(registry.get_system_prompt("reviewer"), "New prompt");
+
+        registry.reload_agent_prompt("inline").unwrap();
+        assert_eq!(registry.get_system_prompt("inline"), "Inline");
+        assert!(registry.reload_agent_prompt("missing").is_err());
+    }
+
+    #[test]
+    fn permissions_policy_parses_supported_values() {
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            permissions: Some("read-only".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.permissions_policy().unwrap().unwrap(),
+            SandboxPolicy::ReadOnly
+        ));
+
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            permissions: Some("workspace-write+network".to_string()),
+            ..Default::default()
+        };
+        match config.permissions_policy().unwrap().unwrap() {
+            SandboxPolicy::WorkspaceWrite { network_access, .. } => assert!(network_access),
+            other => panic!("expected workspace-write override, got {other:?}"),
+        }
+
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            permissions: Some("danger-full-access".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.permissions_policy().unwrap().unwrap(),
+            SandboxPolicy::DangerFullAccess
+        ));
+
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            permissions: Some("inherit".to_string()),
+            ..Default::default()
+        };
+        assert!(config.permissions_policy().unwrap().is_none());
+    }
+
+    fn writable_roots_of(permissions: &str) -> anyhow::Result<(Vec<PathBuf>, bool)> {
+        match parse_sandbox_permissions(permissions)? {
+            SandboxPolicy::WorkspaceWrite {
+                writable_roots,
+                network_access,
+                ..
+            } => Ok((writable_roots, network_access)),
+            other => panic!("expected workspace-write policy, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn permissions_policy_accepts_extra_writable_root() {
+        assert_eq!(
+            writable_roots_of("workspace-write:/tmp/cache").unwrap(),
+            (vec![PathBuf::from("/tmp/cache")], false)
+        );
+    }
+
+    #[test]
+    fn permissions_policy_accepts_multiple_writable_roots() {
+        assert_eq!(
+            writable_roots_of("workspace-write:/tmp/cache, /var/Build").unwrap(),
+            (
+                vec![PathBuf::from("/tmp/cache"), PathBuf::from("/var/Build")],
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn permissions_policy_combines_network_with_writable_roots() {
+        assert_eq!(
+            writable_roots_of("workspace-write+network:/tmp").unwrap(),
+            (vec![PathBuf::from("/tmp")], true)
+        );
+        assert_eq!(
+            writable_roots_of("workspace-write:network").unwrap(),
+            (Vec::new(), true)
+        );
+    }
+
+    #[test]
+    fn parse_sandbox_permissions_accepts_every_listed_value() {
+        for value in PERMISSION_VALUES {
+            let policy = parse_sandbox_permissions(value)
+                .unwrap_or_else(|e| panic!("'{value}' should parse: {e}"));
+            let expected = if value.contains("read") {
+                "read-only"
+            } else if value.contains("danger") {
+                "danger-full-access"
+            } else if value.contains("network") {
+                "workspace-write+network"
+            } else {
+                "workspace-write"
+            };
+            assert_eq!(permissions_policy_to_string(&policy), expected, "{value}");
+            assert_eq!(
+                parse_sandbox_permissions(&format!(" {} ", value.to_uppercase())).unwrap(),
+                policy
+            );
+        }
+
+        let err = parse_sandbox_permissions("full-power").unwrap_err();
+        assert_eq!(err.to_string(), "unknown permissions value 'full-power'");
+    }
+
+    #[test]
+    fn permissions_policy_rejects_relative_writable_root() {
+        let err = parse_sandbox_permissions("workspace-write:/tmp,build").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "writable root 'build' must be an absolute path"
+        );
+    }
+
+    fn assert_round_trips(policy: SandboxPolicy, expected: &str) {
+        let rendered = permissions_policy_to_string(&policy);
+        assert_eq!(rendered, expected);
+        assert_eq!(parse_sandbox_permissions(&rendered).unwrap(), policy);
+    }
+
+    #[test]
+    fn permissions_policy_to_string_round_trips_each_variant() {
+        assert_round_trips(SandboxPolicy::ReadOnly, "read-only");
+        assert_round_trips(SandboxPolicy::DangerFullAccess, "danger-full-access");
+        assert_round_trips(
+            SandboxPolicy::new_workspace_write_policy(),
+            "workspace-write",
+        );
+
+        let mut networked = SandboxPolicy::new_workspace_write_policy();
+        if let SandboxPolicy::WorkspaceWrite { network_access, .. } = &mut networked {
+            *network_access = true;
+        }
+        assert_round_trips(networked, "workspace-write+network");
+    }
+
+    #[test]
+    fn permissions_policy_to_string_round_trips_writable_roots() {
+        let mut policy = SandboxPolicy::new_workspace_write_policy();
+        if let SandboxPolicy::WorkspaceWrite {
+            writable_roots,
+            network_access,
+            ..
+        } = &mut policy
+        {
+            writable_roots.push(PathBuf::from("/tmp/cache"));
+            writable_roots.push(PathBuf::from("/var/build"));
+            *network_access = true;
+        }
+        assert_round_trips(policy, "workspace-write+network:/tmp/cache,/var/build");
+    }
+
+    #[test]
+    fn permissions_policy_rejects_unknown_values() {
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            permissions: Some("totally-unknown".to_string()),
+            ..Default::default()
+        };
+        assert!(config.permissions_policy().is_err());
+    }
+
+    fn agent_with_permissions(permissions: &str) -> AgentConfig {
+        AgentConfig {
+            permissions: Some(permissions.to_string()),
+            ..inline_agent("Inline")
+        }
+    }
+
+    #[test]
+    fn effective_policy_honors_downgrades() {
+        let session = parse_sandbox_permissions("workspace-write+network").unwrap();
+        assert_eq!(
+            agent_with_permissions("read-only").effective_policy(&session),
+            SandboxPolicy::ReadOnly
+        );
+        assert_eq!(
+            agent_with_permissions("workspace-write")
+                .effective_policy(&SandboxPolicy::DangerFullAccess),
+            SandboxPolicy::new_workspace_write_policy()
+        );
+        assert_eq!(inline_agent("Inline").effective_policy(&session), session);
+    }
+
+    #[test]
+    fn effective_policy_clamps_upgrades_to_the_session() {
+        assert_eq!(
+            agent_with_permissions("danger-full-access").effective_policy(&SandboxPolicy::ReadOnly),
+            SandboxPolicy::ReadOnly
+        );
+        let session = parse_sandbox_permissions("workspace-write:/tmp").unwrap();
+        assert_eq!(
+            agent_with_permissions("workspace-write+network:/tmp/cache,/var/build")
+                .effective_policy(&session),
+            parse_sandbox_permissions("workspace-write:/tmp/cache").unwrap()
+        );
+    }
+
+    #[test]
+    fn effective_policy_keeps_equal_policies() {
+        for permissions in [
+            "read-only",
+            "workspace-write+network:/tmp",
+            "danger-full-access",
+        ] {
+            let session = parse_sandbox_permissions(permissions).unwrap();
+            assert_eq!(
+                agent_with_permissions(permissions).effective_policy(&session),
+                session
+            );
+        }
+    }
+
+    #[test]
+    fn model_override_trims_whitespace() {
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            model: Some("  gpt-4o-mini  ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.model_override(), Some("gpt-4o-mini".to_string()));
+
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            model: Some("   ".to_string()),
+            ..Default::default()
+        };
+        assert!(config.model_override().is_none());
+    }
+
+    #[test]
+    fn reasoning_effort_ignores_case_and_whitespace() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[loud]\nprompt = \"L\"\nreasoning_effort = \"HIGH\"\n\n[padded]\nprompt = \"P\"\nreasoning_effort = \"Medium \"\n\n[plain]\nprompt = \"Q\"\nreasoning_effort = \"minimal\"\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert_eq!(
+            registry.reasoning_effort_override("loud"),
+            Some(ReasoningEffortConfig::High)
+        );
+        assert_eq!(
+            registry.reasoning_effort_override("padded"),
+            Some(ReasoningEffortConfig::Medium)
+        );
+        assert_eq!(
+            registry.reasoning_effort_override("plain"),
+            Some(ReasoningEffortConfig::Minimal)
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn unrecognized_reasoning_effort_is_dropped_with_a_warning() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[eager]\nprompt = \"E\"\nreasoning_effort = \"extreme\"\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(registry.get_agent("eager").is_some());
+        assert_eq!(registry.reasoning_effort_override("eager"), None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("eager"));
+        assert!(
+            diagnostics[0]
+                .message
+                .contains("got 'extreme'; falling back to inherited reasoning effort")
+        );
+    }
+
+    #[test]
+    fn clamped_reasoning_effort_stays_within_settings_band() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\nmin_effort = \"low\"\nmax_effort = \"Medium\"\n\n[deep]\nprompt = \"D\"\nreasoning_effort = \"high\"\n\n[quick]\nprompt = \"Q\"\nreasoning_effort = \"minimal\"\n\n[steady]\nprompt = \"S\"\nreasoning_effort = \"low\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        let effort =
+            |name: &str| registry.clamped_reasoning_effort(name, ReasoningEffortConfig::High);
+        assert_eq!(effort("deep"), ReasoningEffortConfig::Medium);
+        assert_eq!(effort("quick"), ReasoningEffortConfig::Low);
+        assert_eq!(effort("steady"), ReasoningEffortConfig::Low);
+        // Agents without an override clamp the context default.
+        assert_eq!(effort("general"), ReasoningEffortConfig::Medium);
+
+        let unbounded_dir = TempDir::new().unwrap();
+        let unbounded_root = unbounded_dir.path().join(".codex");
+        write_agents(
+            &unbounded_root,
+            "[deep]\nprompt = \"D\"\nreasoning_effort = \"high\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(unbounded_root, None, &mut Vec::new());
+        assert_eq!(
+            registry.clamped_reasoning_effort("deep", ReasoningEffortConfig::Low),
+            ReasoningEffortConfig::High
+        );
+    }
+
+    #[test]
+    fn reasoning_effort_override_is_returned() {
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            reasoning_effort: Some(ReasoningEffortConfig::High),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.reasoning_effort_override(),
+            Some(ReasoningEffortConfig::High)
+        );
+    }
+
+    #[test]
+    fn sampling_overrides_accept_in_range_values() {
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            temperature: Some(1.5),
+            top_p: Some(0.9),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.temperature_override(), Some(1.5));
+        assert_eq!(config.top_p_override(), Some(0.9));
+
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            temperature: Some(0.0),
+            top_p: Some(1.0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.temperature_override(), Some(0.0));
+        assert_eq!(config.top_p_override(), Some(1.0));
+    }
+
+    #[test]
+    fn sampling_overrides_reject_out_of_range_values() {
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            temperature: Some(2.5),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(config.temperature_override().is_none());
+
+        let config = AgentConfig {
+            prompt: Some("Inline".to_string()),
+            top_p: Some(-0.1),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(config.top_p_override().is_none());
+    }
+
+    #[test]
+    fn sampling_overrides_absent_by_default() {
+        let config: AgentConfig = toml::from_str(
+            r#"
+            prompt = "Inline"
+            model = "gpt-4o-mini"
+            "#,
+        )
+        .unwrap();
+        assert!(config.temperature_override().is_none());
+        assert!(config.top_p_override().is_none());
+
+        let config: AgentConfig = toml::from_str(
+            r#"
+            prompt = "Inline"
+            temperature = 0.2
+            top_p = 0.5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.temperature_override(), Some(0.2));
+        assert_eq!(config.top_p_override(), Some(0.5));
+    }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn command_filter_deny_overrides_allow() {
+        let config = AgentConfig {
+            allowed_commands: Some(vec!["git".to_string()]),
+            denied_commands: Some(vec!["git push".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(config.command_is_allowed(&argv(&["git", "status"])));
+        assert!(!config.command_is_allowed(&argv(&["git", "push", "origin", "main"])));
+        assert!(!config.command_is_allowed(&argv(&["ls"])));
+    }
+
+    #[test]
+    fn command_filter_matches_multi_word_prefixes() {
+        let config = AgentConfig {
+            denied_commands: Some(vec!["git push".to_string(), "rm".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(!config.command_is_allowed(&argv(&["git", "push"])));
+        assert!(!config.command_is_allowed(&argv(&["rm", "-rf", "target"])));
+        // Prefixes match whole words, not substrings.
+        assert!(config.command_is_allowed(&argv(&["git", "pushd"])));
+        assert!(config.command_is_allowed(&argv(&["rmdir", "empty"])));
+        assert!(config.command_is_allowed(&argv(&["git"])));
+        // Each command inside a `bash -lc` script is checked.
+        assert!(!config.command_is_allowed(&argv(&["bash", "-lc", "ls && rm -rf target"])));
+        assert!(config.command_is_allowed(&argv(&["bash", "-lc", "ls && git status"])));
+    }
+
+    #[test]
+    fn command_filter_defaults_to_allow() {
+        let config = inline_agent("Unrestricted");
+        assert!(config.command_is_allowed(&argv(&["rm", "-rf", "/tmp/scratch"])));
+        assert!(config.command_is_allowed(&argv(&["bash", "-lc", "echo $(whoami)"])));
+
+        let registry = AgentRegistry::new().unwrap();
+        assert!(registry.command_is_allowed("no-such-agent", &argv(&["rm"])));
+    }
+
+    fn description_of(registry: &AgentRegistry, name: &str) -> String {
+        registry
+            .list_agent_details()
+            .into_iter()
+            .find(|info| info.name == name)
+            .map(|info| info.description)
+            .unwrap()
+    }
+
+    fn names_sorted(registry: &AgentRegistry, order: AgentSortOrder) -> Vec<String> {
+        registry
+            .list_agent_details_sorted(&order)
+            .into_iter()
+            .map(|info| info.name)
+            .collect()
+    }
+
+    #[test]
+    fn list_agent_details_sorted_orders_agents() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[zeta]\nprompt = \"Z\"\ngroup = \"ops\"\n\n[aardvark]\nprompt = \"A\"\n\n[mid]\nprompt = \"M\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        let builtin_first = names_sorted(&registry, AgentSortOrder::BuiltinFirst);
+        assert_eq!(builtin_first, vec!["general", "aardvark", "mid", "zeta"]);
+        assert_eq!(
+            registry
+                .list_agent_details()
+                .into_iter()
+                .map(|info| info.name)
+                .collect::<Vec<_>>(),
+            builtin_first
+        );
+        assert_eq!(
+            names_sorted(&registry, AgentSortOrder::Alphabetical),
+            vec!["aardvark", "general", "mid", "zeta"]
+        );
+        assert_eq!(
+            names_sorted(
+                &registry,
+                AgentSortOrder::RecentlyUsed(vec!["zeta".to_string(), "mid".to_string()])
+            ),
+            vec!["zeta", "mid", "general", "aardvark"]
+        );
+    }
+
+    #[test]
+    fn missing_prompt_file_marks_agent_broken() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[ghost]\nprompt_file = \"missing.md\"\n\n[labelled]\nprompt_file = \"gone.md\"\ndescription = \"Labelled agent\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        let details = registry.list_agent_details();
+        let info = |name: &str| details.iter().find(|info| info.name == name).unwrap();
+        assert!(info("ghost").is_broken);
+        assert_eq!(info("ghost").description, "⚠ prompt file missing");
+        assert!(info("labelled").is_broken);
+        assert_eq!(
+            info("labelled").description,
+            "⚠ prompt file missing · Labelled agent"
+        );
+        assert!(!info("general").is_broken);
+    }
+
+    #[test]
+    fn description_skips_markdown_markup() {
+        let registry = AgentRegistry::load_from_roots(PathBuf::new(), None, &mut Vec::new());
+        let cases = [
+            (
+                "# Reviewer\n\nActual description. More text.",
+                "Actual description.",
+            ),
+            (
+                "> Quoted motto\n\nYou are a thorough auditor. Be strict.",
+                "thorough auditor.",
+            ),
+            ("```\ncode\n```\nExplains code.", "Explains code."),
+            ("## Heading only", "Heading only"),
+            ("You are a helpful bot. Extra.", "helpful bot."),
+        ];
+        for (prompt, expected) in cases {
+            assert_eq!(registry.extract_description(prompt), expected, "{prompt:?}");
+        }
+    }
+
+    #[test]
+    fn agent_details_include_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"p\"\ntags = [\"review\", \"rust\"]\n[plain]\nprompt = \"p\"\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        let tags_of = |name: &str| {
+            registry
+                .list_agent_details()
+                .into_iter()
+                .find(|info| info.name == name)
+                .map(|info| info.tags)
+                .unwrap()
+        };
+        assert_eq!(tags_of("reviewer"), vec!["review", "rust"]);
+        assert_eq!(tags_of("plain"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn agent_details_include_model_and_permissions() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[builder]\nprompt = \"p\"\nmodel = \"gpt-5\"\npermissions = \"workspace-write+network\"\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        let details = registry.list_agent_details();
+        let builder = details.iter().find(|info| info.name == "builder").unwrap();
+        assert_eq!(builder.model.as_deref(), Some("gpt-5"));
+        assert_eq!(
+            builder.permissions_summary.as_deref(),
+            Some("workspace-write+network")
+        );
+
+        let general = details.iter().find(|info| info.name == "general").unwrap();
+        assert_eq!(general.model, None);
+        assert_eq!(general.permissions_summary, None);
+    }
+
+    #[test]
+    fn explicit_description_wins_over_prompt() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[pirate]\nprompt = \"# Persona\\nArr, you be a pirate.\"\ndescription = \"  Answers like a pirate  \"\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(description_of(&registry, "pirate"), "Answers like a pirate");
+    }
+
+    #[test]
+    fn description_falls_back_to_file_based_prompt() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[reviewer]\nprompt_file = \"reviewer.md\"\n");
+        fs::write(
+            project_root.join("reviewer.md"),
+            "You are a careful code reviewer. Focus on bugs.\n",
+        )
+        .unwrap();
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(
+            description_of(&registry, "reviewer"),
+            "careful code reviewer."
+        );
+    }
+
+    #[test]
+    fn oversized_prompt_file_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let prompt_path = temp_dir.path().join("huge.md");
+        fs::write(
+            &prompt_path,
+            "a".repeat(DEFAULT_MAX_PROMPT_FILE_BYTES as usize + 1),
+        )
+        .unwrap();
+        let mut config = AgentConfig {
+            prompt_file: Some("huge.md".to_string()),
+            ..Default::default()
+        };
+        let err = config.get_prompt(Some(temp_dir.path())).unwrap_err();
+        assert!(err.to_string().contains("byte limit"), "{err}");
+        assert_eq!(config.prompt, None);
+
+        // The limit can be lowered from `[settings]`.
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\nmax_prompt_file_bytes = 8\n\n[reviewer]\nprompt_file = \"reviewer.md\"\n",
+        );
+        fs::write(project_root.join("reviewer.md"), "Longer than eight bytes").unwrap();
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(registry.get_agent("reviewer").is_none());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("8-byte limit"));
+    }
+
+    #[test]
+    fn binary_prompt_file_is_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[binary]\nprompt_file = \"binary.md\"\n\n[text]\nprompt = \"Text prompt\"\n",
+        );
+        fs::write(project_root.join("binary.md"), [0xff, 0xfe, 0x00, 0x01]).unwrap();
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(registry.get_agent("binary").is_none());
+        assert_eq!(registry.get_system_prompt("text"), "Text prompt");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("binary"));
+        assert!(diagnostics[0].message.contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn long_descriptions_are_truncated() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let long = "word ".repeat(100);
+        write_agents(
+            &project_root,
+            &format!("[verbose]\nprompt = \"Verbose\"\ndescription = \"{long}\"\n"),
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        let description = description_of(&registry, "verbose");
+
+        assert!(description.chars().count() <= MAX_DESCRIPTION_CHARS);
+        assert!(description.ends_with("word…"), "{description}");
+    }
+
+    #[test]
+    fn timeout_and_retries_parse_and_validate() {
+        let config: AgentConfig =
+            toml::from_str("prompt = \"p\"\ntimeout_secs = 30\nmax_retries = 2\n").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.timeout(), Duration::from_secs(30));
+        assert_eq!(config.max_retries(), 2);
+
+        let defaults = inline_agent("p");
+        assert_eq!(defaults.timeout(), DEFAULT_AGENT_TIMEOUT);
+        assert_eq!(defaults.max_retries(), 0);
+
+        let zero_timeout = AgentConfig {
+            timeout_secs: Some(0),
+            ..inline_agent("p")
+        };
+        assert!(zero_timeout.validate().is_err());
+
+        let too_many_retries = AgentConfig {
+            max_retries: Some(MAX_AGENT_RETRIES + 1),
+            ..inline_agent("p")
+        };
+        assert!(too_many_retries.validate().is_err());
+    }
+
+    #[test]
+    fn env_overrides_take_precedence_over_inherited_env() {
+        let config: AgentConfig =
+            toml::from_str("prompt = \"p\"\n[env]\nCI = \"1\"\nPATH = \"/agent/bin\"\n").unwrap();
+        assert!(config.validate().is_ok());
+
+        let mut policy = ShellEnvironmentPolicy::default();
+        policy.r#set.insert("CI".to_string(), "0".to_string());
+        apply_env_overrides(&mut policy, config.env_overrides());
+
+        let env = crate::exec_env::create_env(&policy);
+        assert_eq!(env.get("CI").map(String::as_str), Some("1"));
+        assert_eq!(env.get("PATH").map(String::as_str), Some("/agent/bin"));
+    }
+
+    #[test]
+    fn env_rejects_empty_and_assignment_keys() {
+        let empty_key = AgentConfig {
+            env: Some(HashMap::from([(String::new(), "1".to_string())])),
+            ..inline_agent("p")
+        };
+        assert!(empty_key.validate().is_err());
+
+        let assignment_key = AgentConfig {
+            env: Some(HashMap::from([("A=B".to_string(), "1".to_string())])),
+            ..inline_agent("p")
+        };
+        assert!(assignment_key.validate().is_err());
+    }
+
+    #[test]
+    fn env_overrides_default_to_empty() {
+        assert_eq!(inline_agent("p").env_overrides(), HashMap::new());
+    }
+
+    #[test]
+    fn disable_builtin_omits_general_when_user_agents_exist() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\ndisable_builtin = true\n\n[zeta]\nprompt = \"Zeta prompt\"\n\n[alpha]\nprompt = \"Alpha prompt\"\n",
+        );
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        let mut names = registry.list_agents();
+        names.sort();
+        assert_eq!(names, vec!["alpha", "zeta"]);
+        assert!(
+            registry
+                .list_agent_details()
+                .iter()
+                .all(|info| !info.is_builtin)
+        );
+        assert_eq!(registry.get_system_prompt("missing"), "Alpha prompt");
+    }
+
+    #[test]
+    fn disable_builtin_keeps_general_without_user_agents() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[settings]\ndisable_builtin = true\n");
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(registry.list_agents(), vec!["general"]);
+        let details = registry.list_agent_details();
+        assert!(details[0].is_builtin);
+    }
+
+    #[test]
+    fn empty_registry_falls_back_to_default_prompt() {
+        let registry = AgentRegistry {
+            agents: HashMap::new(),
+            builtin_included: false,
+            index: HashMap::new(),
+            agents_dir: None,
+            project_root: PathBuf::new(),
+            home_root: None,
+            sources: HashMap::new(),
+            origins: HashMap::new(),
+            default_agent: None,
+            effort_model_map: HashMap::new(),
+            allowed_models: Vec::new(),
+            effort_bounds: (None, None),
+            max_concurrent: DEFAULT_MAX_CONCURRENT_AGENTS,
+            order: Vec::new(),
+            usage: AgentUsageStats::default(),
+        };
+
+        assert_eq!(
+            registry.get_system_prompt("anything"),
+            DEFAULT_GENERAL_PROMPT
+        );
+        assert!(registry.list_agent_details().is_empty());
+    }
+
+    #[test]
+    fn personal_agents_load_from_codex_home() {
+        let codex_home = TempDir::new().unwrap();
+        write_agents(
+            codex_home.path(),
+            "[from-codex-home]\nprompt = \"Loaded from CODEX_HOME\"\n",
+        );
+
+        let project = TempDir::new().unwrap();
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(
+            project.path().join(".codex"),
+            Some(codex_home.path().to_path_buf()),
+            &mut diagnostics,
+        );
+
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(
+            registry.get_system_prompt("from-codex-home"),
+            "Loaded from CODEX_HOME"
+        );
+    }
+
+    #[test]
+    fn agents_load_from_json_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        fs::create_dir_all(&project_root).unwrap();
+        fs::write(
+            project_root.join("agents.json"),
+            r#"{"json-agent": {"prompt": "From JSON", "tags": ["json"]}}"#,
+        )
+        .unwrap();
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(registry.get_system_prompt("json-agent"), "From JSON");
+        assert_eq!(
+            registry.get_agent("json-agent").unwrap().tags,
+            Some(vec!["json".to_string()])
+        );
+    }
+
+    #[test]
+    fn agents_load_from_yaml_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        fs::create_dir_all(&project_root).unwrap();
+        fs::write(
+            project_root.join("agents.yaml"),
+            "settings:\n  disable_builtin: true\nyaml-agent:\n  prompt: From YAML\n  permissions: read-only\n",
+        )
+        .unwrap();
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(registry.list_agents(), vec!["yaml-agent"]);
+        assert_eq!(registry.get_system_prompt("yaml-agent"), "From YAML");
+        assert_eq!(
+            registry.permissions_policy("yaml-agent"),
+            Some(SandboxPolicy::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn toml_definition_wins_over_json_and_yaml() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[shared]\nprompt = \"From TOML\"\n");
+        fs::write(
+            project_root.join("agents.yaml"),
+            "shared:\n  prompt: From YAML\n",
+        )
+        .unwrap();
+        fs::write(
+            project_root.join("agents.json"),
+            r#"{"shared": {"prompt": "From JSON"}}"#,
+        )
+        .unwrap();
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut diagnostics);
+
+        assert_eq!(registry.get_system_prompt("shared"), "From TOML");
+        let paths: Vec<PathBuf> = diagnostics.iter().map(|d| d.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                project_root.join("agents.yaml"),
+                project_root.join("agents.json")
+            ]
+        );
+        assert!(
+            diagnostics
+                .iter()
+                .all(|d| d.message.contains("agents.toml") && d.agent.as_deref() == Some("shared"))
+        );
+        assert!(
+            diagnostics
+                .iter()
+                .all(|d| d.shadowed_by == Some(project_root.join("agents.toml")))
+        );
+    }
+
+    #[test]
+    fn same_directory_precedence_ignores_file_creation_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        fs::create_dir_all(&project_root).unwrap();
+        // Create the lower-precedence file first so directory order can't favor YAML.
+        fs::write(
+            project_root.join("agents.json"),
+            r#"{"shared": {"prompt": "From JSON"}, "json-only": {"prompt": "JSON"}}"#,
+        )
+        .unwrap();
+        fs::write(
+            project_root.join("agents.yaml"),
+            "shared:\n  prompt: From YAML\n",
+        )
+        .unwrap();
+
+        for _ in 0..3 {
+            let mut diagnostics = Vec::new();
+            let registry =
+                AgentRegistry::load_from_roots(project_root.clone(), None, &mut diagnostics);
+
+            assert_eq!(registry.get_system_prompt("shared"), "From YAML");
+            assert_eq!(registry.get_system_prompt("json-only"), "JSON");
+            assert_eq!(
+                registry.resolve("shared").unwrap().source,
+                Some(project_root.join("agents.yaml"))
+            );
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].path, project_root.join("agents.json"));
+            assert_eq!(
+                diagnostics[0].shadowed_by,
+                Some(project_root.join("agents.yaml"))
+            );
+        }
+    }
+
+    #[test]
+    fn disabled_agent_is_hidden_but_still_resolvable() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[hidden]\nprompt = \"Hidden prompt\"\nenabled = false\n\n[general]\nprompt = \"Custom general\"\nenabled = false\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        let listed: Vec<String> = registry
+            .list_agent_details()
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        // The built-in agent ignores `enabled`; only `[settings] disable_builtin` removes it.
+        assert_eq!(listed, vec!["general"]);
+        assert_eq!(registry.list_agents(), vec!["general"]);
+        assert_eq!(
+            registry
+                .get_agent("hidden")
+                .and_then(|a| a.prompt.as_deref()),
+            Some("Hidden prompt")
+        );
+    }
+
+    #[test]
+    fn lookup_ignores_case_of_primary_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"Review prompt\"\nmodel = \"gpt-5\"\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert!(registry.get_agent("Reviewer").is_some());
+        assert_eq!(registry.get_system_prompt("REVIEWER"), "Review prompt");
+        assert_eq!(
+            registry.model_override("Reviewer").as_deref(),
+            Some("gpt-5")
+        );
+    }
+
+    #[test]
+    fn lookup_resolves_aliases() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[code-reviewer]\nprompt = \"Review prompt\"\naliases = [\"cr\", \"Review\"]\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(registry.get_system_prompt("cr"), "Review prompt");
+        assert_eq!(registry.get_system_prompt("review"), "Review prompt");
+        assert!(registry.get_agent("unknown").is_none());
+    }
+
+    #[test]
+    fn alias_collisions_keep_the_first_agent() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[alpha]\nprompt = \"Alpha\"\naliases = [\"shared\"]\n\n[beta]\nprompt = \"Beta\"\naliases = [\"shared\", \"ALPHA\"]\n",
+        );
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut diagnostics);
+
+        assert_eq!(registry.get_system_prompt("shared"), "Alpha");
+        assert_eq!(registry.get_system_prompt("alpha"), "Alpha");
+        assert_eq!(
+            diagnostics,
+            vec![
+                AgentLoadError {
+                    agent: Some("beta".to_string()),
+                    path: project_root.join("agents.toml"),
+                    message: "alias 'shared' is already used by agent 'alpha'; ignoring it"
+                        .to_string(),
+                    shadowed_by: None,
+                },
+                AgentLoadError {
+                    agent: Some("beta".to_string()),
+                    path: project_root.join("agents.toml"),
+                    message: "alias 'ALPHA' is already used by agent 'alpha'; ignoring it"
+                        .to_string(),
+                    shadowed_by: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn user_defined_general_prompt_replaces_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[general]\nprompt = \"Project general\"\n");
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(registry.get_system_prompt("general"), "Project general");
+        assert_eq!(registry.get_system_prompt("missing"), "Project general");
+    }
+
+    #[test]
+    fn builtin_general_uses_default_prompt() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry =
+            AgentRegistry::load_from_roots(temp_dir.path().join(".codex"), None, &mut Vec::new());
+
+        assert_eq!(
+            registry.get_system_prompt("general"),
+            DEFAULT_GENERAL_PROMPT
+        );
+    }
+
+    fn known(tools: &[&str]) -> HashSet<String> {
+        tools.iter().map(|tool| (*tool).to_string()).collect()
+    }
+
+    fn with_tools(tools: &[&str]) -> AgentConfig {
+        AgentConfig {
+            tools: Some(tools.iter().map(|tool| (*tool).to_string()).collect()),
+            ..inline_agent("p")
+        }
+    }
+
+    #[test]
+    fn validate_tools_accepts_known_tools() {
+        let known = known(&["shell", "read", "write"]);
+        assert!(
+            with_tools(&["shell", "read"])
+                .validate_tools(&known)
+                .is_ok()
+        );
+        assert!(inline_agent("p").validate_tools(&known).is_ok());
+    }
+
+    #[test]
+    fn validate_tools_flags_unknown_tool() {
+        let known = known(&["shell", "read", "write"]);
+        let err = with_tools(&["shll", "read"])
+            .validate_tools(&known)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown tools: shll");
+    }
+
+    #[test]
+    fn validate_tools_accepts_empty_list() {
+        let known = known(&["shell", "read", "write"]);
+        assert!(with_tools(&[]).validate_tools(&known).is_ok());
+    }
+
+    #[test]
+    fn effective_tools_removes_excluded_tools() {
+        let inherited = vec!["shell".to_string(), "update_plan".to_string()];
+        assert_eq!(
+            with_tools(&["!shell"]).effective_tools(&inherited),
+            vec!["update_plan".to_string()]
+        );
+        assert_eq!(inline_agent("p").effective_tools(&inherited), inherited);
+        assert!(
+            with_tools(&["!shell"])
+                .validate_tools(&known(&["shell"]))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn plain_tools_list_replaces_inherited_tools() {
+        let inherited = vec!["shell".to_string(), "update_plan".to_string()];
+        assert_eq!(
+            with_tools(&["web_search"]).effective_tools(&inherited),
+            vec!["web_search".to_string()]
+        );
+    }
+
+    #[test]
+    fn mixing_excluded_and_listed_tools_is_an_error() {
+        let err = with_tools(&["!shell", "update_plan"])
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tools cannot mix excluded ('!name') and listed tool names"
+        );
+    }
+
+    #[test]
+    fn excluded_tools_apply_to_the_parent_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[runner]\nprompt = \"R\"\ntools = [\"shell\", \"update_plan\"]\n\n[careful]\nextends = \"runner\"\ntools = [\"!shell\"]\n\n[open]\nprompt = \"O\"\ntools = [\"!shell\"]\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(
+            registry.get_agent("careful").unwrap().tools,
+            Some(vec!["update_plan".to_string()])
+        );
+        assert_eq!(
+            registry.agents_with_tool("shell"),
+            vec!["general", "runner"]
+        );
+        assert_eq!(
+            registry.agents_with_tool("web_search"),
+            vec!["general", "open"]
+        );
+    }
+
+    #[test]
+    fn loading_drops_unknown_tools_and_reports_them() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[typo]\nprompt = \"p\"\ntools = [\"shll\", \"shell\"]\n",
+        );
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        let agent = registry.get_agent("typo").unwrap();
+        assert_eq!(agent.tools, Some(vec!["shell".to_string()]));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unknown tools: shll; dropping them");
+    }
+
+    fn inline_agent(prompt: &str) -> AgentConfig {
+        AgentConfig {
+            prompt: Some(prompt.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn max_output_tokens_must_be_positive() {
+        let capped = AgentConfig::builder()
+            .prompt("Summarize")
+            .max_output_tokens(256)
+            .build()
+            .unwrap();
+        assert_eq!(capped.max_output_tokens(), Some(256));
+
+        let zero = AgentConfig::builder()
+            .prompt("Summarize")
+            .max_output_tokens(0)
+            .build();
+        assert!(
+            zero.unwrap_err()
+                .to_string()
+                .contains("max_output_tokens must be greater than 0")
+        );
+
+        let uncapped = AgentConfig::builder().prompt("Summarize").build().unwrap();
+        assert_eq!(uncapped.max_output_tokens(), None);
+    }
+
+    #[test]
+    fn builder_matches_struct_literal() {
+        let built = AgentConfig::builder()
+            .prompt("Review code")
+            .model("gpt-5")
+            .reasoning_effort(ReasoningEffortConfig::High)
+            .permissions("read-only")
+            .tools(["shell", "apply_patch"])
+            .env("CI", "1")
+            .timeout_secs(30)
+            .build()
+            .unwrap();
+        let literal = AgentConfig {
+            prompt: Some("Review code".to_string()),
+            model: Some("gpt-5".to_string()),
+            reasoning_effort: Some(ReasoningEffortConfig::High),
+            permissions: Some("read-only".to_string()),
+            tools: Some(vec!["shell".to_string(), "apply_patch".to_string()]),
+            env: Some(HashMap::from([("CI".to_string(), "1".to_string())])),
+            timeout_secs: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&built).unwrap(),
+            serde_json::to_value(&literal).unwrap()
+        );
+    }
+
+    #[test]
+    fn builder_build_surfaces_validation_errors() {
+        let missing_prompt = AgentConfig::builder().model("gpt-5").build();
+        assert!(
+            missing_prompt
+                .unwrap_err()
+                .to_string()
+                .contains("must have either 'prompt' or 'prompt_file'")
+        );
+
+        let bad_temperature = AgentConfig::builder()
+            .prompt("Review code")
+            .temperature(3.0)
+            .build();
+        assert!(bad_temperature.is_err());
+    }
+
+    #[test]
+    fn interpolate_env_vars_expands_escapes_and_reports_missing() {
+        let lookup = |var: &str| (var == "MODEL_NAME").then(|| "gpt-5".to_string());
+        let mut missing = Vec::new();
+        let expand =
+            |value: &str, missing: &mut Vec<String>| interpolate_env_vars(value, &lookup, missing);
+
+        assert_eq!(expand("${MODEL_NAME}-mini", &mut missing), "gpt-5-mini");
+        assert_eq!(
+            expand("$${MODEL_NAME} costs $5", &mut missing),
+            "${MODEL_NAME} costs $5"
+        );
+        assert_eq!(
+            expand("open ${MODEL_NAME", &mut missing),
+            "open ${MODEL_NAME"
+        );
+        assert_eq!(missing, Vec::<String>::new());
+
+        assert_eq!(expand("[${UNSET}]", &mut missing), "[]");
+        assert_eq!(missing, vec!["UNSET".to_string()]);
+    }
+
+    #[test]
+    fn loading_interpolates_env_vars_and_reports_unset_ones() {
+        let lookup = |var: &str| (var == "MODEL_NAME").then(|| "gpt-5".to_string());
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(".codex").join("agents.toml");
+        let content = "[reviewer]\nprompt = \"Use $${MODEL_NAME} for ${UNSET}review\"\nmodel = \"${MODEL_NAME}\"\nenv = { TARGET = \"${MODEL_NAME}-run\" }\n";
+
+        let mut diagnostics = Vec::new();
+        let (file, _prompts) = AgentRegistry::parse_agents_content(
+            &path,
+            content,
+            AgentsFormat::Toml,
+            &lookup,
+            &mut diagnostics,
+        )
+        .unwrap();
+
+        let reviewer = file.agents.get("reviewer").unwrap();
+        assert_eq!(reviewer.model.as_deref(), Some("gpt-5"));
+        assert_eq!(
+            reviewer.prompt.as_deref(),
+            Some("Use ${MODEL_NAME} for review")
+        );
+        assert_eq!(
+            reviewer.env_overrides().get("TARGET").map(String::as_str),
+            Some("gpt-5-run")
+        );
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["environment variable 'UNSET' is not set; expanding it to an empty string"]
+        );
+    }
+
+    #[test]
+    fn inheritance_single_level_inherits_unset_fields() {
+        let mut agents = HashMap::new();
+        agents.insert(
+            "base".to_string(),
+            AgentConfig {
+                prompt: Some("Base prompt".to_string()),
+                tools: Some(vec!["shell".to_string()]),
+                model: Some("gpt-4o-mini".to_string()),
+                reasoning_effort: Some(ReasoningEffortConfig::Low),
+                permissions: Some("read-only".to_string()),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "child".to_string(),
+            AgentConfig {
+                extends: Some("base".to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(agents["child"].validate().is_ok());
+
+        let resolved = resolve_inheritance(agents, &mut Vec::new());
+        let child = &resolved["child"];
+        assert_eq!(child.prompt.as_deref(), Some("Base prompt"));
+        assert_eq!(child.tools, Some(vec!["shell".to_string()]));
+        assert_eq!(child.model_override(), Some("gpt-4o-mini".to_string()));
+        assert_eq!(
+            child.reasoning_effort_override(),
+            Some(ReasoningEffortConfig::Low)
+        );
+        assert!(matches!(
+            child.permissions_policy().unwrap(),
+            Some(SandboxPolicy::ReadOnly)
+        ));
+    }
+
+    #[test]
+    fn inheritance_resolves_multi_level_chains() {
+        let mut agents = HashMap::new();
+        agents.insert(
+            "root".to_string(),
+            AgentConfig {
+                prompt: Some("Root prompt".to_string()),
+                model: Some("root-model".to_string()),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "middle".to_string(),
+            AgentConfig {
+                prompt: Some("Middle prompt".to_string()),
+                prompt_mode: Some(PromptMode::Append),
+                extends: Some("root".to_string()),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "leaf".to_string(),
+            AgentConfig {
+                prompt: Some("Leaf prompt".to_string()),
+                prompt_mode: Some(PromptMode::Append),
+                extends: Some("middle".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let resolved = resolve_inheritance(agents, &mut Vec::new());
+        let leaf = &resolved["leaf"];
+        assert_eq!(
+            leaf.prompt.as_deref(),
+            Some("Root prompt\n\nMiddle prompt\n\nLeaf prompt")
+        );
+        assert_eq!(leaf.model_override(), Some("root-model".to_string()));
+    }
+
+    #[test]
+    fn inheritance_child_overrides_take_precedence() {
+        let mut agents = HashMap::new();
+        agents.insert(
+            "base".to_string(),
+            AgentConfig {
+                prompt: Some("Base prompt".to_string()),
+                model: Some("base-model".to_string()),
+                permissions: Some("read-only".to_string()),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "child".to_string(),
+            AgentConfig {
+                prompt: Some("Child prompt".to_string()),
+                model: Some("child-model".to_string()),
+                extends: Some("base".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let resolved = resolve_inheritance(agents, &mut Vec::new());
+        let child = &resolved["child"];
+        // Replace is the default prompt mode.
+        assert_eq!(child.prompt.as_deref(), Some("Child prompt"));
+        assert_eq!(child.model_override(), Some("child-model".to_string()));
+        assert_eq!(child.permissions.as_deref(), Some("read-only"));
+    }
+
+    #[test]
+    fn inheritance_tools_mode_replaces_or_merges() {
+        let tools = |names: &[&str]| Some(names.iter().map(|n| (*n).to_string()).collect());
+        let mut agents = HashMap::new();
+        agents.insert(
+            "base".to_string(),
+            AgentConfig {
+                prompt: Some("Base prompt".to_string()),
+                tools: tools(&["shell", "apply_patch"]),
+                ..Default::default()
+            },
+        );
+        let child = |tools_mode| AgentConfig {
+            extends: Some("base".to_string()),
+            tools: tools(&["web_search", "shell", "web_search"]),
+            tools_mode,
+            ..Default::default()
+        };
+        agents.insert("replacing".to_string(), child(None));
+        agents.insert("merging".to_string(), child(Some(ToolsMode::Merge)));
+
+        let resolved = resolve_inheritance(agents, &mut Vec::new());
+        // Replace is the default tools mode: the child list is used as written.
+        assert_eq!(
+            resolved["replacing"].tools,
+            tools(&["web_search", "shell", "web_search"])
+        );
+        // Merge keeps the parent's order, then adds the child's new tools once.
+        assert_eq!(
+            resolved["merging"].tools,
+            tools(&["shell", "apply_patch", "web_search"])
+        );
+    }
+
+    #[test]
+    fn tools_inherit_sentinel_resets_inherited_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[base]\nprompt = \"B\"\ntools = [\"shell\"]\n\n[heir]\nextends = \"base\"\n\n[free]\nextends = \"base\"\ntools = \"inherit\"\n\n[listed]\nextends = \"base\"\ntools = [\"apply_patch\"]\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        let tools = |name: &str| registry.get_agent(name).unwrap().tools.clone();
+        assert_eq!(tools("heir"), Some(vec!["shell".to_string()]));
+        assert_eq!(tools("free"), None);
+        assert_eq!(tools("listed"), Some(vec!["apply_patch".to_string()]));
+        assert!(diagnostics.is_empty());
+        assert!(registry.agents_with_tool("web_search").contains(&"free"));
+        assert!(!registry.agents_with_tool("web_search").contains(&"heir"));
+
+        let err = parse_agents_file(
+            "[bad]\nprompt = \"B\"\ntools = \"all\"\n",
+            AgentsFormat::Toml,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("tools must be a list of tool names or \"inherit\"")
+        );
+    }
+
+    #[test]
+    fn len_counts_listed_agents_with_and_without_builtin() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"Review\"\n\n[tester]\nprompt = \"Test\"\n\n[retired]\nprompt = \"Old\"\nenabled = false\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.custom_len(), 2);
+        assert!(!registry.is_empty());
+
+        write_agents(
+            &project_root,
+            "[settings]\ndisable_builtin = true\n\n[reviewer]\nprompt = \"Review\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.custom_len(), 1);
+    }
+
+    #[test]
+    fn agents_by_group_buckets_and_sorts_groups() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[zeta]\nprompt = \"Z\"\ngroup = \"web\"\n\n[alpha]\nprompt = \"A\"\ngroup = \"web\"\n\n[linter]\nprompt = \"L\"\ngroup = \"backend\"\n\n[loner]\nprompt = \"Solo\"\ngroup = \" \"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        let groups = registry.agents_by_group();
+        let names: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(group, agents)| {
+                (
+                    group.as_str(),
+                    agents.iter().map(|info| info.name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("backend", vec!["linter"]),
+                (UNGROUPED_AGENTS, vec!["general", "loner"]),
+                ("web", vec!["alpha", "zeta"]),
+            ]
+        );
+        assert_eq!(groups["web"][0].group.as_deref(), Some("web"));
+        assert_eq!(groups[UNGROUPED_AGENTS][1].group, None);
+    }
+
+    #[test]
+    fn model_aliases_expand_at_load_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let home_root = temp_dir.path().join("home");
+        write_agents(
+            &project_root,
+            "[models]\nfast = \"gpt-4o-mini\"\n\n[quick]\nprompt = \"Q\"\nmodel = \"fast\"\n\n[shouty]\nprompt = \"S\"\nmodel = \"FAST\"\n\n[exact]\nprompt = \"E\"\nmodel = \"gpt-5\"\n\n[heir]\nextends = \"quick\"\n\n[deep]\nprompt = \"D\"\nmodel = \"deep\"\n",
+        );
+        write_agents(
+            &home_root,
+            "[models]\nfast = \"home-model\"\ndeep = \"o3\"\n",
+        );
+        let registry =
+            AgentRegistry::load_from_roots(project_root, Some(home_root), &mut Vec::new());
+
+        let model = |name: &str| registry.model_override(name);
+        assert_eq!(model("quick"), Some("gpt-4o-mini".to_string()));
+        assert_eq!(model("heir"), Some("gpt-4o-mini".to_string()));
+        assert_eq!(model("deep"), Some("o3".to_string()));
+        // Aliases are case-sensitive, and unknown names pass through unchanged.
+        assert_eq!(model("shouty"), Some("FAST".to_string()));
+        assert_eq!(model("exact"), Some("gpt-5".to_string()));
+    }
+
+    #[test]
+    fn normalize_prompts_tidies_whitespace_when_enabled() {
+        let messy = "You review code.  \t\n\n\n\n\nBe concise.\t\n\nThanks. ";
+        assert_eq!(
+            normalize_prompt(messy),
+            "You review code.\n\n\nBe concise.\n\nThanks."
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let agents =
+            "[reviewer]\nprompt = \'\'\'\nYou review code.  \t\n\n\n\n\nBe concise.\t\n\'\'\'\n";
+        write_agents(
+            &project_root,
+            &format!("[settings]\nnormalize_prompts = true\n\n{agents}"),
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        assert_eq!(
+            registry.get_system_prompt("reviewer"),
+            "You review code.\n\n\nBe concise.\n"
+        );
+
+        let plain_dir = TempDir::new().unwrap();
+        let plain_root = plain_dir.path().join(".codex");
+        write_agents(&plain_root, agents);
+        let registry = AgentRegistry::load_from_roots(plain_root, None, &mut Vec::new());
+        assert_eq!(
+            registry.get_system_prompt("reviewer"),
+            "You review code.  \t\n\n\n\n\nBe concise.\t\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn load_async_matches_sync_loading() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let home_root = temp_dir.path().join("home");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt_file = \"reviewer.md\"\n\n[missing]\nprompt_file = \"missing.md\"\n\n[inline]\nprompt = \"Inline\"\nreasoning_effort = \"loud\"\n",
+        );
+        fs::write(project_root.join("reviewer.md"), "Review code").unwrap();
+        write_agents(&home_root, "[notes]\nprompt = \"Take notes\"\n");
+
+        let mut sync_diagnostics = Vec::new();
+        let sync = AgentRegistry::load_from_roots(
+            project_root.clone(),
+            Some(home_root.clone()),
+            &mut sync_diagnostics,
+        );
+        let mut async_diagnostics = Vec::new();
+        let loaded = AgentRegistry::load_from_roots_async(
+            project_root,
+            Some(home_root),
+            &mut async_diagnostics,
+        )
+        .await;
+
+        assert_eq!(loaded.list_agent_details(), sync.list_agent_details());
+        for name in ["reviewer", "missing", "inline", "notes", "general"] {
+            assert_eq!(loaded.get_system_prompt(name), sync.get_system_prompt(name));
+        }
+        assert_eq!(loaded.get_system_prompt("reviewer"), "Review code");
+        assert_eq!(async_diagnostics, sync_diagnostics);
+        assert_eq!(async_diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn agent_source_reports_where_each_agent_came_from() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let home_root = temp_dir.path().join("home");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"Project reviewer\"\naliases = [\"rev\"]\n",
+        );
+        write_agents(
+            &home_root,
+            "[reviewer]\nprompt = \"Home reviewer\"\n\n[notes]\nprompt = \"N\"\n",
+        );
+        let registry =
+            AgentRegistry::load_from_roots(project_root, Some(home_root), &mut Vec::new());
+
+        assert_eq!(registry.agent_source("reviewer"), AgentSource::Project);
+        assert_eq!(registry.agent_source("rev"), AgentSource::Project);
+        assert_eq!(registry.agent_source("notes"), AgentSource::Home);
+        assert_eq!(registry.agent_source("general"), AgentSource::Builtin);
+        let sources: Vec<(String, AgentSource)> = registry
+            .list_agent_details()
+            .into_iter()
+            .map(|info| (info.name, info.source))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("general".to_string(), AgentSource::Builtin),
+                ("notes".to_string(), AgentSource::Home),
+                ("reviewer".to_string(), AgentSource::Project),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_reflects_inheritance_and_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[base]\nprompt = \"Base prompt\"\nmodel = \"gpt-5\"\npermissions = \"read-only\"\ntools = [\"shell\"]\n\n[child]\nextends = \"base\"\nreasoning_effort = \"high\"\naliases = [\"kid\"]\n",
+        );
+
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
+
+        assert_eq!(
+            registry.resolve("KID"),
+            Some(ResolvedAgent {
+                name: "child".to_string(),
+                prompt: "Base prompt".to_string(),
+                model: Some("gpt-5".to_string()),
+                reasoning_effort: Some(ReasoningEffortConfig::High),
+                permissions: Some(SandboxPolicy::ReadOnly),
+                tools: Some(vec!["shell".to_string()]),
+                source: Some(project_root.join("agents.toml")),
+                config_hash: registry.get_agent("child").unwrap().config_hash(),
+            })
+        );
+        assert_eq!(registry.resolve("general").unwrap().source, None);
+        assert_eq!(registry.resolve("missing"), None);
+    }
+
+    #[test]
+    fn from_agents_builds_a_registry_without_files() {
+        let agents = HashMap::from([
+            (
+                "reviewer".to_string(),
+                AgentConfig::builder()
+                    .prompt("You review code.")
+                    .model("gpt-5")
+                    .build()
+                    .unwrap(),
+            ),
+            (
+                "strict".to_string(),
+                AgentConfig {
+                    extends: Some("reviewer".to_string()),
+                    aliases: Some(vec!["pedant".to_string()]),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let registry = AgentRegistry::from_agents(agents);
+
+        let mut names = registry.list_agents();
+        names.sort();
+        assert_eq!(names, vec!["general", "reviewer", "strict"]);
+        let strict = registry.resolve("Pedant").unwrap();
+        assert_eq!(strict.prompt, "You review code.");
+        assert_eq!(strict.model.as_deref(), Some("gpt-5"));
+        assert_eq!(strict.source, None);
+        assert_eq!(registry.agent_source("strict"), AgentSource::Project);
+        assert_eq!(registry.agent_source("general"), AgentSource::Builtin);
+    }
+
+    #[test]
+    fn config_hash_is_stable_and_tracks_the_prompt() {
+        let config = || AgentConfig {
+            prompt: Some("Review code".to_string()),
+            model: Some("gpt-5".to_string()),
+            temperature: Some(0.2),
+            env: Some(HashMap::from([
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string()),
+                ("C".to_string(), "3".to_string()),
+            ])),
+            ..Default::default()
+        };
+        assert_eq!(config().config_hash(), config().config_hash());
+
+        let changed = AgentConfig {
+            prompt: Some("Review docs".to_string()),
+            ..config()
+        };
+        assert_ne!(changed.config_hash(), config().config_hash());
+    }
+
+    #[test]
+    fn resolved_config_hash_changes_with_the_prompt_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[reviewer]\nprompt_file = \"reviewer.md\"\n");
+        fs::write(project_root.join("reviewer.md"), "Old prompt").unwrap();
+        let mut registry =
+            AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
+        let before = registry.resolve("reviewer").unwrap().config_hash;
+
+        registry.reload_agent_prompt("reviewer").unwrap();
+        assert_eq!(registry.resolve("reviewer").unwrap().config_hash, before);
+
+        fs::write(project_root.join("reviewer.md"), "New prompt").unwrap();
+        registry.reload_agent_prompt("reviewer").unwrap();
+        assert_ne!(registry.resolve("reviewer").unwrap().config_hash, before);
+    }
+
+    #[test]
+    fn agent_names_are_trimmed_and_case_collisions_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let home_root = temp_dir.path().join("home");
+        write_agents(
+            &project_root,
+            "[\" padded \"]\nprompt = \"Padded\"\n\n[Reviewer]\nprompt = \"First\"\n\n[reviewer]\nprompt = \"Second\"\n\n[\"  \"]\nprompt = \"Blank\"\n",
+        );
+        write_agents(&home_root, "[PADDED]\nprompt = \"Home\"\n");
+        let mut diagnostics = Vec::new();
+        let registry =
+            AgentRegistry::load_from_roots(project_root.clone(), Some(home_root), &mut diagnostics);
+
+        let mut names = registry.list_agents();
+        names.sort();
+        assert_eq!(names, vec!["Reviewer", "general", "padded"]);
+        assert_eq!(registry.get_system_prompt("padded"), "Padded");
+        assert_eq!(registry.get_system_prompt("reviewer"), "First");
+        let shadowed = format!(
+            "duplicate definition; the one in '{}' takes precedence",
+            project_root.join("agents.toml").display()
+        );
+        let problems: Vec<(Option<&str>, &str)> = diagnostics
+            .iter()
+            .map(|d| (d.agent.as_deref(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    Some("reviewer"),
+                    "agent name collides with 'Reviewer' defined earlier in this file; ignoring it"
+                ),
+                (Some("  "), "agent name must not be empty"),
+                (Some("PADDED"), shadowed.as_str()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lower_name_case_lowercases_agent_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\nagent_name_case = \"lower\"\n\n[Reviewer]\nprompt = \"R\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        let mut names = registry.list_agents();
+        names.sort();
+        assert_eq!(names, vec!["general", "reviewer"]);
+    }
+
+    #[test]
+    fn agents_with_tool_resolves_inherited_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[runner]\nprompt = \"R\"\ntools = [\"shell\", \"update_plan\"]\n\n[heir]\nextends = \"runner\"\n\n[planner]\nprompt = \"P\"\ntools = [\"update_plan\"]\n\n[anything]\nprompt = \"A\"\n\n[hidden]\nprompt = \"H\"\nenabled = false\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(
+            registry.agents_with_tool("shell"),
+            vec!["general", "runner", "heir", "anything"]
+        );
+        assert_eq!(
+            registry.agents_with_tool("update_plan"),
+            vec!["general", "runner", "heir", "planner", "anything"]
+        );
+    }
+
+    #[test]
+    fn iter_in_order_follows_definition_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join("project/.codex");
+        let home_root = temp_dir.path().join("home/.codex");
+        write_agents(
+            &project_root,
+            "[zeta]\nprompt = \"Z\"\n\n[alpha]\nprompt = \"A\"\n\n[mid]\nprompt = \"M\"\n",
+        );
+        fs::write(
+            project_root.join("agents.yaml"),
+            "yankee:\n  prompt: Y\nbravo:\n  prompt: B\n",
+        )
+        .unwrap();
+        write_agents(
+            &home_root,
+            "[personal]\nprompt = \"P\"\n\n[alpha]\nprompt = \"Shadowed\"\n",
+        );
+
+        let registry =
+            AgentRegistry::load_from_roots(project_root, Some(home_root), &mut Vec::new());
+
+        let names: Vec<&str> = registry.iter_in_order().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "general", "zeta", "alpha", "mid", "yankee", "bravo", "personal"
+            ]
+        );
     }
 
-    /// Check if agents can spawn other agents (always false to prevent recursion)
-    #[allow(dead_code)]
-    pub fn can_spawn_agents(metadata: &HashMap<String, String>) -> bool {
-        !metadata.contains_key("is_agent")
-    }
+    #[test]
+    fn to_toml_round_trips_agents() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"Review.\"\nmodel = \"gpt-5\"\ntools = [\"shell\"]\nenv = { API_TOKEN = \"s3cret\" }\n\n[strict]\nextends = \"reviewer\"\nprompt = \"Be strict.\"\nprompt_mode = \"append\"\n\n[writer]\nprompt_file = \"writer.md\"\nenabled = false\n",
+        );
+        fs::write(project_root.join("writer.md"), "Write docs.").unwrap();
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
 
-    /// Mark a context as being an agent context
-    #[allow(dead_code)]
-    pub fn mark_as_agent_context(metadata: &mut HashMap<String, String>) {
-        metadata.insert("is_agent".to_string(), "true".to_string());
+        let exported = registry.to_toml().unwrap();
+        assert!(exported.contains("prompt_file = \"writer.md\""));
+        assert!(!exported.contains("Write docs."));
+
+        write_agents(&project_root, &exported);
+        let mut diagnostics = Vec::new();
+        let reloaded = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        for name in ["reviewer", "strict", "writer"] {
+            assert_eq!(reloaded.resolve(name), registry.resolve(name));
+        }
+        assert_eq!(reloaded.to_toml().unwrap(), exported);
     }
-}
 
-/// Execute an agent with a specific task
-#[allow(dead_code)]
-pub async fn execute_agent_task(
-    agent_name: &str,
-    task: String,
-    registry: &AgentRegistry,
-) -> Result<String> {
-    // Get the agent's system prompt
-    let system_prompt = registry.get_system_prompt(agent_name);
+    #[test]
+    fn to_toml_excludes_the_builtin_agent() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[reviewer]\nprompt = \"Review.\"\n");
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
 
-    // Build the specialized prompt for this agent
-    let full_prompt = format!("{system_prompt}\n\nTask: {task}");
+        let exported = registry.to_toml().unwrap();
+        assert_eq!(exported, "[reviewer]\nprompt = \"Review.\"\n");
+    }
 
-    // Note: The actual execution will be handled by the parent context
-    // using the existing conversation infrastructure
-    Ok(full_prompt)
-}
+    #[test]
+    fn to_json_exports_resolved_agents() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"You are a careful reviewer.\"\nmodel = \"gpt-5\"\npermissions = \"read-only\"\ntools = [\"shell\"]\n\n[deployer]\ndescription = \"Ships builds\"\nprompt = \"Deploy.\"\nenabled = false\nenv = { API_TOKEN = \"s3cret\" }\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
+        let source = project_root.join("agents.toml");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::TempDir;
+        assert_eq!(
+            registry.to_json(false),
+            serde_json::json!([
+                {
+                    "name": "general",
+                    "description": registry.describe(registry.get_agent("general").unwrap()),
+                    "builtin": true,
+                    "enabled": true,
+                    "model": null,
+                    "reasoning_effort": null,
+                    "permissions": null,
+                    "tools": null,
+                    "env": {},
+                    "source": null,
+                },
+                {
+                    "name": "reviewer",
+                    "description": "careful reviewer.",
+                    "builtin": false,
+                    "enabled": true,
+                    "model": "gpt-5",
+                    "reasoning_effort": null,
+                    "permissions": "read-only",
+                    "tools": ["shell"],
+                    "env": {},
+                    "source": source,
+                },
+                {
+                    "name": "deployer",
+                    "description": "Ships builds",
+                    "builtin": false,
+                    "enabled": false,
+                    "model": null,
+                    "reasoning_effort": null,
+                    "permissions": null,
+                    "tools": null,
+                    "env": { "API_TOKEN": "s3cret" },
+                    "source": source,
+                },
+            ])
+        );
 
-    #[test]
-    fn test_default_agent_exists() {
-        let registry = AgentRegistry::new().unwrap();
-        assert!(registry.get_agent("general").is_some());
+        let redacted = registry.to_json(true);
+        assert_eq!(
+            redacted[2]["env"],
+            serde_json::json!({ "API_TOKEN": "<redacted>" })
+        );
+        assert!(!redacted.to_string().contains("s3cret"));
     }
 
     #[test]
-    fn test_agent_recursion_prevention() {
-        let mut metadata = HashMap::new();
-        assert!(AgentRegistry::can_spawn_agents(&metadata));
+    fn resolved_model_prefers_override_then_effort_map_then_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\neffort_model_map = { High = \"gpt-5\" }\n\n[pinned]\nprompt = \"P\"\nmodel = \"o3\"\nreasoning_effort = \"high\"\n\n[thinker]\nprompt = \"T\"\nreasoning_effort = \"high\"\n\n[quick]\nprompt = \"Q\"\nreasoning_effort = \"low\"\n",
+        );
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
 
-        AgentRegistry::mark_as_agent_context(&mut metadata);
-        assert!(!AgentRegistry::can_spawn_agents(&metadata));
+        assert_eq!(registry.resolved_model("pinned", "gpt-4o"), "o3");
+        assert_eq!(registry.resolved_model("thinker", "gpt-4o"), "gpt-5");
+        assert_eq!(registry.resolved_model("quick", "gpt-4o"), "gpt-4o");
+        assert_eq!(registry.resolved_model("general", "gpt-4o"), "gpt-4o");
+        assert_eq!(registry.resolved_model("missing", "gpt-4o"), "gpt-4o");
     }
 
     #[test]
-    fn test_path_traversal_prevention() {
-        // Create a temporary directory structure
+    fn allowed_models_drop_disallowed_agent_models() {
         let temp_dir = TempDir::new().unwrap();
-        let base_dir = temp_dir.path();
-
-        // Create a safe file
-        let safe_dir = base_dir.join("prompts");
-        fs::create_dir(&safe_dir).unwrap();
-        let safe_file = safe_dir.join("test.txt");
-        fs::write(&safe_file, "safe content").unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\nallowed_models = [\"gpt-4o-mini\"]\neffort_model_map = { high = \"o3\" }\n\n[cheap]\nprompt = \"C\"\nmodel = \"gpt-4o-mini\"\n\n[pricey]\nprompt = \"P\"\nmodel = \"o3\"\n\n[thinker]\nprompt = \"T\"\nreasoning_effort = \"high\"\n",
+        );
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
 
-        // Test that normal paths work
-        let result = AgentRegistry::validate_prompt_path(base_dir, "prompts/test.txt");
-        assert!(result.is_ok());
+        assert!(registry.model_is_allowed("gpt-4o-mini"));
+        assert!(!registry.model_is_allowed("o3"));
+        assert_eq!(
+            registry.model_override("cheap"),
+            Some("gpt-4o-mini".to_string())
+        );
+        assert_eq!(registry.model_override("pricey"), None);
+        assert_eq!(registry.resolved_model("pricey", "gpt-4o"), "gpt-4o");
+        assert_eq!(registry.resolved_model("thinker", "gpt-4o"), "gpt-4o");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("pricey"));
+        assert!(diagnostics[0].message.contains("not in allowed_models"));
+    }
 
-        // Test that path traversal is blocked
-        let result = AgentRegistry::validate_prompt_path(base_dir, "../../../etc/passwd");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Security error"));
+    #[test]
+    fn empty_allowed_models_allows_every_model() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[pricey]\nprompt = \"P\"\nmodel = \"o3\"\n");
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
 
-        // Test that absolute paths outside allowed dirs are blocked
-        let result = AgentRegistry::validate_prompt_path(base_dir, "/etc/passwd");
-        assert!(result.is_err());
+        assert!(registry.model_is_allowed("o3"));
+        assert_eq!(registry.model_override("pricey"), Some("o3".to_string()));
+        assert!(diagnostics.is_empty());
     }
 
     #[test]
-    fn test_agent_config_validation() {
-        // Test config with prompt is valid
-        let config = AgentConfig {
-            prompt: Some("Test prompt".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: None,
+    fn max_concurrent_is_read_from_settings_and_kept_positive() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        let load = |settings: &str| {
+            write_agents(&project_root, &format!("{settings}[a]\nprompt = \"A\"\n"));
+            AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new())
+                .max_concurrent()
         };
-        assert!(config.validate().is_ok());
 
-        // Test config with prompt_file is valid
-        let config = AgentConfig {
-            prompt: None,
-            prompt_file: Some("test.txt".to_string()),
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: None,
-        };
-        assert!(config.validate().is_ok());
+        assert_eq!(load(""), DEFAULT_MAX_CONCURRENT_AGENTS);
+        assert_eq!(load("[settings]\nmax_concurrent = 2\n\n"), 2);
+        assert_eq!(load("[settings]\nmax_concurrent = 0\n\n"), 1);
+    }
 
-        // Test config with neither prompt nor prompt_file is invalid
-        let config = AgentConfig {
-            prompt: None,
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: None,
-        };
-        assert!(config.validate().is_err());
+    #[test]
+    fn check_prompt_files_reports_missing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt_file = \"reviewer.md\"\n\n[inline]\nprompt = \"Inline\"\n",
+        );
+        fs::write(project_root.join("reviewer.md"), "Review code").unwrap();
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut Vec::new());
+        assert!(registry.check_prompt_files().is_empty());
 
-        // Test config with both prompt and prompt_file is invalid
-        let config = AgentConfig {
-            prompt: Some("Test prompt".to_string()),
-            prompt_file: Some("test.txt".to_string()),
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: None,
-        };
-        assert!(config.validate().is_err());
+        fs::remove_file(project_root.join("reviewer.md")).unwrap();
+        let problems = registry.check_prompt_files();
+        let summary: Vec<(&str, PathBuf, std::io::ErrorKind)> = problems
+            .iter()
+            .map(|(name, path, e)| (name.as_str(), path.clone(), e.kind()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(
+                "reviewer",
+                project_root.join("reviewer.md"),
+                std::io::ErrorKind::NotFound
+            )]
+        );
     }
 
     #[test]
-    fn test_agent_config_get_prompt() {
-        // Test getting prompt from inline prompt
-        let mut config = AgentConfig {
-            prompt: Some("Inline prompt".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: None,
-        };
-        assert_eq!(config.get_prompt(None).unwrap(), "Inline prompt");
+    fn validate_file_accepts_a_clean_file() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("reviewer.md"), "Review code").unwrap();
+        write_agents(
+            temp_dir.path(),
+            "[reviewer]\nprompt_file = \"reviewer.md\"\npermissions = \"read-only\"\ntools = [\"shell\"]\n",
+        );
 
-        // Test getting prompt from file
+        let problems = AgentRegistry::validate_file(&temp_dir.path().join("agents.toml"));
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn validate_file_reports_every_problem() {
         let temp_dir = TempDir::new().unwrap();
-        let prompt_file = temp_dir.path().join("test_prompt.txt");
-        fs::write(&prompt_file, "File-based prompt").unwrap();
+        write_agents(
+            temp_dir.path(),
+            "[ghost]\nprompt_file = \"missing.md\"\n\n[locked]\nprompt = \"L\"\npermissions = \"sometimes\"\n\n[typo]\nprompt = \"T\"\ntools = [\"shel\"]\n",
+        );
 
-        let mut config = AgentConfig {
-            prompt: None,
-            prompt_file: Some("test_prompt.txt".to_string()),
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: None,
-        };
+        let problems = AgentRegistry::validate_file(&temp_dir.path().join("agents.toml"));
+        let agents: Vec<Option<&str>> = problems.iter().map(|p| p.agent.as_deref()).collect();
+        assert_eq!(agents, vec![Some("ghost"), Some("locked"), Some("typo")]);
+        assert_eq!(
+            problems[0].message,
+            "prompt file 'missing.md' does not exist"
+        );
 
-        let prompt = config.get_prompt(Some(temp_dir.path())).unwrap();
-        assert_eq!(prompt, "File-based prompt");
+        let unparsable = temp_dir.path().join("broken.toml");
+        fs::write(&unparsable, "[reviewer\n").unwrap();
+        let problems = AgentRegistry::validate_file(&unparsable);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].agent, None);
+    }
 
-        // Check that prompt is cached
-        assert_eq!(config.prompt, Some("File-based prompt".to_string()));
+    #[test]
+    fn default_agent_comes_from_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\ndefault_agent = \"Reviewer\"\n\n[reviewer]\nprompt = \"Review\"\n",
+        );
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(registry.default_agent_name(), "reviewer");
     }
 
     #[test]
-    fn permissions_policy_parses_supported_values() {
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: Some("read-only".to_string()),
-        };
-        assert!(matches!(
-            config.permissions_policy().unwrap().unwrap(),
-            SandboxPolicy::ReadOnly
-        ));
+    fn unknown_default_agent_falls_back_to_general() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[settings]\ndefault_agent = \"missing\"\n\n[reviewer]\nprompt = \"Review\"\n",
+        );
 
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: Some("workspace-write+network".to_string()),
-        };
-        match config.permissions_policy().unwrap().unwrap() {
-            SandboxPolicy::WorkspaceWrite { network_access, .. } => assert!(network_access),
-            other => panic!("expected workspace-write override, got {other:?}"),
-        }
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
 
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: Some("danger-full-access".to_string()),
-        };
-        assert!(matches!(
-            config.permissions_policy().unwrap().unwrap(),
-            SandboxPolicy::DangerFullAccess
-        ));
+        assert_eq!(registry.default_agent_name(), "general");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("missing"));
+    }
 
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: Some("inherit".to_string()),
-        };
-        assert!(config.permissions_policy().unwrap().is_none());
+    #[test]
+    fn default_agent_defaults_to_general() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[reviewer]\nprompt = \"Review\"\n");
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+
+        assert_eq!(registry.default_agent_name(), "general");
     }
 
     #[test]
-    fn permissions_policy_rejects_unknown_values() {
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: None,
-            permissions: Some("totally-unknown".to_string()),
-        };
-        assert!(config.permissions_policy().is_err());
+    fn inheritance_cycles_are_dropped() {
+        let mut agents = HashMap::new();
+        agents.insert("standalone".to_string(), inline_agent("Standalone"));
+        agents.insert(
+            "a".to_string(),
+            AgentConfig {
+                extends: Some("b".to_string()),
+                ..inline_agent("A")
+            },
+        );
+        agents.insert(
+            "b".to_string(),
+            AgentConfig {
+                extends: Some("a".to_string()),
+                ..inline_agent("B")
+            },
+        );
+        agents.insert(
+            "c".to_string(),
+            AgentConfig {
+                extends: Some("a".to_string()),
+                ..inline_agent("C")
+            },
+        );
+        agents.insert(
+            "orphan".to_string(),
+            AgentConfig {
+                extends: Some("missing".to_string()),
+                ..inline_agent("Orphan")
+            },
+        );
+
+        let mut dropped = Vec::new();
+        let resolved = resolve_inheritance(agents, &mut dropped);
+        let mut names: Vec<&str> = resolved.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, vec!["standalone"]);
+        let mut dropped_names: Vec<&str> = dropped.iter().map(|(n, _)| n.as_str()).collect();
+        dropped_names.sort();
+        assert_eq!(dropped_names, vec!["a", "b", "c", "orphan"]);
+    }
+
+    fn write_agents(root: &Path, content: &str) {
+        fs::create_dir_all(root).unwrap();
+        fs::write(root.join("agents.toml"), content).unwrap();
     }
 
     #[test]
-    fn model_override_trims_whitespace() {
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: Some("  gpt-4o-mini  ".to_string()),
-            reasoning_effort: None,
-            permissions: None,
-        };
-        assert_eq!(config.model_override(), Some("gpt-4o-mini".to_string()));
+    fn diagnostics_report_agent_without_prompt() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(&project_root, "[empty]\ntools = [\"shell\"]\n");
 
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: Some("   ".to_string()),
-            reasoning_effort: None,
-            permissions: None,
-        };
-        assert!(config.model_override().is_none());
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut diagnostics);
+
+        assert!(registry.get_agent("empty").is_none());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("empty"));
+        assert_eq!(diagnostics[0].path, project_root.join("agents.toml"));
+        assert!(
+            diagnostics[0]
+                .message
+                .contains("either 'prompt' or 'prompt_file'"),
+            "{}",
+            diagnostics[0].message
+        );
     }
 
     #[test]
-    fn reasoning_effort_override_is_returned() {
-        let config = AgentConfig {
-            prompt: Some("Inline".to_string()),
-            prompt_file: None,
-            tools: None,
-            model: None,
-            reasoning_effort: Some(ReasoningEffortConfig::High),
-            permissions: None,
-        };
+    fn diagnostics_report_duplicate_definitions() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join("project/.codex");
+        let home_root = temp_dir.path().join("home/.codex");
+        write_agents(&project_root, "[reviewer]\nprompt = \"Project reviewer\"\n");
+        write_agents(&home_root, "[reviewer]\nprompt = \"Home reviewer\"\n");
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(
+            project_root.clone(),
+            Some(home_root.clone()),
+            &mut diagnostics,
+        );
+
         assert_eq!(
-            config.reasoning_effort_override(),
-            Some(ReasoningEffortConfig::High)
+            registry.get_system_prompt("reviewer"),
+            "Project reviewer".to_string()
         );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("reviewer"));
+        assert_eq!(diagnostics[0].path, home_root.join("agents.toml"));
+        assert_eq!(
+            diagnostics[0].shadowed_by,
+            Some(project_root.join("agents.toml"))
+        );
+        assert!(diagnostics[0].message.contains("duplicate definition"));
     }
-}
 
-fn parse_permissions_policy(value: &str) -> anyhow::Result<SandboxPolicy> {
-    let normalized = value.trim().to_ascii_lowercase();
-    match normalized.as_str() {
-        "read-only" | "readonly" => Ok(SandboxPolicy::ReadOnly),
-        "danger-full-access" | "dangerfullaccess" => Ok(SandboxPolicy::DangerFullAccess),
-        "workspace-write" | "workspacewrite" => Ok(SandboxPolicy::new_workspace_write_policy()),
-        "workspace-write+network"
-        | "workspace-write-network"
-        | "workspace-write:network"
-        | "workspacewrite+network" => {
-            let mut policy = SandboxPolicy::new_workspace_write_policy();
-            if let SandboxPolicy::WorkspaceWrite { network_access, .. } = &mut policy {
-                *network_access = true;
+    #[test]
+    fn diagnostics_report_duplicate_tables_in_one_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[reviewer]\nprompt = \"One\"\n\n[reviewer]\nprompt = \"Two\"\n",
+        );
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root.clone(), None, &mut diagnostics);
+
+        assert!(registry.get_agent("reviewer").is_none());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent, None);
+        assert_eq!(diagnostics[0].path, project_root.join("agents.toml"));
+    }
+
+    #[test]
+    fn diagnostics_report_malformed_permissions() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        write_agents(
+            &project_root,
+            "[locked]\nprompt = \"Locked\"\npermissions = \"superuser\"\n",
+        );
+
+        let mut diagnostics = Vec::new();
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut diagnostics);
+
+        // The agent is kept but falls back to inherited permissions.
+        assert!(registry.get_agent("locked").is_some());
+        assert_eq!(registry.permissions_policy("locked"), None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].agent.as_deref(), Some("locked"));
+        assert!(
+            diagnostics[0].message.contains("permissions"),
+            "{}",
+            diagnostics[0].message
+        );
+    }
+
+    #[test]
+    fn watch_reloads_agents_and_keeps_previous_on_parse_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_root = temp_dir.path().join(".codex");
+        fs::create_dir(&project_root).unwrap();
+        let agents_file = project_root.join("agents.toml");
+
+        let registry = AgentRegistry::load_from_roots(project_root, None, &mut Vec::new());
+        assert!(registry.get_agent("alpha").is_none());
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let _watcher = registry
+            .watch(move |reloaded| {
+                let _ = tx.send(reloaded);
+            })
+            .unwrap();
+
+        fs::write(&agents_file, "[alpha]\nprompt = \"Alpha prompt\"\n").unwrap();
+        let reloaded = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(reloaded.get_system_prompt("alpha"), "Alpha prompt");
+        // Drain any trailing reloads caused by the same save.
+        while rx.recv_timeout(WATCH_DEBOUNCE * 2).is_ok() {}
+
+        // A half-written file must not replace the last good registry.
+        fs::write(&agents_file, "[alpha\nprompt = ").unwrap();
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_err());
+
+        fs::write(&agents_file, "[beta]\nprompt = \"Beta prompt\"\n").unwrap();
+        let reloaded = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(reloaded.get_agent("alpha").is_none());
+        assert_eq!(reloaded.get_system_prompt("beta"), "Beta prompt");
+    }
+
+    /// Backend that tracks how many turns are in flight and fails tasks named "fail".
+    #[derive(Default)]
+    struct CountingBackend {
+        in_flight: std::sync::atomic::AtomicUsize,
+        peak: std::sync::atomic::AtomicUsize,
+    }
+
+    impl executor::AgentBackend for &CountingBackend {
+        async fn run_turn(
+            &self,
+            request: executor::AgentTurnRequest,
+            _cancel: CancellationToken,
+        ) -> Result<String> {
+            use std::sync::atomic::Ordering;
+
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if request.task == "fail" {
+                Err(CodexErr::UnsupportedOperation("boom".to_string()))
+            } else {
+                Ok(format!("{}: {}", request.agent_name, request.task))
             }
-            Ok(policy)
         }
-        other => Err(anyhow::anyhow!("unknown permissions value '{other}'")),
+
+        async fn run_hook(
+            &self,
+            _command: &str,
+            _request: &executor::AgentTurnRequest,
+        ) -> Result<String> {
+            Ok(String::new())
+        }
+
+        async fn run_command(
+            &self,
+            _command: &str,
+            _request: &executor::AgentTurnRequest,
+        ) -> Result<String> {
+            Ok(String::new())
+        }
+    }
+
+    fn scheduler(
+        backend: &CountingBackend,
+        max_concurrent: usize,
+    ) -> AgentScheduler<&CountingBackend> {
+        let mut agents = HashMap::new();
+        agents.insert("worker".to_string(), inline_agent("You do work."));
+        let registry = Arc::new(AgentRegistry::from_agents(agents));
+        let executor = Arc::new(executor::AgentExecutor::new(registry, backend));
+        AgentScheduler::new(executor, max_concurrent)
+    }
+
+    fn batch(tasks: &[&str]) -> Vec<(String, String)> {
+        tasks
+            .iter()
+            .map(|task| ("worker".to_string(), (*task).to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn scheduler_caps_concurrent_agents() {
+        let backend = CountingBackend::default();
+        let scheduler = scheduler(&backend, 2);
+
+        let results = scheduler
+            .run_batch(
+                batch(&["a", "b", "c", "d", "e"]),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+
+        assert_eq!(results.len(), 5);
+        assert_eq!(backend.peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn scheduler_keeps_order_and_isolates_failures() {
+        let backend = CountingBackend::default();
+        let scheduler = scheduler(&backend, 3);
+
+        let results = scheduler
+            .run_batch(
+                batch(&["first", "fail", "third"]),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+
+        assert_eq!(
+            results[0].as_ref().ok().map(String::as_str),
+            Some("worker: first")
+        );
+        assert!(matches!(results[1], Err(CodexErr::UnsupportedOperation(_))));
+        assert_eq!(
+            results[2].as_ref().ok().map(String::as_str),
+            Some("worker: third")
+        );
     }
 }
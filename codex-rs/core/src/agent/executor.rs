@@ -0,0 +1,1198 @@
+//! Runs an agent task as a real conversation turn.
+//!
+//! [`AgentExecutor`] resolves an agent's prompt and overrides from the
+//! [`AgentRegistry`], refuses to run from inside another agent, and hands the
+//! resulting [`AgentTurnRequest`] to an [`AgentBackend`], running the agent's
+//! `after_hook` once the turn is over. Command agents run their `run` command
+//! through the backend instead of a model turn. The production backend,
+//! [`ConversationBackend`], spawns a dedicated conversation through the
+//! [`ConversationManager`] and returns the final assistant message.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
+use codex_protocol::mcp_protocol::ConversationId;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use super::AgentKind;
+use super::AgentRegistry;
+use super::CommandRestrictions;
+use super::DEFAULT_MAX_AGENT_DEPTH;
+use super::apply_env_overrides;
+use super::restrict_sandbox_policy;
+use crate::config::Config;
+use crate::conversation_manager::ConversationManager;
+use crate::conversation_manager::NewConversation;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::exec::ExecParams;
+use crate::exec::ExecToolCallOutput;
+use crate::exec::SandboxType;
+use crate::exec::process_exec_tool_call;
+use crate::exec_env::create_env;
+use crate::model_family::derive_default_model_family;
+use crate::model_family::find_family_for_model;
+use crate::openai_model_info::get_model_info;
+use crate::protocol::AskForApproval;
+use crate::protocol::ErrorEvent;
+use crate::protocol::EventMsg;
+use crate::protocol::InputItem;
+use crate::protocol::Op;
+use crate::protocol::SandboxPolicy;
+use crate::protocol::TaskCompleteEvent;
+use crate::safety::get_platform_sandbox;
+use crate::util::backoff;
+
+/// Everything a backend needs to run a single agent turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentTurnRequest {
+    pub agent_name: String,
+    /// Prompt replacing the session's base instructions; `None` keeps them.
+    pub system_prompt: Option<String>,
+    /// Guidance appended after the base instructions.
+    pub prompt_suffix: Option<String>,
+    /// The task handed to the agent as user input.
+    pub task: String,
+    pub model: Option<String>,
+    pub reasoning_effort: Option<ReasoningEffortConfig>,
+    pub sandbox_policy: Option<SandboxPolicy>,
+    /// Environment variables layered over the inherited shell environment.
+    pub env: HashMap<String, String>,
+    /// Directory the turn runs in; `None` keeps the parent's working directory.
+    pub cwd: Option<PathBuf>,
+    /// Output token cap; `None` keeps the model default.
+    pub max_output_tokens: Option<u32>,
+    /// Context metadata for the agent's turn; always marked as an agent context.
+    pub metadata: HashMap<String, String>,
+    /// Shell command run instead of a model turn, for command agents.
+    pub command: Option<String>,
+    /// How long the turn, or a command agent's command or `after_hook`, may run.
+    pub timeout: Duration,
+    /// Shell commands the agent's turn may run; `None` allows every command.
+    pub command_restrictions: Option<CommandRestrictions>,
+}
+
+/// Executes an [`AgentTurnRequest`] and returns the agent's final message.
+pub trait AgentBackend: Send + Sync {
+    fn run_turn(
+        &self,
+        request: AgentTurnRequest,
+        cancel: CancellationToken,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Run an agent's `after_hook` shell command in the same sandbox, working directory
+    /// and environment as `request`, returning its captured output.
+    fn run_hook(
+        &self,
+        command: &str,
+        request: &AgentTurnRequest,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Run a command agent's `command` in the sandbox, working directory and environment
+    /// of `request`, with the task on stdin, returning its stdout. Failing commands are
+    /// errors.
+    fn run_command(
+        &self,
+        command: &str,
+        request: &AgentTurnRequest,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Reasoning effort a turn runs with when the agent sets none, so the registry's
+    /// effort band can clamp it too.
+    fn default_reasoning_effort(&self) -> Option<ReasoningEffortConfig> {
+        None
+    }
+}
+
+/// Bridges [`AgentRegistry`] configuration to an [`AgentBackend`].
+pub struct AgentExecutor<B> {
+    registry: Arc<AgentRegistry>,
+    backend: B,
+}
+
+impl<B: AgentBackend> AgentExecutor<B> {
+    pub fn new(registry: Arc<AgentRegistry>, backend: B) -> Self {
+        Self { registry, backend }
+    }
+
+    /// Run `task` with `agent_name` and return the agent's final message.
+    ///
+    /// `parent_metadata` describes the calling context; tasks that would nest agents
+    /// deeper than [`DEFAULT_MAX_AGENT_DEPTH`] are refused.
+    pub async fn execute(
+        &self,
+        agent_name: &str,
+        task: String,
+        parent_metadata: &HashMap<String, String>,
+        cancel: CancellationToken,
+    ) -> Result<String> {
+        if !AgentRegistry::spawn_allowed(parent_metadata, DEFAULT_MAX_AGENT_DEPTH) {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "agent '{agent_name}' cannot be started from inside another agent"
+            )));
+        }
+        let Some(agent) = self.registry.get_agent(agent_name) else {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "unknown agent '{agent_name}'"
+            )));
+        };
+        let timeout = agent.timeout();
+        let max_retries = agent.max_retries();
+        let after_hook = agent.after_hook();
+        let always_run_hook = agent.always_run_hook();
+        let cwd = match agent.working_dir {
+            // Registries built in memory have no project to resolve `working_dir` in.
+            Some(_) if self.registry.project_root.as_os_str().is_empty() => {
+                return Err(CodexErr::UnsupportedOperation(format!(
+                    "agent '{agent_name}': working_dir needs a registry loaded from a project"
+                )));
+            }
+            Some(_) => {
+                // `.codex` lives directly under the project root.
+                let project_root = self
+                    .registry
+                    .project_root
+                    .parent()
+                    .unwrap_or(&self.registry.project_root);
+                let dir = agent.working_dir_abs(project_root).map_err(|e| {
+                    CodexErr::UnsupportedOperation(format!("agent '{agent_name}': {e}"))
+                })?;
+                Some(dir)
+            }
+            None => None,
+        };
+
+        self.registry.record_invocation(agent_name);
+        let mut metadata = parent_metadata.clone();
+        AgentRegistry::increment_depth(&mut metadata);
+
+        let (system_prompt, prompt_suffix) = self.registry.get_system_prompt_parts(agent_name);
+        let request = AgentTurnRequest {
+            agent_name: agent_name.to_string(),
+            system_prompt,
+            prompt_suffix,
+            task,
+            model: self.registry.mapped_model(agent_name),
+            reasoning_effort: self
+                .backend
+                .default_reasoning_effort()
+                .or(self.registry.reasoning_effort_override(agent_name))
+                .map(|context_default| {
+                    self.registry
+                        .clamped_reasoning_effort(agent_name, context_default)
+                }),
+            sandbox_policy: self.registry.permissions_policy(agent_name),
+            env: agent.env_overrides(),
+            cwd,
+            max_output_tokens: agent.max_output_tokens(),
+            metadata,
+            command: match agent.kind() {
+                AgentKind::Command { run } => Some(run),
+                AgentKind::Llm => None,
+            },
+            timeout,
+            command_restrictions: agent.command_restrictions(),
+        };
+
+        let run = async {
+            let result = self
+                .run_with_retries(request.clone(), timeout, max_retries, cancel.clone())
+                .await;
+            self.finish_with_hook(after_hook, always_run_hook, &request, result)
+                .await
+        };
+        tokio::select! {
+            result = run => result,
+            _ = cancel.cancelled() => Err(CodexErr::Interrupted),
+        }
+    }
+
+    /// Run the agent's `after_hook` once its turn is over and append the hook's output to
+    /// a successful result. Failed turns skip the hook unless `always_run` is set, and
+    /// keep their error either way.
+    async fn finish_with_hook(
+        &self,
+        hook: Option<&str>,
+        always_run: bool,
+        request: &AgentTurnRequest,
+        result: Result<String>,
+    ) -> Result<String> {
+        let Some(hook) = hook else {
+            return result;
+        };
+        if result.is_err() && !always_run {
+            return result;
+        }
+        let output = match self.backend.run_hook(hook, request).await {
+            Ok(output) if output.is_empty() => "(no output)".to_string(),
+            Ok(output) => output,
+            Err(err) => format!("failed to run: {err}"),
+        };
+        match result {
+            Ok(message) => Ok(format!("{message}\n\n[after_hook `{hook}`]\n{output}")),
+            Err(err) => {
+                warn!(
+                    "agent '{}' failed; after_hook `{hook}` output:\n{output}",
+                    request.agent_name
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Run the turn under `timeout`, retrying transient failures with exponential backoff.
+    async fn run_with_retries(
+        &self,
+        request: AgentTurnRequest,
+        timeout: Duration,
+        max_retries: u32,
+        cancel: CancellationToken,
+    ) -> Result<String> {
+        let mut retries = 0;
+        loop {
+            let turn = async {
+                match &request.command {
+                    Some(command) => self.backend.run_command(command, &request).await,
+                    None => self.backend.run_turn(request.clone(), cancel.clone()).await,
+                }
+            };
+            let result = match tokio::time::timeout(timeout, turn).await {
+                Ok(result) => result,
+                Err(_) => Err(CodexErr::AgentTimeout(timeout)),
+            };
+            match result {
+                Err(err) if retries < max_retries && is_transient(&err) => {
+                    retries += 1;
+                    let delay = backoff(u64::from(retries));
+                    warn!(
+                        "agent '{}' failed: {err}; retrying ({retries}/{max_retries} in {delay:?})...",
+                        request.agent_name
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether retrying the turn could plausibly succeed.
+fn is_transient(err: &CodexErr) -> bool {
+    !matches!(
+        err,
+        CodexErr::Interrupted
+            | CodexErr::EnvVar(_)
+            | CodexErr::UsageLimitReached(_)
+            | CodexErr::UsageNotIncluded
+            | CodexErr::UnsupportedOperation(_)
+    )
+}
+
+/// Runs agent turns in a fresh conversation derived from the parent config.
+pub struct ConversationBackend {
+    conversation_manager: Arc<ConversationManager>,
+    config: Config,
+}
+
+impl ConversationBackend {
+    pub fn new(conversation_manager: Arc<ConversationManager>, config: Config) -> Self {
+        Self {
+            conversation_manager,
+            config,
+        }
+    }
+
+    fn agent_config(&self, request: &AgentTurnRequest) -> Config {
+        let mut config = self.config.clone();
+        // The agent runs unattended and must not delegate further.
+        config.approval_policy = AskForApproval::Never;
+        config.include_agent_tool = false;
+        if let Some(model) = &request.model
+            && *model != config.model
+        {
+            config.model_family =
+                find_family_for_model(model).unwrap_or_else(|| derive_default_model_family(model));
+            config.model = model.clone();
+            // The parent's limits describe the parent's model.
+            let info = get_model_info(&config.model_family);
+            config.model_context_window = info.as_ref().map(|info| info.context_window);
+            config.model_max_output_tokens = info.as_ref().map(|info| info.max_output_tokens);
+            config.model_auto_compact_token_limit =
+                info.and_then(|info| info.auto_compact_token_limit);
+        }
+        if let Some(effort) = request.reasoning_effort {
+            config.model_reasoning_effort = Some(effort);
+        }
+        if let Some(policy) = &request.sandbox_policy {
+            config.sandbox_policy = restrict_sandbox_policy(policy, &config.sandbox_policy);
+        }
+        // Resolved after the model so a kept base comes from the agent's model family.
+        let mut instructions = request
+            .system_prompt
+            .clone()
+            .or_else(|| config.base_instructions.clone())
+            .unwrap_or_else(|| config.model_family.base_instructions.clone());
+        if let Some(suffix) = &request.prompt_suffix {
+            instructions = format!("{}\n\n{suffix}", instructions.trim_end());
+        }
+        config.base_instructions = Some(instructions);
+        if let Some(cwd) = &request.cwd {
+            config.cwd = cwd.clone();
+        }
+        if let Some(max_output_tokens) = request.max_output_tokens {
+            config.max_output_tokens = Some(u64::from(max_output_tokens));
+        }
+        if let Some(restrictions) = &request.command_restrictions {
+            // Only the shell tool checks commands against the restrictions.
+            config.use_experimental_streamable_shell_tool = false;
+            config.use_experimental_unified_exec_tool = false;
+            config.command_restrictions = Some(restrictions.clone());
+        }
+        apply_env_overrides(&mut config.shell_environment_policy, request.env.clone());
+        config
+    }
+}
+
+impl AgentBackend for ConversationBackend {
+    fn default_reasoning_effort(&self) -> Option<ReasoningEffortConfig> {
+        self.config.model_reasoning_effort
+    }
+
+    async fn run_turn(
+        &self,
+        request: AgentTurnRequest,
+        cancel: CancellationToken,
+    ) -> Result<String> {
+        let NewConversation {
+            conversation_id,
+            conversation,
+            ..
+        } = self
+            .conversation_manager
+            .new_conversation(self.agent_config(&request))
+            .await?;
+        let _guard = ConversationGuard {
+            conversation_manager: Arc::clone(&self.conversation_manager),
+            conversation_id,
+        };
+
+        conversation
+            .submit(Op::UserInput {
+                items: vec![InputItem::Text { text: request.task }],
+            })
+            .await?;
+
+        loop {
+            let event = tokio::select! {
+                event = conversation.next_event() => event?,
+                _ = cancel.cancelled() => {
+                    conversation.submit(Op::Interrupt).await?;
+                    return Err(CodexErr::Interrupted);
+                }
+            };
+            match event.msg {
+                EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }) => {
+                    return Ok(last_agent_message.unwrap_or_default());
+                }
+                EventMsg::Error(ErrorEvent { message }) => {
+                    return Err(CodexErr::AgentFailed(format!(
+                        "{}: {message}",
+                        request.agent_name
+                    )));
+                }
+                EventMsg::TurnAborted(_) => return Err(CodexErr::Interrupted),
+                _ => {}
+            }
+        }
+    }
+
+    async fn run_hook(&self, command: &str, request: &AgentTurnRequest) -> Result<String> {
+        let script = vec!["bash".to_string(), "-lc".to_string(), command.to_string()];
+        let output = self.exec(script, request).await?;
+        let mut text = output.aggregated_output.text.trim_end().to_string();
+        if output.exit_code != 0 {
+            text.push_str(&format!("\n(exit code {})", output.exit_code));
+        }
+        Ok(text)
+    }
+
+    async fn run_command(&self, command: &str, request: &AgentTurnRequest) -> Result<String> {
+        // The task is passed as `$1` and piped in, so it is never parsed by the shell.
+        let script = vec![
+            "bash".to_string(),
+            "-lc".to_string(),
+            format!("printf '%s' \"$1\" | {{\n{command}\n}}"),
+            "codex-agent".to_string(),
+            request.task.clone(),
+        ];
+        let output = self.exec(script, request).await?;
+        if output.exit_code != 0 {
+            return Err(CodexErr::AgentFailed(format!(
+                "{}: `{command}` exited with code {}: {}",
+                request.agent_name,
+                output.exit_code,
+                output.stderr.text.trim_end()
+            )));
+        }
+        Ok(output.stdout.text.trim_end().to_string())
+    }
+}
+
+/// Shuts an agent's conversation down and drops it from the manager however its turn
+/// ends, including when the turn's future is dropped.
+struct ConversationGuard {
+    conversation_manager: Arc<ConversationManager>,
+    conversation_id: ConversationId,
+}
+
+impl Drop for ConversationGuard {
+    fn drop(&mut self) {
+        let conversation_manager = Arc::clone(&self.conversation_manager);
+        let conversation_id = self.conversation_id;
+        tokio::spawn(async move {
+            if let Some(conversation) = conversation_manager
+                .remove_conversation(&conversation_id)
+                .await
+            {
+                let _ = conversation.submit(Op::Interrupt).await;
+                let _ = conversation.submit(Op::Shutdown).await;
+            }
+        });
+    }
+}
+
+impl ConversationBackend {
+    /// Run `command` in the sandbox, working directory and environment of `request`.
+    async fn exec(
+        &self,
+        command: Vec<String>,
+        request: &AgentTurnRequest,
+    ) -> Result<ExecToolCallOutput> {
+        let config = self.agent_config(request);
+        let sandbox_type = match config.sandbox_policy {
+            SandboxPolicy::DangerFullAccess => SandboxType::None,
+            _ => get_platform_sandbox().unwrap_or(SandboxType::None),
+        };
+        let params = ExecParams {
+            command,
+            cwd: config.cwd.clone(),
+            // The executor enforces the timeout too; this keeps the exec default from
+            // cutting long commands short.
+            timeout_ms: Some(u64::try_from(request.timeout.as_millis()).unwrap_or(u64::MAX)),
+            env: create_env(&config.shell_environment_policy),
+            with_escalated_permissions: None,
+            justification: None,
+        };
+        process_exec_tool_call(
+            params,
+            sandbox_type,
+            &config.sandbox_policy,
+            &config.cwd,
+            &config.codex_linux_sandbox_exe,
+            None,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CodexAuth;
+    use crate::agent::AgentConfig;
+    use crate::agent::DEFAULT_AGENT_TIMEOUT;
+    use crate::config::ConfigOverrides;
+    use crate::config::ConfigToml;
+    use pretty_assertions::assert_eq;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct RecordingBackend {
+        requests: Mutex<Vec<AgentTurnRequest>>,
+        /// `after_hook` commands run, in order.
+        hooks: Mutex<Vec<String>>,
+        /// Command agent commands run, with the task they were given, in order.
+        commands: Mutex<Vec<(String, String)>>,
+        hang: bool,
+        /// Number of leading attempts that fail.
+        fail_first: usize,
+        default_effort: Option<ReasoningEffortConfig>,
+    }
+
+    impl AgentBackend for &RecordingBackend {
+        async fn run_turn(
+            &self,
+            request: AgentTurnRequest,
+            _cancel: CancellationToken,
+        ) -> Result<String> {
+            let task = request.task.clone();
+            let attempt = {
+                let mut requests = self.requests.lock().unwrap();
+                requests.push(request);
+                requests.len()
+            };
+            if self.hang {
+                std::future::pending::<()>().await;
+            }
+            if attempt <= self.fail_first {
+                return Err(CodexErr::AgentFailed(format!("attempt {attempt}")));
+            }
+            Ok(format!("done: {task}"))
+        }
+
+        async fn run_hook(&self, command: &str, _request: &AgentTurnRequest) -> Result<String> {
+            self.hooks.lock().unwrap().push(command.to_string());
+            Ok(format!("ran {command}"))
+        }
+
+        async fn run_command(&self, command: &str, request: &AgentTurnRequest) -> Result<String> {
+            self.commands
+                .lock()
+                .unwrap()
+                .push((command.to_string(), request.task.clone()));
+            Ok(format!("stdout of {command}"))
+        }
+
+        fn default_reasoning_effort(&self) -> Option<ReasoningEffortConfig> {
+            self.default_effort
+        }
+    }
+
+    fn registry() -> Arc<AgentRegistry> {
+        let mut agents = HashMap::new();
+        agents.insert(
+            "reviewer".to_string(),
+            AgentConfig {
+                prompt: Some("You review code.".to_string()),
+                model: Some("gpt-5".to_string()),
+                reasoning_effort: Some(ReasoningEffortConfig::High),
+                permissions: Some("read-only".to_string()),
+                env: Some(HashMap::from([("CI".to_string(), "1".to_string())])),
+                max_output_tokens: Some(512),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "slow".to_string(),
+            AgentConfig {
+                prompt: Some("You take your time.".to_string()),
+                timeout_secs: Some(1),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "flaky".to_string(),
+            AgentConfig {
+                prompt: Some("You sometimes fail.".to_string()),
+                max_retries: Some(2),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "formatter".to_string(),
+            AgentConfig {
+                prompt: Some("You format code.".to_string()),
+                after_hook: Some("cargo fmt".to_string()),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "tidy".to_string(),
+            AgentConfig {
+                prompt: Some("You tidy up.".to_string()),
+                after_hook: Some("cargo fmt".to_string()),
+                always_run_hook: Some(true),
+                ..Default::default()
+            },
+        );
+        agents.insert(
+            "linter".to_string(),
+            AgentConfig {
+                run: Some("cargo clippy".to_string()),
+                permissions: Some("read-only".to_string()),
+                ..Default::default()
+            },
+        );
+        Arc::new(AgentRegistry::from_agents(agents))
+    }
+
+    fn test_config(codex_home: &TempDir) -> Config {
+        Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )
+        .expect("load default test config")
+    }
+
+    /// The production backend over `config`, for checking what agent conversations and
+    /// commands are started with.
+    fn conversation_backend(config: Config) -> ConversationBackend {
+        ConversationBackend::new(
+            Arc::new(ConversationManager::with_auth(CodexAuth::from_api_key(
+                "Test API Key",
+            ))),
+            config,
+        )
+    }
+
+    fn turn_request(agent_name: &str) -> AgentTurnRequest {
+        AgentTurnRequest {
+            agent_name: agent_name.to_string(),
+            system_prompt: None,
+            prompt_suffix: None,
+            task: "task".to_string(),
+            model: None,
+            reasoning_effort: None,
+            sandbox_policy: None,
+            env: HashMap::new(),
+            cwd: None,
+            max_output_tokens: None,
+            metadata: HashMap::new(),
+            command: None,
+            timeout: DEFAULT_AGENT_TIMEOUT,
+            command_restrictions: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_passes_prompt_and_overrides_to_backend() {
+        let backend = RecordingBackend::default();
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let output = executor
+            .execute(
+                "reviewer",
+                "check main.rs".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(output, "done: check main.rs");
+        let requests = backend.requests.lock().unwrap();
+        assert_eq!(
+            *requests,
+            vec![AgentTurnRequest {
+                agent_name: "reviewer".to_string(),
+                system_prompt: Some("You review code.".to_string()),
+                prompt_suffix: None,
+                task: "check main.rs".to_string(),
+                model: Some("gpt-5".to_string()),
+                reasoning_effort: Some(ReasoningEffortConfig::High),
+                sandbox_policy: Some(SandboxPolicy::ReadOnly),
+                env: HashMap::from([("CI".to_string(), "1".to_string())]),
+                cwd: None,
+                max_output_tokens: Some(512),
+                metadata: HashMap::from([("agent_depth".to_string(), "1".to_string())]),
+                command: None,
+                timeout: DEFAULT_AGENT_TIMEOUT,
+                command_restrictions: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn reasoning_effort_is_clamped_including_the_context_default() {
+        let mut registry = AgentRegistry::from_agents(HashMap::from([
+            (
+                "thinker".to_string(),
+                AgentConfig {
+                    prompt: Some("You think hard.".to_string()),
+                    reasoning_effort: Some(ReasoningEffortConfig::High),
+                    ..Default::default()
+                },
+            ),
+            (
+                "plain".to_string(),
+                AgentConfig {
+                    prompt: Some("You answer.".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]));
+        registry.effort_bounds = (None, Some(ReasoningEffortConfig::Medium));
+        let backend = RecordingBackend {
+            default_effort: Some(ReasoningEffortConfig::High),
+            ..Default::default()
+        };
+        let executor = AgentExecutor::new(Arc::new(registry), &backend);
+
+        for agent_name in ["thinker", "plain"] {
+            executor
+                .execute(
+                    agent_name,
+                    "go".to_string(),
+                    &HashMap::new(),
+                    CancellationToken::new(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let efforts: Vec<Option<ReasoningEffortConfig>> = backend
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.reasoning_effort)
+            .collect();
+        assert_eq!(
+            efforts,
+            vec![
+                Some(ReasoningEffortConfig::Medium),
+                Some(ReasoningEffortConfig::Medium)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn effort_model_map_picks_the_agent_model() {
+        let mut registry = AgentRegistry::from_agents(HashMap::from([(
+            "thinker".to_string(),
+            AgentConfig {
+                prompt: Some("You think hard.".to_string()),
+                reasoning_effort: Some(ReasoningEffortConfig::High),
+                ..Default::default()
+            },
+        )]));
+        registry.effort_model_map = HashMap::from([("high".to_string(), "o3".to_string())]);
+        let backend = RecordingBackend::default();
+        let executor = AgentExecutor::new(Arc::new(registry), &backend);
+
+        executor
+            .execute(
+                "thinker",
+                "go".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            backend.requests.lock().unwrap()[0].model,
+            Some("o3".to_string())
+        );
+    }
+
+    #[test]
+    fn agent_model_brings_its_own_limits() {
+        let codex_home = TempDir::new().unwrap();
+        let backend = conversation_backend(test_config(&codex_home));
+
+        let config = backend.agent_config(&AgentTurnRequest {
+            model: Some("o3".to_string()),
+            ..turn_request("thinker")
+        });
+
+        assert_eq!(config.model, "o3");
+        assert_eq!(config.model_family.slug, "o3");
+        assert_eq!(config.model_context_window, Some(200_000));
+        assert_eq!(config.model_max_output_tokens, Some(100_000));
+    }
+
+    #[tokio::test]
+    async fn command_agent_runs_its_command_instead_of_a_turn() {
+        let backend = RecordingBackend::default();
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let output = executor
+            .execute(
+                "linter",
+                "check src/".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(output, "stdout of cargo clippy");
+        assert_eq!(
+            *backend.commands.lock().unwrap(),
+            vec![("cargo clippy".to_string(), "check src/".to_string())]
+        );
+        assert!(backend.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn execute_refuses_to_run_inside_an_agent() {
+        let backend = RecordingBackend::default();
+        let executor = AgentExecutor::new(registry(), &backend);
+        let mut parent = HashMap::new();
+        AgentRegistry::increment_depth(&mut parent);
+
+        let result = executor
+            .execute(
+                "reviewer",
+                "nested".to_string(),
+                &parent,
+                CancellationToken::new(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(CodexErr::UnsupportedOperation(_))));
+        assert!(backend.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn execute_stops_when_cancelled() {
+        let backend = RecordingBackend {
+            hang: true,
+            ..Default::default()
+        };
+        let executor = AgentExecutor::new(registry(), &backend);
+        let cancel = CancellationToken::new();
+        let parent = HashMap::new();
+
+        let run = executor.execute("reviewer", "slow".to_string(), &parent, cancel.clone());
+        cancel.cancel();
+
+        assert!(matches!(run.await, Err(CodexErr::Interrupted)));
+    }
+
+    #[tokio::test]
+    async fn execute_times_out_hung_turns() {
+        let backend = RecordingBackend {
+            hang: true,
+            ..Default::default()
+        };
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let result = executor
+            .execute(
+                "slow",
+                "never finishes".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+
+        assert!(
+            matches!(result, Err(CodexErr::AgentTimeout(timeout)) if timeout == Duration::from_secs(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn execute_retries_transient_failures() {
+        let backend = RecordingBackend {
+            fail_first: 1,
+            ..Default::default()
+        };
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let output = executor
+            .execute(
+                "flaky",
+                "try again".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(output, "done: try again");
+        assert_eq!(backend.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn execute_returns_last_error_when_retries_are_exhausted() {
+        let backend = RecordingBackend {
+            fail_first: usize::MAX,
+            ..Default::default()
+        };
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let result = executor
+            .execute(
+                "flaky",
+                "keeps failing".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(CodexErr::AgentFailed(message)) if message == "attempt 3"));
+        assert_eq!(backend.requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn after_hook_runs_after_a_successful_turn() {
+        let backend = RecordingBackend::default();
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let output = executor
+            .execute(
+                "formatter",
+                "format".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            output,
+            "done: format\n\n[after_hook `cargo fmt`]\nran cargo fmt"
+        );
+        assert_eq!(
+            *backend.hooks.lock().unwrap(),
+            vec!["cargo fmt".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn after_hook_is_skipped_on_failure_unless_always_run() {
+        let backend = RecordingBackend {
+            fail_first: usize::MAX,
+            ..Default::default()
+        };
+        let executor = AgentExecutor::new(registry(), &backend);
+
+        let result = executor
+            .execute(
+                "formatter",
+                "format".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+        assert!(matches!(result, Err(CodexErr::AgentFailed(_))));
+        assert!(backend.hooks.lock().unwrap().is_empty());
+
+        let result = executor
+            .execute(
+                "tidy",
+                "tidy".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+        assert!(matches!(result, Err(CodexErr::AgentFailed(_))));
+        assert_eq!(
+            *backend.hooks.lock().unwrap(),
+            vec!["cargo fmt".to_string()]
+        );
+    }
+
+    #[test]
+    fn command_restrictions_reach_the_agent_conversation() {
+        let codex_home = TempDir::new().unwrap();
+        let backend = conversation_backend(test_config(&codex_home));
+        let restrictions = CommandRestrictions {
+            allowed: Some(vec!["cargo".to_string()]),
+            denied: None,
+        };
+
+        let config = backend.agent_config(&AgentTurnRequest {
+            command_restrictions: Some(restrictions.clone()),
+            ..turn_request("builder")
+        });
+
+        assert_eq!(config.command_restrictions, Some(restrictions));
+        assert!(!config.use_experimental_streamable_shell_tool);
+        assert!(!config.use_experimental_unified_exec_tool);
+        assert_eq!(
+            backend
+                .agent_config(&turn_request("free"))
+                .command_restrictions,
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_agents_are_held_to_their_timeout() {
+        let codex_home = TempDir::new().unwrap();
+        let mut config = test_config(&codex_home);
+        config.sandbox_policy = SandboxPolicy::DangerFullAccess;
+        let registry = Arc::new(AgentRegistry::from_agents(HashMap::from([(
+            "sleeper".to_string(),
+            AgentConfig {
+                run: Some("sleep 30".to_string()),
+                timeout_secs: Some(1),
+                ..Default::default()
+            },
+        )])));
+        let executor = AgentExecutor::new(registry, conversation_backend(config));
+
+        let result = executor
+            .execute(
+                "sleeper",
+                "wait".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+
+        assert!(
+            matches!(result, Err(CodexErr::AgentTimeout(timeout)) if timeout == Duration::from_secs(1))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn agents_run_in_their_working_dir() {
+        let codex_home = TempDir::new().unwrap();
+        let web = TempDir::new().unwrap();
+        let mut config = test_config(&codex_home);
+        config.sandbox_policy = SandboxPolicy::DangerFullAccess;
+        let parent_cwd = config.cwd.clone();
+        let backend = conversation_backend(config);
+        let request = AgentTurnRequest {
+            cwd: Some(web.path().to_path_buf()),
+            ..turn_request("frontend")
+        };
+
+        assert_eq!(backend.agent_config(&request).cwd, web.path());
+        assert_eq!(
+            backend.agent_config(&turn_request("anywhere")).cwd,
+            parent_cwd
+        );
+        let pwd = backend.run_command("pwd -P", &request).await.unwrap();
+        assert_eq!(PathBuf::from(pwd), web.path().canonicalize().unwrap());
+    }
+
+    #[tokio::test]
+    async fn working_dir_needs_a_project_root() {
+        let registry = Arc::new(AgentRegistry::from_agents(HashMap::from([(
+            "frontend".to_string(),
+            AgentConfig {
+                prompt: Some("You build the UI.".to_string()),
+                working_dir: Some("web".to_string()),
+                ..Default::default()
+            },
+        )])));
+        let backend = RecordingBackend::default();
+        let executor = AgentExecutor::new(registry, &backend);
+
+        let err = executor
+            .execute(
+                "frontend",
+                "go".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "unsupported operation: agent 'frontend': working_dir needs a registry loaded from a project"
+        );
+        assert!(backend.requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn max_output_tokens_caps_the_agent_conversation() {
+        let codex_home = TempDir::new().unwrap();
+        let backend = conversation_backend(test_config(&codex_home));
+
+        let config = backend.agent_config(&AgentTurnRequest {
+            max_output_tokens: Some(512),
+            ..turn_request("summarizer")
+        });
+
+        assert_eq!(config.max_output_tokens, Some(512));
+        assert_eq!(
+            backend
+                .agent_config(&turn_request("verbose"))
+                .max_output_tokens,
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn after_hook_runs_on_the_production_backend() {
+        let codex_home = TempDir::new().unwrap();
+        let mut config = test_config(&codex_home);
+        config.sandbox_policy = SandboxPolicy::DangerFullAccess;
+        let registry = Arc::new(AgentRegistry::from_agents(HashMap::from([(
+            "formatter".to_string(),
+            AgentConfig {
+                run: Some("cat".to_string()),
+                after_hook: Some("echo formatted".to_string()),
+                ..Default::default()
+            },
+        )])));
+        let executor = AgentExecutor::new(registry, conversation_backend(config));
+
+        let output = executor
+            .execute(
+                "formatter",
+                "format src/".to_string(),
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        // Hook output includes stderr, which login shell startup may add to.
+        assert!(
+            output.starts_with("format src/\n\n[after_hook `echo formatted`]\n"),
+            "{output}"
+        );
+        assert!(output.ends_with("formatted"), "{output}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_agents_read_the_task_and_fail_on_nonzero_exit() {
+        let codex_home = TempDir::new().unwrap();
+        let mut config = test_config(&codex_home);
+        config.sandbox_policy = SandboxPolicy::DangerFullAccess;
+        let backend = conversation_backend(config);
+
+        let echoed = backend
+            .run_command("tr a-z A-Z", &turn_request("shout"))
+            .await
+            .unwrap();
+        assert_eq!(echoed, "TASK");
+
+        let failed = backend.run_command("exit 3", &turn_request("broken")).await;
+        assert!(
+            matches!(&failed, Err(CodexErr::AgentFailed(message)) if message.starts_with("broken: `exit 3` exited with code 3")),
+            "{failed:?}"
+        );
+    }
+
+    #[test]
+    fn prompt_suffix_is_appended_to_the_base_instructions() {
+        let codex_home = TempDir::new().unwrap();
+        let mut config = test_config(&codex_home);
+        config.base_instructions = Some("Base instructions.".to_string());
+        config.user_instructions = Some("From AGENTS.md".to_string());
+        let backend = conversation_backend(config);
+
+        let suffix_only = backend.agent_config(&AgentTurnRequest {
+            prompt_suffix: Some("Be brief.".to_string()),
+            ..turn_request("brief")
+        });
+        assert_eq!(
+            suffix_only.base_instructions.as_deref(),
+            Some("Base instructions.\n\nBe brief.")
+        );
+        assert_eq!(
+            suffix_only.user_instructions.as_deref(),
+            Some("From AGENTS.md")
+        );
+
+        let with_prompt = backend.agent_config(&AgentTurnRequest {
+            system_prompt: Some("You review code.".to_string()),
+            prompt_suffix: Some("Be brief.".to_string()),
+            ..turn_request("reviewer")
+        });
+        assert_eq!(
+            with_prompt.base_instructions.as_deref(),
+            Some("You review code.\n\nBe brief.")
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Persistence for the list of recently used agents.
+//!
+//! The list lives at `$CODEX_HOME/agent_history.json`, most recent first, and is
+//! used to float frequently used agents to the top of the `@agent` popup.
+
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Filename that stores the recent agents inside `$CODEX_HOME`.
+const AGENT_HISTORY_FILENAME: &str = "agent_history.json";
+
+/// Maximum number of recent agents kept on disk.
+const MAX_RECENT_AGENTS: usize = 10;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AgentHistory {
+    #[serde(default)]
+    recent: Vec<String>,
+}
+
+/// Load the recently used agent names, most recent first. A missing or unreadable
+/// history yields an empty list.
+pub fn load_recent_agents(codex_home: &Path) -> Vec<String> {
+    let path = codex_home.join(AGENT_HISTORY_FILENAME);
+    match std::fs::read_to_string(&path) {
+        Ok(serialized) => match serde_json::from_str::<AgentHistory>(&serialized) {
+            Ok(history) => history.recent,
+            Err(error) => {
+                tracing::warn!("failed to parse agent history: {error:?}");
+                Vec::new()
+            }
+        },
+        Err(error) => {
+            if error.kind() != ErrorKind::NotFound {
+                tracing::warn!("failed to read agent history: {error:?}");
+            }
+            Vec::new()
+        }
+    }
+}
+
+/// Record that `agent_name` was just used and persist the updated list.
+/// Returns the new list, most recent first.
+pub fn record_agent_use(codex_home: &Path, agent_name: &str) -> std::io::Result<Vec<String>> {
+    let mut recent = load_recent_agents(codex_home);
+    push_recent(&mut recent, agent_name);
+
+    std::fs::create_dir_all(codex_home)?;
+    let history = AgentHistory {
+        recent: recent.clone(),
+    };
+    let serialized = serde_json::to_string_pretty(&history).map_err(std::io::Error::other)?;
+    std::fs::write(codex_home.join(AGENT_HISTORY_FILENAME), serialized)?;
+    Ok(recent)
+}
+
+/// Move `agent_name` to the front of `recent`, dropping duplicates and anything past
+/// [`MAX_RECENT_AGENTS`].
+fn push_recent(recent: &mut Vec<String>, agent_name: &str) {
+    recent.retain(|name| name != agent_name);
+    recent.insert(0, agent_name.to_string());
+    recent.truncate(MAX_RECENT_AGENTS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn missing_history_is_empty() {
+        let codex_home = TempDir::new().unwrap();
+        assert_eq!(load_recent_agents(codex_home.path()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn repeated_use_moves_agent_to_front_without_duplicates() {
+        let codex_home = TempDir::new().unwrap();
+        record_agent_use(codex_home.path(), "reviewer").unwrap();
+        record_agent_use(codex_home.path(), "researcher").unwrap();
+        let recent = record_agent_use(codex_home.path(), "reviewer").unwrap();
+
+        assert_eq!(recent, vec!["reviewer", "researcher"]);
+        assert_eq!(load_recent_agents(codex_home.path()), recent);
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let codex_home = TempDir::new().unwrap();
+        for i in 0..(MAX_RECENT_AGENTS + 3) {
+            record_agent_use(codex_home.path(), &format!("agent-{i}")).unwrap();
+        }
+
+        let recent = load_recent_agents(codex_home.path());
+        assert_eq!(recent.len(), MAX_RECENT_AGENTS);
+        assert_eq!(recent.first().map(String::as_str), Some("agent-12"));
+        assert_eq!(recent.last().map(String::as_str), Some("agent-3"));
+    }
+}
@@ -127,6 +127,7 @@ impl ModelClient {
                     &self.config.model_family,
                     &self.client,
                     &self.provider,
+                    self.config.max_output_tokens,
                 )
                 .await?;
 
@@ -219,6 +220,7 @@ impl ModelClient {
             include,
             prompt_cache_key: Some(self.conversation_id.to_string()),
             text,
+            max_output_tokens: self.config.max_output_tokens,
         };
 
         let mut payload_json = serde_json::to_value(&payload)?;
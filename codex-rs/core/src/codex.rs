@@ -36,6 +36,7 @@ use serde_json::Value;
 use tokio::sync::Mutex;
 use tokio::sync::oneshot;
 use tokio::task::AbortHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
@@ -43,6 +44,10 @@ use tracing::trace;
 use tracing::warn;
 
 use crate::ModelProviderInfo;
+use crate::agent::AgentKind;
+use crate::agent::AgentScheduler;
+use crate::agent::executor::AgentExecutor;
+use crate::agent::executor::ConversationBackend;
 use crate::apply_patch;
 use crate::apply_patch::ApplyPatchExec;
 use crate::apply_patch::CODEX_APPLY_PATCH_ARG1;
@@ -54,6 +59,8 @@ use crate::client_common::ResponseEvent;
 use crate::config::Config;
 use crate::config_types::ShellEnvironmentPolicy;
 use crate::conversation_history::ConversationHistory;
+use crate::conversation_manager::ConversationManager;
+use crate::custom_prompts::PromptCache;
 use crate::environment_context::EnvironmentContext;
 use crate::error::CodexErr;
 use crate::error::Result as CodexResult;
@@ -73,7 +80,6 @@ use crate::exec_command::WriteStdinParams;
 use crate::exec_env::create_env;
 use crate::mcp_connection_manager::McpConnectionManager;
 use crate::mcp_tool_call::handle_mcp_tool_call;
-use crate::model_family::derive_default_model_family;
 use crate::model_family::find_family_for_model;
 use crate::openai_model_info::get_model_info;
 use crate::openai_tools::ApplyPatchToolArgs;
@@ -297,6 +303,12 @@ pub(crate) struct Session {
 
     /// Agent registry for multi-agent orchestration
     agent_registry: Mutex<Option<Arc<crate::agent::AgentRegistry>>>,
+    /// Spawns the conversations agent tool calls run in.
+    agent_conversations: Arc<ConversationManager>,
+    /// Custom prompts read so far, so listing them only re-reads changed files.
+    prompt_cache: Mutex<PromptCache>,
+    /// Cancels the prompt listing in flight, if any.
+    prompt_scan: Mutex<Option<CancellationToken>>,
 
     /// Wrapper around the optional external notifier command (invoked via exec()).
     notifier: UserNotifier,
@@ -486,7 +498,7 @@ impl Session {
                 use_streamable_shell_tool: config.use_experimental_streamable_shell_tool,
                 include_view_image_tool: config.include_view_image_tool,
                 experimental_unified_exec_tool: config.use_experimental_unified_exec_tool,
-                include_agent_tool: true, // Enable agent tool by default
+                include_agent_tool: config.include_agent_tool,
             }),
             user_instructions,
             base_instructions,
@@ -514,6 +526,9 @@ impl Session {
             session_manager: ExecSessionManager::default(),
             unified_exec_manager: UnifiedExecSessionManager::default(),
             agent_registry: Mutex::new(agent_registry),
+            agent_conversations: Arc::new(ConversationManager::new(auth_manager.clone())),
+            prompt_cache: Mutex::new(PromptCache::new()),
+            prompt_scan: Mutex::new(None),
             notifier: notify,
             state: Mutex::new(state),
             rollout: Mutex::new(Some(rollout_recorder)),
@@ -1215,6 +1230,9 @@ async fn submission_loop(
         match sub.op {
             Op::Interrupt => {
                 sess.interrupt_task().await;
+                if let Some(scan) = sess.prompt_scan.lock().await.take() {
+                    scan.cancel();
+                }
             }
             Op::OverrideTurnContext {
                 cwd,
@@ -1274,7 +1292,7 @@ async fn submission_loop(
                     use_streamable_shell_tool: config.use_experimental_streamable_shell_tool,
                     include_view_image_tool: config.include_view_image_tool,
                     experimental_unified_exec_tool: config.use_experimental_unified_exec_tool,
-                    include_agent_tool: true,
+                    include_agent_tool: config.include_agent_tool,
                 });
 
                 let new_turn_context = TurnContext {
@@ -1365,7 +1383,7 @@ async fn submission_loop(
                             include_view_image_tool: config.include_view_image_tool,
                             experimental_unified_exec_tool: config
                                 .use_experimental_unified_exec_tool,
-                            include_agent_tool: true,
+                            include_agent_tool: config.include_agent_tool,
                         }),
                         user_instructions: turn_context.user_instructions.clone(),
                         base_instructions: turn_context.base_instructions.clone(),
@@ -1467,17 +1485,18 @@ async fn submission_loop(
                 let sub_id = sub.id.clone();
 
                 // Get the agent registry and list agents
-                let agents = {
+                let (agents, last_used) = {
                     let agent_registry_guard = sess.agent_registry.lock().await;
-                    agent_registry_guard
-                        .as_ref()
-                        .map(|r| r.list_agent_details())
-                        .unwrap_or_else(Vec::new)
+                    match agent_registry_guard.as_ref() {
+                        Some(r) => (r.list_agent_details(), r.last_used_agent(&turn_context.cwd)),
+                        None => (Vec::new(), None),
+                    }
                 }; // MutexGuard is dropped here
                 let event = Event {
                     id: sub_id,
                     msg: EventMsg::ListAgentsResponse(crate::protocol::ListAgentsResponseEvent {
                         agents,
+                        last_used,
                     }),
                 };
                 sess.send_event(event).await;
@@ -1485,25 +1504,59 @@ async fn submission_loop(
             Op::ListCustomPrompts => {
                 let sub_id = sub.id.clone();
 
-                let custom_prompts: Vec<CustomPrompt> = {
-                    let cwd =
-                        std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
-                    let personal = crate::custom_prompts::default_prompts_dir();
-                    // Exclude built-ins (if any) on discovery. For now, no extra excludes.
-                    let exclude = std::collections::HashSet::new();
-                    crate::custom_prompts::discover_project_and_personal_prompts(
-                        &cwd, &exclude, personal,
-                    )
-                    .await
-                };
+                // Scan in the background so a slow filesystem doesn't hold up other ops;
+                // an interrupt or a newer listing cancels the scan.
+                let cancel = CancellationToken::new();
+                if let Some(previous) = sess.prompt_scan.lock().await.replace(cancel.clone()) {
+                    previous.cancel();
+                }
+                let sess = Arc::clone(&sess);
+                let respect_gitignore = config.prompts_respect_gitignore;
+                tokio::spawn(async move {
+                    let custom_prompts: Vec<CustomPrompt> = {
+                        let cwd = std::env::current_dir()
+                            .unwrap_or_else(|_| std::path::PathBuf::from("."));
+                        let personal = crate::custom_prompts::default_prompts_dir();
+                        // Exclude built-ins (if any) on discovery. For now, no extra excludes.
+                        let exclude = std::collections::HashSet::new();
+                        let extra = crate::custom_prompts::extra_prompt_dirs();
+                        sess.prompt_cache
+                            .lock()
+                            .await
+                            .discover_all(
+                                &cwd,
+                                &exclude,
+                                personal,
+                                &extra,
+                                respect_gitignore,
+                                Some(&cancel),
+                            )
+                            .await
+                    };
+                    // A cancelled scan is incomplete; don't report it as the prompt list.
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+                    // Discovery can't see the registry, so unknown target agents are only
+                    // flagged here.
+                    if let Some(registry) = sess.agent_registry.lock().await.as_ref() {
+                        for prompt in &custom_prompts {
+                            if let Some(agent) = &prompt.agent
+                                && registry.get_agent(agent).is_none()
+                            {
+                                warn!("prompt '{}' targets unknown agent '{agent}'", prompt.name);
+                            }
+                        }
+                    }
 
-                let event = Event {
-                    id: sub_id,
-                    msg: EventMsg::ListCustomPromptsResponse(ListCustomPromptsResponseEvent {
-                        custom_prompts,
-                    }),
-                };
-                sess.send_event(event).await;
+                    let event = Event {
+                        id: sub_id,
+                        msg: EventMsg::ListCustomPromptsResponse(ListCustomPromptsResponseEvent {
+                            custom_prompts,
+                        }),
+                    };
+                    sess.send_event(event).await;
+                });
             }
             Op::Compact => {
                 // Attempt to inject input into current task
@@ -2408,27 +2461,13 @@ async fn try_run_turn(
                 (
                     call.call_id.clone(),
                     call.arguments.clone().unwrap_or_default(),
-                    sub_id.to_string(),
                 )
             })
             .collect();
 
-        // Execute all agents in parallel with proper concurrency control
-        let agent_results = {
-            // Create thread-safe wrappers for concurrent execution
-            // SAFETY: sess and turn_context are guaranteed to outlive this call
-            // as they're borrowed from the enclosing function scope
-            let sess_wrapper = Arc::new(SessionWrapper::new(sess));
-            let context_wrapper = Arc::new(TurnContextWrapper::new(turn_context));
-
-            execute_agents_concurrent_safe(
-                sess_wrapper,
-                context_wrapper,
-                turn_diff_tracker,
-                agent_call_params,
-            )
-            .await
-        };
+        // Execute all agents in parallel
+        let agent_results =
+            execute_agent_calls(sess, turn_context, sub_id, agent_call_params).await;
 
         // Process agent results
         for (i, (_call_id, result)) in agent_results.into_iter().enumerate() {
@@ -2804,73 +2843,6 @@ enum AgentMessage {
     Summary(String),             // Summary of agent's work
 }
 
-// =================================================================================
-// Concurrent Execution Wrappers for Thread Safety
-// =================================================================================
-// These wrappers enable safe sharing of Session and TurnContext across threads
-// during parallel agent execution. They use raw pointers internally but are safe
-// because:
-// 1. The referenced objects are guaranteed to outlive the wrappers (enforced by caller)
-// 2. Session and TurnContext have internal synchronization via Mutex fields
-// 3. The wrappers are only used within a controlled scope where lifetimes are guaranteed
-
-/// Thread-safe wrapper for Session to enable concurrent execution
-struct SessionWrapper {
-    // We use a raw pointer here because:
-    // 1. We need to share &Session across threads but Session isn't Clone
-    // 2. The Session is guaranteed to outlive this wrapper (created in same scope)
-    // 3. Session has internal thread-safety via Mutex fields
-    ptr: *const Session,
-    _phantom: std::marker::PhantomData<Session>,
-}
-
-// SAFETY: Session has internal synchronization via Mutex fields
-// The wrapper ensures the Session outlives all uses
-unsafe impl Send for SessionWrapper {}
-unsafe impl Sync for SessionWrapper {}
-
-impl SessionWrapper {
-    fn new(sess: &Session) -> Self {
-        Self {
-            ptr: sess as *const Session,
-            _phantom: std::marker::PhantomData,
-        }
-    }
-
-    fn get(&self) -> &Session {
-        // SAFETY: The caller guarantees Session outlives this wrapper
-        // This is enforced by the structure of execute_agents_concurrent_safe
-        unsafe { &*self.ptr }
-    }
-}
-
-/// Thread-safe wrapper for TurnContext to enable concurrent execution
-struct TurnContextWrapper {
-    // We use a raw pointer here for the same reasons as SessionWrapper
-    ptr: *const TurnContext,
-    _phantom: std::marker::PhantomData<TurnContext>,
-}
-
-// SAFETY: TurnContext contains only thread-safe types
-// The wrapper ensures the TurnContext outlives all uses
-unsafe impl Send for TurnContextWrapper {}
-unsafe impl Sync for TurnContextWrapper {}
-
-impl TurnContextWrapper {
-    fn new(ctx: &TurnContext) -> Self {
-        Self {
-            ptr: ctx as *const TurnContext,
-            _phantom: std::marker::PhantomData,
-        }
-    }
-
-    fn get(&self) -> &TurnContext {
-        // SAFETY: The caller guarantees TurnContext outlives this wrapper
-        // This is enforced by the structure of execute_agents_concurrent_safe
-        unsafe { &*self.ptr }
-    }
-}
-
 // =================================================================================
 // Agent Execution Helper Functions
 // =================================================================================
@@ -2888,15 +2860,6 @@ fn build_agent_task_message(context: Option<&str>, task: &str) -> String {
     }
 }
 
-/// Generate a unique plan ID for agent execution
-fn generate_agent_plan_id(agent_name: &str, call_id: &str) -> String {
-    format!(
-        "agent-{}-{}",
-        agent_name,
-        call_id.get(..8).unwrap_or(call_id)
-    )
-}
-
 /// Create an error response for agent calls
 #[allow(dead_code)]
 fn create_tool_error_response(item: &ResponseItem, error_msg: &str) -> Option<ResponseInputItem> {
@@ -2939,507 +2902,184 @@ async fn get_agent_registry(sess: &Session) -> Result<Arc<crate::agent::AgentReg
         None => Err("Agent registry not available".to_string()),
     }
 }
-/// Execute multiple agents with true parallel execution
-/// Uses safe wrappers to enable concurrent access to Session and TurnContext
-/// All agents run in parallel using futures::future::join_all
-async fn execute_agents_concurrent_safe(
-    sess_wrapper: Arc<SessionWrapper>,
-    context_wrapper: Arc<TurnContextWrapper>,
-    turn_diff_tracker: &mut TurnDiffTracker,
-    agent_calls: Vec<(String, String, String)>, // (call_id, arguments, sub_id)
+/// Run the turn's agent tool calls concurrently, each through [`AgentExecutor`] in a
+/// conversation of its own started from this turn's settings.
+async fn execute_agent_calls(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: &str,
+    agent_calls: Vec<(String, String)>, // (call_id, arguments)
 ) -> Vec<(String, ResponseInputItem)> {
-    use futures::future::join_all;
-
-    let sess = sess_wrapper.get();
-
-    // Get the agent registry once using helper
     let registry = match get_agent_registry(sess).await {
         Ok(r) => r,
         Err(msg) => {
             return agent_calls
                 .into_iter()
-                .map(|(call_id, _, _)| create_agent_error_response(call_id, &msg))
+                .map(|(call_id, _)| create_agent_error_response(call_id, &msg))
                 .collect();
         }
     };
+    let executor = Arc::new(AgentExecutor::new(
+        Arc::clone(&registry),
+        ConversationBackend::new(
+            Arc::clone(&sess.agent_conversations),
+            agent_base_config(turn_context),
+        ),
+    ));
+    let scheduler = AgentScheduler::new(executor, registry.max_concurrent());
+
+    let registry = &registry;
+    let scheduler = &scheduler;
+    let agent_futures = agent_calls.into_iter().map(|(call_id, arguments)| async move {
+        let args = match parse_agent_args(&arguments) {
+            Ok(a) => a,
+            Err(e) => {
+                return create_agent_error_response(
+                    call_id,
+                    &format!("Failed to parse agent arguments: {e}"),
+                );
+            }
+        };
+        let agent_name = args
+            .agent
+            .unwrap_or_else(|| registry.default_agent_name().to_string());
+        let task_message = build_agent_task_message(args.context.as_deref(), &args.task);
+        let context_suffix = agent_context_suffix(registry, &agent_name, turn_context);
 
-    // Set agent context flag to prevent recursion
-    // Create futures for TRUE PARALLEL agent execution
-    // Each agent runs independently and concurrently
-    let agent_futures: Vec<_> = agent_calls
-        .into_iter()
-        .map(|(call_id, arguments, sub_id)| {
-            let registry_clone = Arc::clone(&registry);
-            let sess_wrapper_clone = Arc::clone(&sess_wrapper);
-            let context_wrapper_clone = Arc::clone(&context_wrapper);
-
-            // Each agent executes in parallel
-            async move {
-                // Parse agent arguments
-                let args = match parse_agent_args(&arguments) {
-                    Ok(a) => a,
-                    Err(e) => {
-                        let (call_id, response) = create_agent_error_response(
-                            call_id.clone(),
-                            &format!("Failed to parse agent arguments: {e}"),
-                        );
-                        return (call_id, response, TurnDiffTracker::new());
-                    }
-                };
-
-                let agent_name = args.agent.unwrap_or_else(|| "general".to_string());
-                let agent_system_prompt = registry_clone.get_system_prompt(&agent_name);
-                let sandbox_override = registry_clone.permissions_policy(&agent_name);
-                let model_override = registry_clone.model_override(&agent_name);
-                let reasoning_override = registry_clone.reasoning_effort_override(&agent_name);
-
-                // Build the agent's task message (what the user is asking)
-                let agent_task_message =
-                    build_agent_task_message(args.context.as_deref(), &args.task);
+        sess.send_event(Event {
+            id: sub_id.to_string(),
+            msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
+                message: format!(
+                    "🤖 Agent '{agent_name}' started: {task_message}{context_suffix}"
+                ),
+            }),
+        })
+        .await;
 
-                let plan_item_id = generate_agent_plan_id(&agent_name, &call_id);
+        let start_time = std::time::Instant::now();
+        // Agent conversations never offer the agent tool, so this call is never nested.
+        let result = scheduler
+            .run(
+                &agent_name,
+                task_message,
+                &HashMap::new(),
+                CancellationToken::new(),
+            )
+            .await;
+        let duration = start_time.elapsed();
+        info!(
+            "Agent '{agent_name}' finished in {}ms (call_id: {call_id})",
+            duration.as_millis()
+        );
 
-                // Execute the agent with non-streaming approach
-                let (result, diff_tracker) = execute_agent_non_streaming(
-                    sess_wrapper_clone.get(),
-                    context_wrapper_clone.get(),
-                    AgentExecutionParams {
-                        sub_id: sub_id.clone(),
-                        agent_name: agent_name.clone(),
-                        task_message: agent_task_message,
-                        agent_system_prompt: agent_system_prompt.clone(),
-                        sandbox_override,
-                        model_override,
-                        reasoning_override,
-                        call_id: call_id.clone(),
-                        _plan_item_id: Some(plan_item_id),
+        let (status_msg, output) = match result {
+            Ok(agent_response) if agent_response.trim().is_empty() => (
+                format!("❌ Agent '{agent_name}' failed: No response generated{context_suffix}"),
+                FunctionCallOutputPayload {
+                    content: agent_response,
+                    success: Some(true),
+                },
+            ),
+            Ok(agent_response) => {
+                let preview: String = agent_response.chars().take(100).collect();
+                let ellipsis = if preview.len() < agent_response.len() {
+                    "..."
+                } else {
+                    ""
+                };
+                (
+                    format!(
+                        "✅ Agent '{agent_name}' completed in {:.2}s: {}{ellipsis}{context_suffix}",
+                        duration.as_secs_f64(),
+                        preview.trim().replace('\n', " "),
+                    ),
+                    FunctionCallOutputPayload {
+                        content: agent_response,
+                        success: Some(true),
                     },
                 )
-                .await;
-
-                // Convert result to ResponseInputItem
-                let response = match result {
-                    Ok(agent_response) => ResponseInputItem::FunctionCallOutput {
-                        call_id: call_id.clone(),
-                        output: FunctionCallOutputPayload {
-                            content: agent_response,
-                            success: Some(true),
-                        },
-                    },
-                    Err(e) => ResponseInputItem::FunctionCallOutput {
-                        call_id: call_id.clone(),
-                        output: FunctionCallOutputPayload {
-                            content: format!("Agent execution failed: {e}"),
-                            success: Some(false),
-                        },
+            }
+            Err(e) => {
+                error!("Agent '{agent_name}' failed: {e:#}");
+                (
+                    format!("❌ Agent '{agent_name}' failed: {e}{context_suffix}"),
+                    FunctionCallOutputPayload {
+                        content: format!("Agent execution failed: {e}"),
+                        success: Some(false),
                     },
-                };
-
-                (call_id, response, diff_tracker)
+                )
             }
+        };
+        sess.send_event(Event {
+            id: sub_id.to_string(),
+            msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
+                message: status_msg,
+            }),
         })
-        .collect();
-
-    // Execute all agents concurrently - TRUE PARALLELISM
-    // All agents run at the same time, not sequentially
-    let agent_results = join_all(agent_futures).await;
-
-    // Merge all diff trackers from agents and return results
-    let mut results = Vec::new();
-    for (call_id, response, agent_diff_tracker) in agent_results {
-        // Merge the agent's diff tracker into the main one
-        turn_diff_tracker.merge(agent_diff_tracker);
-        results.push((call_id, response));
-    }
+        .await;
 
-    results
-}
-struct AgentExecutionParams {
-    sub_id: String,
-    agent_name: String,
-    task_message: String,
-    agent_system_prompt: String,
-    sandbox_override: Option<SandboxPolicy>,
-    model_override: Option<String>,
-    reasoning_override: Option<ReasoningEffortConfig>,
-    call_id: String,
-    _plan_item_id: Option<String>,
-}
+        (
+            call_id.clone(),
+            ResponseInputItem::FunctionCallOutput { call_id, output },
+        )
+    });
 
-/// Execute an agent in non-streaming mode to prevent streaming contamination
-async fn execute_agent_non_streaming(
-    sess: &Session,
-    parent_context: &TurnContext,
-    params: AgentExecutionParams,
-) -> (Result<String, String>, TurnDiffTracker) {
-    use std::time::Instant;
-    let start_time = Instant::now();
-
-    info!(
-        "Executing agent '{}' with non-streaming mode (parallel)",
-        params.agent_name
-    );
-    // Log agent start and notify UI
-    info!(
-        "Agent '{}' starting task (call_id: {}) - NO-STREAMING EXECUTION",
-        params.agent_name, params.call_id
-    );
+    // Agents run in parallel, at most `max_concurrent` at a time.
+    futures::future::join_all(agent_futures).await
+}
 
-    let parent_client = parent_context.client.clone();
-    let mut model_note: Option<String> = None;
-    let mut effort_note: Option<String> = None;
-    let need_new_client = params.model_override.is_some() || params.reasoning_override.is_some();
+/// Config agent conversations start from: the session's config with this turn's model,
+/// reasoning effort, instructions, sandbox, environment and working directory.
+fn agent_base_config(turn_context: &TurnContext) -> Config {
+    let mut config = (*turn_context.client.config()).clone();
+    config.model_reasoning_effort = turn_context.client.get_reasoning_effort();
+    config.base_instructions = turn_context.base_instructions.clone();
+    config.user_instructions = turn_context.user_instructions.clone();
+    config.sandbox_policy = turn_context.sandbox_policy.clone();
+    config.shell_environment_policy = turn_context.shell_environment_policy.clone();
+    config.cwd = turn_context.cwd.clone();
+    config
+}
 
-    let client = if need_new_client {
-        match build_agent_client(
-            sess,
-            parent_context,
-            params.model_override.as_deref(),
-            params.reasoning_override,
-        ) {
-            Ok((client, model_slug, effort)) => {
-                model_note = model_slug.map(|m| format!("model: {m}"));
-                effort_note = effort.map(|e| format!("effort: {}", stringify_effort(e)));
-                client
+/// ` [model: …] [effort: …] [sandbox: …]` notes for the overrides `agent_name` runs
+/// with (` [runs: …]` instead of the model notes for command agents), or an empty string
+/// when it has none.
+fn agent_context_suffix(
+    registry: &crate::agent::AgentRegistry,
+    agent_name: &str,
+    turn_context: &TurnContext,
+) -> String {
+    let mut context_notes: Vec<String> = Vec::new();
+    match registry.get_agent(agent_name).map(|agent| agent.kind()) {
+        Some(AgentKind::Command { run }) => context_notes.push(format!("runs: {run}")),
+        Some(AgentKind::Llm) | None => {
+            let session_model = turn_context.client.get_model();
+            let model = registry.resolved_model(agent_name, &session_model);
+            if model != session_model {
+                context_notes.push(format!("model: {model}"));
             }
-            Err(err) => {
-                warn!(
-                    "Agent '{}' override failed: {err}; falling back to session model",
-                    params.agent_name
-                );
-                sess.send_event(Event {
-                    id: params.sub_id.clone(),
-                    msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
-                        message: format!(
-                            "⚠️ Agent '{}' overrides failed: {err}. Using session defaults instead.",
-                            params.agent_name
-                        ),
-                    }),
-                })
-                .await;
-                parent_client.clone()
+            let session_effort = turn_context.client.get_reasoning_effort();
+            if let Some(effort) = session_effort
+                .or(registry.reasoning_effort_override(agent_name))
+                .map(|default| registry.clamped_reasoning_effort(agent_name, default))
+                .filter(|effort| Some(*effort) != session_effort)
+            {
+                context_notes.push(format!("effort: {}", stringify_effort(effort)));
             }
         }
-    } else {
-        parent_client.clone()
-    };
-
-    // Build agent's custom instructions: agent system prompt + AGENTS.md
-    let mut agent_custom_instructions = String::new();
-
-    // First append the agent's system prompt
-    if !params.agent_system_prompt.is_empty() {
-        agent_custom_instructions.push_str(&params.agent_system_prompt);
-    }
-
-    // Then append AGENTS.md if present
-    if let Some(user_inst) = parent_context.user_instructions.as_deref()
-        && !user_inst.is_empty()
-    {
-        if !agent_custom_instructions.is_empty() {
-            agent_custom_instructions.push_str("\n\n");
-        }
-        agent_custom_instructions.push_str(user_inst);
-    }
-
-    // Create a modified turn context for the agent
-    // base_instructions: Keep parent's base instructions (default Codex instructions)
-    // user_instructions: Agent system prompt + AGENTS.md
-    // IMPORTANT: Disable agent tool for agents to prevent recursion
-    let sandbox_policy = params
-        .sandbox_override
-        .clone()
-        .unwrap_or_else(|| parent_context.sandbox_policy.clone());
-
-    let mut context_notes: Vec<String> = Vec::new();
-    if let Some(note) = model_note.clone() {
-        context_notes.push(note);
     }
-    if let Some(note) = effort_note.clone() {
-        context_notes.push(note);
-    }
-    if params.sandbox_override.is_some() {
+    if let Some(policy) = registry.permissions_policy(agent_name) {
+        let sandbox_policy =
+            crate::agent::restrict_sandbox_policy(&policy, &turn_context.sandbox_policy);
         context_notes.push(format!(
             "sandbox: {}",
             sandbox_policy_label(&sandbox_policy)
         ));
     }
-    let context_suffix = if context_notes.is_empty() {
-        String::new()
-    } else {
-        let decorated = context_notes
-            .into_iter()
-            .map(|n| format!("[{n}]"))
-            .collect::<Vec<_>>()
-            .join(" ");
-        format!(" {decorated}")
-    };
-
-    // Send agent start event to UI for status display (after resolving sandbox override).
-    sess.send_event(Event {
-        id: params.sub_id.clone(),
-        msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
-            message: format!(
-                "🤖 Agent '{}' started: {}{}",
-                params.agent_name, params.task_message, context_suffix
-            ),
-        }),
-    })
-    .await;
-
-    let mut agent_tools_config = parent_context.tools_config.clone();
-    agent_tools_config.include_agent_tool = false; // Prevent agents from spawning other agents
-
-    let agent_turn_context = TurnContext {
-        client,
-        tools_config: agent_tools_config,
-        base_instructions: parent_context.base_instructions.clone(), // Keep default base instructions
-        user_instructions: if agent_custom_instructions.is_empty() {
-            None
-        } else {
-            Some(agent_custom_instructions)
-        }, // Agent prompt + AGENTS.md
-        approval_policy: parent_context.approval_policy,
-        sandbox_policy,
-        shell_environment_policy: parent_context.shell_environment_policy.clone(),
-        cwd: parent_context.cwd.clone(),
-        is_review_mode: true,
-        final_output_json_schema: parent_context.final_output_json_schema.clone(),
-    };
-
-    let task_message = if params.agent_system_prompt.trim().is_empty() {
-        params.task_message.clone()
-    } else {
-        format!(
-            "Agent context: {}\n\n{}",
-            params.agent_system_prompt.trim(),
-            params.task_message
-        )
-    };
-
-    let agent_messages = vec![ResponseItem::Message {
-        id: None,
-        role: "user".to_string(),
-        content: vec![ContentItem::InputText { text: task_message }],
-    }];
-
-    // Execute agent without streaming (using review mode suppression)
-    let mut review_history = sess.build_initial_context(&agent_turn_context);
-    review_history.extend(agent_messages.clone());
-
-    let mut assistant_chunks: Vec<String> = Vec::new();
-    let mut fallback_response: Option<String> = None;
-    let mut turn_diff_tracker = TurnDiffTracker::new();
-
-    fn collect_output_text(items: &[ContentItem]) -> Option<String> {
-        let mut combined = String::new();
-        for item in items {
-            if let ContentItem::OutputText { text } = item {
-                if !combined.is_empty() {
-                    combined.push('\n');
-                }
-                combined.push_str(text);
-            }
-        }
-        if combined.trim().is_empty() {
-            None
-        } else {
-            Some(combined)
-        }
-    }
-
-    fn collect_response_text(response: &ResponseInputItem) -> Option<String> {
-        match response {
-            ResponseInputItem::FunctionCallOutput { output, .. } => {
-                if output.content.trim().is_empty() {
-                    None
-                } else {
-                    Some(output.content.clone())
-                }
-            }
-            ResponseInputItem::CustomToolCallOutput { output, .. } => {
-                if output.trim().is_empty() {
-                    None
-                } else {
-                    Some(output.clone())
-                }
-            }
-            ResponseInputItem::McpToolCallOutput { result, .. } => Some(match result {
-                Ok(call_tool_result) => serde_json::to_string(call_tool_result)
-                    .unwrap_or_else(|_| "unserializable MCP result".to_string()),
-                Err(err) => err.clone(),
-            }),
-            ResponseInputItem::Message { content, .. } => collect_output_text(content),
-        }
-    }
-
-    loop {
-        let turn_input = review_history.clone();
-        let run_result = run_turn(
-            sess,
-            &agent_turn_context,
-            &mut turn_diff_tracker,
-            params.sub_id.clone(),
-            turn_input,
-        )
-        .await;
-
-        match run_result {
-            Ok(turn_output) => {
-                let mut responses_pending = false;
-
-                for processed_item in turn_output.processed_items {
-                    let ProcessedResponseItem { item, response } = processed_item;
-
-                    if let ResponseItem::Message { role, content, .. } = &item {
-                        if let Some(text) = collect_output_text(content) {
-                            fallback_response = Some(text.clone());
-                            if response.is_none() && role == "assistant" {
-                                assistant_chunks.push(text);
-                            }
-                        }
-                    }
-
-                    review_history.push(item.clone());
-
-                    if let Some(response) = response {
-                        responses_pending = true;
-                        if let Some(text) = collect_response_text(&response) {
-                            fallback_response = Some(text.clone());
-                        }
-                        let response_item: ResponseItem = response.clone().into();
-                        review_history.push(response_item);
-                    }
-                }
-
-                if !assistant_chunks.is_empty() {
-                    break;
-                }
-
-                if !responses_pending {
-                    break;
-                }
-
-                continue;
-            }
-            Err(e) => {
-                error!("Agent '{}' turn failed: {e:#}", params.agent_name);
-                sess.notify_stream_error(
-                    &params.sub_id.clone(),
-                    format!("Error during agent execution: {e}"),
-                )
-                .await;
-                return (
-                    Err(format!("Error during agent execution: {e}")),
-                    turn_diff_tracker,
-                );
-            }
-        }
-    }
-
-    let mut agent_response = if assistant_chunks.is_empty() {
-        fallback_response.unwrap_or_default()
-    } else {
-        assistant_chunks.join("\n\n")
-    };
-    if !agent_response.is_empty() && !agent_response.ends_with('\n') {
-        agent_response.push('\n');
-    }
-
-    let duration = start_time.elapsed();
-    info!(
-        "Agent '{}' completed in {}ms (call_id: {}) - NO STREAMING",
-        params.agent_name,
-        duration.as_millis(),
-        params.call_id
-    );
-
-    // Send completion status message
-    let status_msg = if agent_response.is_empty() {
-        format!(
-            "❌ Agent '{}' failed: No response generated{}",
-            params.agent_name, context_suffix
-        )
-    } else {
-        let preview = if agent_response.len() > 100 {
-            format!("{}...", &agent_response[..100])
-        } else {
-            agent_response.clone()
-        };
-        format!(
-            "✅ Agent '{}' completed in {:.2}s: {}{}",
-            params.agent_name,
-            duration.as_secs_f64(),
-            preview.trim().replace('\n', " "),
-            context_suffix
-        )
-    };
-
-    sess.send_event(Event {
-        id: params.sub_id.clone(),
-        msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
-            message: status_msg,
-        }),
-    })
-    .await;
-
-    (Ok(agent_response), turn_diff_tracker)
-}
-
-fn build_agent_client(
-    sess: &Session,
-    parent_context: &TurnContext,
-    model_override: Option<&str>,
-    effort_override: Option<ReasoningEffortConfig>,
-) -> Result<(ModelClient, Option<String>, Option<ReasoningEffortConfig>), String> {
-    let parent_client = parent_context.client.clone();
-    let parent_config = parent_client.config();
-    let mut config = (*parent_config).clone();
-
-    let applied_model = model_override.and_then(|slug| {
-        let trimmed = slug.trim();
-        if trimmed.is_empty() {
-            None
-        } else {
-            config.model = trimmed.to_string();
-            let model_family = find_family_for_model(trimmed)
-                .unwrap_or_else(|| derive_default_model_family(trimmed));
-            config.model_family = model_family.clone();
-            if let Some(info) = get_model_info(&model_family) {
-                config.model_context_window = Some(info.context_window);
-                config.model_max_output_tokens = Some(info.max_output_tokens);
-                config.model_auto_compact_token_limit = info.auto_compact_token_limit;
-            }
-            Some(trimmed.to_string())
-        }
-    });
-
-    if let Some(effort) = effort_override {
-        config.model_reasoning_effort = Some(effort);
-    }
-
-    let provider_id = config.model_provider_id.clone();
-    let provider = config
-        .model_providers
-        .get(&provider_id)
-        .cloned()
-        .ok_or_else(|| format!("model provider `{provider_id}` not found"))?;
-    config.model_provider = provider.clone();
-
-    let arc_config = Arc::new(config);
-    let effort = effort_override.or_else(|| parent_client.get_reasoning_effort());
-    let summary = parent_client.get_reasoning_summary();
-
-    let client = ModelClient::new(
-        arc_config,
-        parent_client.get_auth_manager(),
-        provider,
-        effort,
-        summary,
-        sess.conversation_id,
-    );
-
-    Ok((client, applied_model, effort_override))
+    context_notes
+        .into_iter()
+        .map(|n| format!(" [{n}]"))
+        .collect()
 }
 
 fn stringify_effort(effort: ReasoningEffortConfig) -> &'static str {
@@ -3682,6 +3322,16 @@ async fn handle_container_exec_with_params(
         MaybeApplyPatchVerified::NotApplyPatch => None,
     };
 
+    if apply_patch_exec.is_none()
+        && let Some(restrictions) = &turn_context.client.config().command_restrictions
+        && !restrictions.allows(&params.command)
+    {
+        return Err(FunctionCallError::RespondToModel(format!(
+            "command not allowed for this agent: {}",
+            params.command.join(" ")
+        )));
+    }
+
     let (params, safety, command_for_display) = match &apply_patch_exec {
         Some(ApplyPatchExec {
             action: ApplyPatchAction { patch, cwd, .. },
@@ -4220,6 +3870,7 @@ pub(crate) use tests::make_session_and_context;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::CodexAuth;
     use crate::config::ConfigOverrides;
     use crate::config::ConfigToml;
     use crate::protocol::CompactedItem;
@@ -4276,6 +3927,96 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn agent_conversations_start_from_the_turn_settings() {
+        let (_session, mut turn_context) = make_session_and_context();
+        turn_context.cwd = PathBuf::from("/tmp/agent-turn");
+        turn_context.sandbox_policy = SandboxPolicy::ReadOnly;
+        turn_context.user_instructions = Some("Follow AGENTS.md".to_string());
+
+        let config = agent_base_config(&turn_context);
+
+        assert_eq!(config.cwd, PathBuf::from("/tmp/agent-turn"));
+        assert_eq!(config.sandbox_policy, SandboxPolicy::ReadOnly);
+        assert_eq!(
+            config.user_instructions.as_deref(),
+            Some("Follow AGENTS.md")
+        );
+        assert_eq!(config.model, turn_context.client.get_model());
+    }
+
+    #[test]
+    fn command_agents_are_announced_with_their_command() {
+        let (_session, turn_context) = make_session_and_context();
+        let registry = crate::agent::AgentRegistry::from_agents(HashMap::from([
+            (
+                "linter".to_string(),
+                crate::agent::AgentConfig {
+                    run: Some("cargo clippy".to_string()),
+                    model: Some("gpt-5".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "reviewer".to_string(),
+                crate::agent::AgentConfig {
+                    prompt: Some("You review code.".to_string()),
+                    model: Some("gpt-5".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]));
+
+        assert_eq!(
+            agent_context_suffix(&registry, "linter", &turn_context),
+            " [runs: cargo clippy]"
+        );
+        assert_eq!(
+            agent_context_suffix(&registry, "reviewer", &turn_context),
+            " [model: gpt-5]"
+        );
+    }
+
+    #[tokio::test]
+    async fn agent_calls_run_through_the_agent_executor() {
+        let (session, turn_context) = make_session_and_context();
+        *session.agent_registry.lock().await = Some(Arc::new(
+            crate::agent::AgentRegistry::from_agents(HashMap::new()),
+        ));
+
+        let results = execute_agent_calls(
+            &session,
+            &turn_context,
+            "sub",
+            vec![
+                (
+                    "call-1".to_string(),
+                    r#"{"task":"review","agent":"ghost"}"#.to_string(),
+                ),
+                ("call-2".to_string(), "not json".to_string()),
+            ],
+        )
+        .await;
+
+        let outputs: Vec<(String, String, Option<bool>)> = results
+            .into_iter()
+            .map(|(call_id, response)| match response {
+                ResponseInputItem::FunctionCallOutput { output, .. } => {
+                    (call_id, output.content, output.success)
+                }
+                other => panic!("unexpected response: {other:?}"),
+            })
+            .collect();
+        assert_eq!(outputs[0].0, "call-1");
+        assert_eq!(
+            outputs[0].1,
+            "Agent execution failed: unsupported operation: unknown agent 'ghost'"
+        );
+        assert_eq!(outputs[0].2, Some(false));
+        assert_eq!(outputs[1].0, "call-2");
+        assert!(outputs[1].1.starts_with("Failed to parse agent arguments"));
+    }
+
     #[test]
     fn prefers_structured_content_when_present() {
         let ctr = CallToolResult {
@@ -4506,6 +4247,11 @@ mod tests {
             session_manager: ExecSessionManager::default(),
             unified_exec_manager: UnifiedExecSessionManager::default(),
             agent_registry: Mutex::new(None),
+            agent_conversations: Arc::new(ConversationManager::with_auth(CodexAuth::from_api_key(
+                "Test API Key",
+            ))),
+            prompt_cache: Mutex::new(PromptCache::new()),
+            prompt_scan: Mutex::new(None),
             notifier: UserNotifier::default(),
             rollout: Mutex::new(None),
             state: Mutex::new(State {
@@ -4719,4 +4465,64 @@ mod tests {
         pretty_assertions::assert_eq!(exec_output.metadata, ResponseExecMetadata { exit_code: 0 });
         assert!(exec_output.output.contains("hi"));
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn agent_command_restrictions_are_enforced_on_exec() {
+        use crate::agent::CommandRestrictions;
+        use crate::exec::ExecParams;
+
+        let (session, mut turn_context) = make_session_and_context();
+        turn_context.sandbox_policy = SandboxPolicy::DangerFullAccess;
+        let mut config = (*turn_context.client.config()).clone();
+        config.command_restrictions = Some(CommandRestrictions {
+            allowed: None,
+            denied: Some(vec!["rm".to_string()]),
+        });
+        turn_context.client = ModelClient::new(
+            Arc::new(config.clone()),
+            None,
+            config.model_provider.clone(),
+            config.model_reasoning_effort,
+            config.model_reasoning_summary,
+            session.conversation_id,
+        );
+        let exec = |script: &str| ExecParams {
+            command: vec!["bash".to_string(), "-lc".to_string(), script.to_string()],
+            cwd: turn_context.cwd.clone(),
+            timeout_ms: Some(10_000),
+            env: HashMap::new(),
+            with_escalated_permissions: None,
+            justification: None,
+        };
+        let mut turn_diff_tracker = TurnDiffTracker::new();
+
+        let denied = handle_container_exec_with_params(
+            exec("echo hi && rm -rf missing-dir"),
+            &session,
+            &turn_context,
+            &mut turn_diff_tracker,
+            "test-sub".to_string(),
+            "test-call".to_string(),
+        )
+        .await;
+        let Err(FunctionCallError::RespondToModel(output)) = denied else {
+            panic!("expected the command to be refused");
+        };
+        assert_eq!(
+            output,
+            "command not allowed for this agent: bash -lc echo hi && rm -rf missing-dir"
+        );
+
+        let allowed = handle_container_exec_with_params(
+            exec("echo hi"),
+            &session,
+            &turn_context,
+            &mut turn_diff_tracker,
+            "test-sub".to_string(),
+            "test-call-2".to_string(),
+        )
+        .await;
+        assert!(allowed.expect("expected Ok result").contains("hi"));
+    }
 }
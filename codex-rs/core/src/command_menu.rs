@@ -0,0 +1,183 @@
+//! One list of agents and custom prompts for a unified command menu.
+//!
+//! [`merge_menu_entries`] sorts both kinds together by name. When a prompt and an agent
+//! share a name, the agent is listed and the prompt is reported in
+//! [`CommandMenu::shadowed_prompts`] so frontends can point out the clash.
+
+use std::path::PathBuf;
+
+use codex_protocol::custom_prompts::CustomPrompt;
+
+use crate::protocol::AgentInfo;
+
+/// What a menu needs to list a custom prompt; unlike [`CustomPrompt`] it leaves out the
+/// prompt's content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptSummary {
+    pub name: String,
+    pub path: PathBuf,
+    /// The front-matter `description`, if any
+    pub description: Option<String>,
+    pub argument_hint: Option<String>,
+}
+
+impl From<&CustomPrompt> for PromptSummary {
+    fn from(prompt: &CustomPrompt) -> Self {
+        Self {
+            name: prompt.name.clone(),
+            path: prompt.path.clone(),
+            description: prompt
+                .meta
+                .as_ref()
+                .and_then(|meta| meta.description.clone()),
+            argument_hint: prompt.argument_hint.clone(),
+        }
+    }
+}
+
+/// A row of the command menu.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuEntry {
+    Agent(AgentInfo),
+    Prompt(PromptSummary),
+}
+
+impl MenuEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            MenuEntry::Agent(agent) => &agent.name,
+            MenuEntry::Prompt(prompt) => &prompt.name,
+        }
+    }
+
+    /// Marker shown next to the entry to tell the two kinds apart.
+    pub fn kind_label(&self) -> &'static str {
+        match self {
+            MenuEntry::Agent(_) => "agent",
+            MenuEntry::Prompt(_) => "prompt",
+        }
+    }
+}
+
+/// Agents and prompts merged by [`merge_menu_entries`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandMenu {
+    /// Entries sorted by name, ignoring case
+    pub entries: Vec<MenuEntry>,
+    /// Prompts left out because an agent has the same name (ignoring case), by name
+    pub shadowed_prompts: Vec<PromptSummary>,
+}
+
+/// Merge `agents` and `prompts` into one list sorted by name (ignoring case). A prompt
+/// named like an agent is left out in favor of the agent and reported as shadowed.
+pub fn merge_menu_entries(agents: &[AgentInfo], prompts: &[CustomPrompt]) -> CommandMenu {
+    let mut menu = CommandMenu {
+        entries: agents.iter().cloned().map(MenuEntry::Agent).collect(),
+        shadowed_prompts: Vec::new(),
+    };
+    for prompt in prompts.iter().map(PromptSummary::from) {
+        if agents
+            .iter()
+            .any(|agent| agent.name.eq_ignore_ascii_case(&prompt.name))
+        {
+            tracing::debug!(
+                "Prompt '{}' has the same name as an agent; listing the agent",
+                prompt.name
+            );
+            menu.shadowed_prompts.push(prompt);
+        } else {
+            menu.entries.push(MenuEntry::Prompt(prompt));
+        }
+    }
+    menu.entries.sort_by(|a, b| {
+        a.name()
+            .to_lowercase()
+            .cmp(&b.name().to_lowercase())
+            .then_with(|| a.name().cmp(b.name()))
+    });
+    menu.shadowed_prompts.sort_by(|a, b| a.name.cmp(&b.name));
+    menu
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::AgentSource;
+    use codex_protocol::custom_prompts::CustomPromptMeta;
+    use pretty_assertions::assert_eq;
+
+    fn agent(name: &str) -> AgentInfo {
+        AgentInfo {
+            name: name.to_string(),
+            source: AgentSource::Project,
+            ..Default::default()
+        }
+    }
+
+    fn prompt(name: &str) -> CustomPrompt {
+        CustomPrompt {
+            name: name.to_string(),
+            path: format!("/tmp/{name}.md").into(),
+            content: format!("content of {name}"),
+            meta: Some(CustomPromptMeta {
+                description: Some(format!("about {name}")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn labels(menu: &CommandMenu) -> Vec<String> {
+        menu.entries
+            .iter()
+            .map(|entry| format!("{} ({})", entry.name(), entry.kind_label()))
+            .collect()
+    }
+
+    #[test]
+    fn agents_and_prompts_are_sorted_together_by_name() {
+        let menu = merge_menu_entries(
+            &[agent("reviewer"), agent("Deployer")],
+            &[prompt("summarize"), prompt("changelog")],
+        );
+
+        assert_eq!(
+            labels(&menu),
+            vec![
+                "changelog (prompt)",
+                "Deployer (agent)",
+                "reviewer (agent)",
+                "summarize (prompt)",
+            ]
+        );
+        assert_eq!(
+            menu.entries[0],
+            MenuEntry::Prompt(PromptSummary {
+                name: "changelog".to_string(),
+                path: "/tmp/changelog.md".into(),
+                description: Some("about changelog".to_string()),
+                argument_hint: None,
+            })
+        );
+        assert!(menu.shadowed_prompts.is_empty());
+    }
+
+    #[test]
+    fn agent_wins_a_name_clash_and_the_prompt_is_reported() {
+        let menu = merge_menu_entries(
+            &[agent("reviewer")],
+            &[prompt("Reviewer"), prompt("summarize")],
+        );
+
+        assert_eq!(
+            labels(&menu),
+            vec!["reviewer (agent)", "summarize (prompt)"]
+        );
+        let shadowed: Vec<&str> = menu
+            .shadowed_prompts
+            .iter()
+            .map(|prompt| prompt.name.as_str())
+            .collect();
+        assert_eq!(shadowed, vec!["Reviewer"]);
+    }
+}
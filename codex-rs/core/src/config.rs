@@ -1,7 +1,10 @@
+use crate::agent::CommandRestrictions;
 use crate::config_profile::ConfigProfile;
+use crate::config_types::AgentPopupSettings;
 use crate::config_types::History;
 use crate::config_types::McpServerConfig;
 use crate::config_types::Notifications;
+use crate::config_types::Prompts;
 use crate::config_types::ReasoningSummaryFormat;
 use crate::config_types::SandboxWorkspaceWrite;
 use crate::config_types::ShellEnvironmentPolicy;
@@ -122,6 +125,12 @@ pub struct Config {
     /// and turn completions when not focused.
     pub tui_notifications: Notifications,
 
+    /// Settings for the TUI's `@agent` suggestion popup.
+    pub tui_agent_popup: AgentPopupSettings,
+
+    /// Skip project prompt files ignored by `.gitignore` when listing custom prompts.
+    pub prompts_respect_gitignore: bool,
+
     /// The directory that should be treated as the current working directory
     /// for the session. All relative paths inside the business-logic layer are
     /// resolved against this path.
@@ -187,6 +196,19 @@ pub struct Config {
     /// Include the `view_image` tool that lets the agent attach a local image path to context.
     pub include_view_image_tool: bool,
 
+    /// Include the `agent` tool that lets the model delegate tasks to sub-agents.
+    /// Conversations that run an agent turn this off so agents cannot spawn agents.
+    pub include_agent_tool: bool,
+
+    /// Shell command prefixes this session may and may not run. Set for the conversations
+    /// of agents that declare `allowed_commands` or `denied_commands`.
+    pub command_restrictions: Option<CommandRestrictions>,
+
+    /// Cap on the tokens each model response may use, sent with every request; `None`
+    /// leaves it to the model. Set for the conversations of agents that declare
+    /// `max_output_tokens`.
+    pub max_output_tokens: Option<u64>,
+
     /// The active profile name used to derive this `Config` (if any).
     pub active_profile: Option<String>,
 
@@ -666,6 +688,9 @@ pub struct ConfigToml {
     /// Collection of settings that are specific to the TUI.
     pub tui: Option<Tui>,
 
+    /// Settings for custom prompt discovery.
+    pub prompts: Option<Prompts>,
+
     /// When set to `true`, `AgentReasoning` events will be hidden from the
     /// UI/output. Defaults to `false`.
     pub hide_agent_reasoning: Option<bool>,
@@ -1044,6 +1069,9 @@ impl Config {
                 .experimental_use_unified_exec_tool
                 .unwrap_or(false),
             include_view_image_tool,
+            include_agent_tool: true,
+            command_restrictions: None,
+            max_output_tokens: None,
             active_profile: active_profile_name,
             disable_paste_burst: cfg.disable_paste_burst.unwrap_or(false),
             tui_notifications: cfg
@@ -1051,6 +1079,12 @@ impl Config {
                 .as_ref()
                 .map(|t| t.notifications.clone())
                 .unwrap_or_default(),
+            tui_agent_popup: cfg
+                .tui
+                .as_ref()
+                .map(|t| t.agent_popup.clone())
+                .unwrap_or_default(),
+            prompts_respect_gitignore: cfg.prompts.as_ref().is_some_and(|p| p.respect_gitignore),
         };
         Ok(config)
     }
@@ -1212,6 +1246,47 @@ persistence = "none"
         let tui = parsed.tui.expect("config should include tui section");
 
         assert_eq!(tui.notifications, Notifications::Enabled(false));
+        assert_eq!(tui.agent_popup, AgentPopupSettings::default());
+    }
+
+    #[test]
+    fn tui_agent_popup_settings_are_read_from_config() {
+        let cfg = r#"
+[tui.agent_popup]
+prefix = "ag"
+wrap = false
+max_rows = 12
+show_index = true
+"#;
+
+        let parsed = toml::from_str::<ConfigToml>(cfg).expect("agent popup settings should parse");
+        let tui = parsed.tui.expect("config should include tui section");
+
+        assert_eq!(
+            tui.agent_popup,
+            AgentPopupSettings {
+                prefix: "ag".to_string(),
+                wrap: false,
+                max_rows: Some(12),
+                show_index: true,
+            }
+        );
+    }
+
+    #[test]
+    fn prompts_respect_gitignore_is_read_from_config() -> std::io::Result<()> {
+        let codex_home = TempDir::new()?;
+        let load = |cfg: &str| {
+            Config::load_from_base_config_with_overrides(
+                toml::from_str::<ConfigToml>(cfg).expect("prompts settings should parse"),
+                ConfigOverrides::default(),
+                codex_home.path().to_path_buf(),
+            )
+        };
+
+        assert!(!load("")?.prompts_respect_gitignore);
+        assert!(load("[prompts]\nrespect_gitignore = true\n")?.prompts_respect_gitignore);
+        Ok(())
     }
 
     #[test]
@@ -1652,9 +1727,14 @@ model_verbosity = "high"
                 use_experimental_streamable_shell_tool: false,
                 use_experimental_unified_exec_tool: false,
                 include_view_image_tool: true,
+                include_agent_tool: true,
+                command_restrictions: None,
+                max_output_tokens: None,
                 active_profile: Some("o3".to_string()),
                 disable_paste_burst: false,
                 tui_notifications: Default::default(),
+                tui_agent_popup: Default::default(),
+                prompts_respect_gitignore: false,
             },
             o3_profile_config
         );
@@ -1710,9 +1790,14 @@ model_verbosity = "high"
             use_experimental_streamable_shell_tool: false,
             use_experimental_unified_exec_tool: false,
             include_view_image_tool: true,
+            include_agent_tool: true,
+            command_restrictions: None,
+            max_output_tokens: None,
             active_profile: Some("gpt3".to_string()),
             disable_paste_burst: false,
             tui_notifications: Default::default(),
+            tui_agent_popup: Default::default(),
+            prompts_respect_gitignore: false,
         };
 
         assert_eq!(expected_gpt3_profile_config, gpt3_profile_config);
@@ -1783,9 +1868,14 @@ model_verbosity = "high"
             use_experimental_streamable_shell_tool: false,
             use_experimental_unified_exec_tool: false,
             include_view_image_tool: true,
+            include_agent_tool: true,
+            command_restrictions: None,
+            max_output_tokens: None,
             active_profile: Some("zdr".to_string()),
             disable_paste_burst: false,
             tui_notifications: Default::default(),
+            tui_agent_popup: Default::default(),
+            prompts_respect_gitignore: false,
         };
 
         assert_eq!(expected_zdr_profile_config, zdr_profile_config);
@@ -1842,9 +1932,14 @@ model_verbosity = "high"
             use_experimental_streamable_shell_tool: false,
             use_experimental_unified_exec_tool: false,
             include_view_image_tool: true,
+            include_agent_tool: true,
+            command_restrictions: None,
+            max_output_tokens: None,
             active_profile: Some("gpt5".to_string()),
             disable_paste_burst: false,
             tui_notifications: Default::default(),
+            tui_agent_popup: Default::default(),
+            prompts_respect_gitignore: false,
         };
 
         assert_eq!(expected_gpt5_profile_config, gpt5_profile_config);
@@ -168,6 +168,14 @@ impl Default for Notifications {
     }
 }
 
+/// Settings for custom prompt discovery.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Prompts {
+    /// Skip project prompt files ignored by `.gitignore`. Defaults to `false`.
+    #[serde(default)]
+    pub respect_gitignore: bool,
+}
+
 /// Collection of settings that are specific to the TUI.
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Tui {
@@ -175,6 +183,38 @@ pub struct Tui {
     /// Defaults to `false`.
     #[serde(default)]
     pub notifications: Notifications,
+
+    /// Settings for the `@agent` suggestion popup.
+    #[serde(default)]
+    pub agent_popup: AgentPopupSettings,
+}
+
+/// Settings for the TUI's `@agent` suggestion popup.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AgentPopupSettings {
+    /// Token prefix after `@` that lists agents instead of searching files, matched
+    /// ignoring case. Defaults to `agent`.
+    pub prefix: String,
+    /// Whether Up/Down wrap around at the ends of the list. Defaults to `true`.
+    pub wrap: bool,
+    /// Most agents shown at once before the list scrolls. Defaults to the height of
+    /// the TUI's other popups.
+    pub max_rows: Option<usize>,
+    /// Number the unfiltered rows so `@agent <number>` selects that row. Defaults to
+    /// `false`, in which case a number filters agent names like any other text.
+    pub show_index: bool,
+}
+
+impl Default for AgentPopupSettings {
+    fn default() -> Self {
+        Self {
+            prefix: "agent".to_string(),
+            wrap: true,
+            max_rows: None,
+            show_index: false,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -1,9 +1,15 @@
+use crate::path_utils::resolve_within;
 use codex_protocol::custom_prompts::CustomPrompt;
+use codex_protocol::custom_prompts::CustomPromptMeta;
+use futures::StreamExt;
+use futures::stream;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::SystemTime;
 use tokio::fs;
+use tokio_util::sync::CancellationToken;
 
 /// Return the default prompts directory: `$CODEX_HOME/prompts`.
 /// If `CODEX_HOME` cannot be resolved, returns `None`.
@@ -16,40 +22,146 @@ pub fn default_prompts_dir() -> Option<PathBuf> {
 /// Discover prompt files in the given directory, returning entries sorted by name.
 /// Non-files are ignored. If the directory does not exist or cannot be read, returns empty.
 pub async fn discover_prompts_in(dir: &Path) -> Vec<CustomPrompt> {
-    discover_prompts_in_excluding(dir, &HashSet::new()).await
+    discover_prompts_in_excluding(dir, &HashSet::new(), None, None).await
 }
 
+/// File extensions recognized as prompts, in order of preference when several files
+/// share a stem (e.g. `foo.md` wins over `foo.txt`).
+pub const PROMPT_EXTENSIONS: &[&str] = &["md", "prompt", "txt"];
+
 /// Discover prompt files in the given directory, excluding any with names in `exclude`.
 /// Returns entries sorted by name. Non-files are ignored. Missing/unreadable dir yields empty.
+/// Only files with one of the [`PROMPT_EXTENSIONS`] are considered. Bodies longer than
+/// `max_bytes` are truncated and flagged as such.
+///
+/// Files are read concurrently. Cancelling `cancel` stops the scan early, returning only
+/// the prompts read by then.
 pub async fn discover_prompts_in_excluding(
     dir: &Path,
     exclude: &HashSet<String>,
+    max_bytes: Option<usize>,
+    cancel: Option<&CancellationToken>,
+) -> Vec<CustomPrompt> {
+    discover_prompts(dir, exclude, max_bytes, false, cancel).await
+}
+
+/// Most prompt files read at once, so slow (e.g. network) filesystems don't serialize
+/// discovery without opening every file at the same time.
+const PROMPT_READ_CONCURRENCY: usize = 8;
+
+/// [`discover_prompts_in_excluding`], also skipping files ignored by `.gitignore` when
+/// `respect_gitignore` is set.
+async fn discover_prompts(
+    dir: &Path,
+    exclude: &HashSet<String>,
+    max_bytes: Option<usize>,
+    respect_gitignore: bool,
+    cancel: Option<&CancellationToken>,
 ) -> Vec<CustomPrompt> {
+    let cancelled = cancelled(cancel);
+    tokio::pin!(cancelled);
+
     let mut out: Vec<CustomPrompt> = Vec::new();
+    let files = tokio::select! {
+        biased;
+        _ = &mut cancelled => return out,
+        files = prompt_files_in(dir, exclude, respect_gitignore) => files,
+    };
+    let mut reads = stream::iter(files)
+        .map(|(name, path)| read_prompt(name, path, max_bytes))
+        .buffer_unordered(PROMPT_READ_CONCURRENCY);
+    loop {
+        tokio::select! {
+            biased;
+            _ = &mut cancelled => {
+                tracing::debug!("prompt discovery in {} cancelled", dir.display());
+                break;
+            }
+            read = reads.next() => match read {
+                Some(Some(prompt)) => out.push(prompt),
+                Some(None) => {}
+                None => break,
+            },
+        }
+    }
+    // Reads finish in any order.
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    out
+}
+
+/// Resolves once `cancel` is cancelled; never resolves without a token.
+async fn cancelled(cancel: Option<&CancellationToken>) {
+    match cancel {
+        Some(cancel) => cancel.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Count the prompts [`discover_prompts_in`] would find in `dir`, without reading their
+/// contents. Files that turn out to be unreadable are still counted.
+pub async fn count_prompts_in(dir: &Path) -> usize {
+    prompt_files_in(dir, &HashSet::new(), false).await.len()
+}
+
+/// Names of the prompts in `dir` starting with `prefix` (ignoring case), sorted, for
+/// incremental filtering. Only file names are inspected; contents are never read.
+pub async fn discover_prompt_names_with_prefix(dir: &Path, prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut names: Vec<String> = prompt_files_in(dir, &HashSet::new(), false)
+        .await
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.to_lowercase().starts_with(&prefix))
+        .collect();
+    names.sort();
+    names
+}
+
+/// List the prompt files in `dir` as `(name, path)` pairs, keeping only the preferred
+/// extension for each stem and skipping names in `exclude`. With `respect_gitignore`,
+/// files ignored by `.gitignore` are skipped too.
+async fn prompt_files_in(
+    dir: &Path,
+    exclude: &HashSet<String>,
+    respect_gitignore: bool,
+) -> Vec<(String, PathBuf)> {
+    let mut best: HashMap<String, (usize, PathBuf)> = HashMap::new();
     let mut entries = match fs::read_dir(dir).await {
         Ok(entries) => entries,
-        Err(_) => return out,
+        Err(_) => return Vec::new(),
+    };
+    let not_ignored = if respect_gitignore {
+        not_gitignored_in(dir).await
+    } else {
+        None
     };
 
     while let Ok(Some(entry)) = entries.next_entry().await {
         let path = entry.path();
-        let is_file = entry
-            .file_type()
-            .await
-            .map(|ft| ft.is_file())
-            .unwrap_or(false);
-        if !is_file {
+        if let Some(not_ignored) = &not_ignored
+            && !not_ignored.contains(&path)
+        {
             continue;
         }
-        // Only include Markdown files with a .md extension.
-        let is_md = path
-            .extension()
-            .and_then(|s| s.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("md"))
-            .unwrap_or(false);
-        if !is_md {
+        let is_file = match entry.file_type().await {
+            // Follow symlinks so links to files count but links to directories don't.
+            Ok(ft) if ft.is_symlink() => fs::metadata(&path)
+                .await
+                .map(|meta| meta.is_file())
+                .unwrap_or(false),
+            Ok(ft) => ft.is_file(),
+            Err(_) => false,
+        };
+        if !is_file {
             continue;
         }
+        let Some(rank) = path.extension().and_then(|s| s.to_str()).and_then(|ext| {
+            PROMPT_EXTENSIONS
+                .iter()
+                .position(|known| ext.eq_ignore_ascii_case(known))
+        }) else {
+            continue;
+        };
         let Some(name) = path
             .file_stem()
             .and_then(|s| s.to_str())
@@ -60,25 +172,605 @@ pub async fn discover_prompts_in_excluding(
         if exclude.contains(&name) {
             continue;
         }
-        let content = match fs::read_to_string(&path).await {
-            Ok(s) => s,
-            Err(_) => continue,
+        match best.get(&name) {
+            Some((existing, _)) if *existing <= rank => {}
+            _ => {
+                best.insert(name, (rank, path));
+            }
+        }
+    }
+    best.into_iter()
+        .map(|(name, (_, path))| (name, path))
+        .collect()
+}
+
+/// Entries of `dir` not ignored by `.gitignore` files (in `dir` or its parents) or
+/// `.git/info/exclude`. `None` if the walk couldn't run, in which case nothing should be
+/// skipped.
+async fn not_gitignored_in(dir: &Path) -> Option<HashSet<PathBuf>> {
+    let dir = dir.to_path_buf();
+    let walk = tokio::task::spawn_blocking(move || {
+        ignore::WalkBuilder::new(&dir)
+            .standard_filters(false)
+            .git_ignore(true)
+            .git_exclude(true)
+            .parents(true)
+            // Honor `.gitignore` even outside a git checkout.
+            .require_git(false)
+            .max_depth(Some(1))
+            .build()
+            .filter_map(Result::ok)
+            .map(ignore::DirEntry::into_path)
+            .collect()
+    });
+    match walk.await {
+        Ok(paths) => Some(paths),
+        Err(e) => {
+            tracing::debug!("not applying .gitignore to prompts: {e}");
+            None
+        }
+    }
+}
+
+/// Read a single prompt file, splitting off any front-matter and cutting the body down
+/// to `max_bytes` when given. Unreadable or non-UTF-8 files yield `None`, including
+/// files deleted or replaced by a directory since they were listed.
+async fn read_prompt(
+    name: String,
+    path: PathBuf,
+    max_bytes: Option<usize>,
+) -> Option<CustomPrompt> {
+    read_prompt_with_includes(name, path, max_bytes)
+        .await
+        .map(|(prompt, _)| prompt)
+}
+
+/// [`read_prompt`], also returning the canonical paths of the files its includes
+/// pulled in.
+async fn read_prompt_with_includes(
+    name: String,
+    path: PathBuf,
+    max_bytes: Option<usize>,
+) -> Option<(CustomPrompt, Vec<PathBuf>)> {
+    let content = match fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(e) => {
+            tracing::debug!("skipping prompt {}: {e}", path.display());
+            return None;
+        }
+    };
+    let (meta, mut content) = match parse_front_matter(&content) {
+        Ok(Some((meta, body))) => (Some(meta), body.to_string()),
+        Ok(None) => (None, content),
+        Err(e) => {
+            tracing::debug!("ignoring malformed front-matter in {}: {e}", path.display());
+            (None, content)
+        }
+    };
+    let mut argument_hint = meta
+        .as_ref()
+        .and_then(|meta| meta.argument_hint.as_deref())
+        .map(str::trim)
+        .filter(|hint| !hint.is_empty())
+        .map(str::to_string);
+    let mut includes = Vec::new();
+    let is_markdown = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
+    if is_markdown && let Some(dir) = path.parent() {
+        // Seed the stack with the prompt itself so an include leading back to it is
+        // caught as a cycle before its body is repeated.
+        let mut stack: Vec<PathBuf> = std::fs::canonicalize(&path).ok().into_iter().collect();
+        content = expand_includes_within(&content, dir, &mut stack, &mut includes);
+    }
+    if let Some((hint, body)) = parse_args_comment(&content) {
+        argument_hint = argument_hint.or(Some(hint));
+        content = body.to_string();
+    }
+    let agent = meta
+        .as_ref()
+        .and_then(|meta| meta.agent.as_deref())
+        .map(str::trim)
+        .filter(|agent| !agent.is_empty())
+        .map(str::to_string);
+    let byte_len = content.len();
+    let truncated = max_bytes.is_some_and(|max| truncate_at_char_boundary(&mut content, max));
+    let prompt = CustomPrompt {
+        name,
+        path,
+        content,
+        meta,
+        argument_hint,
+        byte_len,
+        truncated,
+        agent,
+    };
+    Some((prompt, includes))
+}
+
+/// Shorten `content` to at most `max_bytes`, backing off to the nearest character
+/// boundary. Returns whether anything was cut.
+fn truncate_at_char_boundary(content: &mut String, max_bytes: usize) -> bool {
+    if content.len() <= max_bytes {
+        return false;
+    }
+    let mut end = max_bytes;
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    content.truncate(end);
+    true
+}
+
+/// Split a leading `<!-- args: <hint> -->` line off `content`, returning the hint and
+/// the rest of the body. Returns `None` when the first line is not such a comment or
+/// the hint is empty.
+fn parse_args_comment(content: &str) -> Option<(String, &str)> {
+    let (first_line, body) = match content.find('\n') {
+        Some(end) => (&content[..end], &content[end + 1..]),
+        None => (content, ""),
+    };
+    let hint = first_line
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("args:")?
+        .trim();
+    (!hint.is_empty()).then(|| (hint.to_string(), body))
+}
+
+/// Remembers prompts read from disk so repeated discovery only re-reads files whose
+/// modification time, or that of a file they include, changed.
+#[derive(Debug, Default)]
+pub struct PromptCache {
+    entries: HashMap<PathBuf, CachedPrompt>,
+    /// Number of prompt files read from disk so far.
+    reads: usize,
+}
+
+#[derive(Debug)]
+struct CachedPrompt {
+    modified: SystemTime,
+    /// Files pulled in through includes, with their modification times when read
+    includes: Vec<(PathBuf, Option<SystemTime>)>,
+    prompt: CustomPrompt,
+}
+
+impl CachedPrompt {
+    /// Whether any included file changed (or disappeared) since the prompt was read.
+    async fn includes_changed(&self) -> bool {
+        for (path, modified) in &self.includes {
+            if modified_time(path).await != *modified {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Modification time of `path`, or `None` when it can't be read.
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .await
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+impl PromptCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like [`discover_prompts_in_excluding`], but reuses cached contents for files whose
+    /// modification time, and that of every file they include, is unchanged since the
+    /// last call. Cached entries for files that no longer exist in `dir` are dropped.
+    ///
+    /// With `respect_gitignore`, files ignored by `.gitignore` are skipped. Changed files
+    /// are read concurrently. Cancelling `cancel` stops the scan early, returning only
+    /// the prompts found by then.
+    pub async fn discover(
+        &mut self,
+        dir: &Path,
+        exclude: &HashSet<String>,
+        respect_gitignore: bool,
+        cancel: Option<&CancellationToken>,
+    ) -> Vec<CustomPrompt> {
+        let cancelled = cancelled(cancel);
+        tokio::pin!(cancelled);
+
+        let mut out: Vec<CustomPrompt> = Vec::new();
+        let files = tokio::select! {
+            biased;
+            _ = &mut cancelled => return out,
+            files = prompt_files_in(dir, exclude, respect_gitignore) => files,
         };
-        out.push(CustomPrompt {
-            name,
-            path,
-            content,
-        });
+        let current: HashSet<&PathBuf> = files.iter().map(|(_, path)| path).collect();
+        self.entries
+            .retain(|path, _| path.parent() != Some(dir) || current.contains(path));
+
+        let mut stale = Vec::new();
+        for (name, path) in files {
+            let modified = modified_time(&path).await;
+            if let Some(cached) = self.entries.get(&path)
+                && Some(cached.modified) == modified
+                && cached.prompt.name == name
+                && !cached.includes_changed().await
+            {
+                out.push(cached.prompt.clone());
+            } else {
+                stale.push((name, path, modified));
+            }
+        }
+
+        let mut reads = stream::iter(stale)
+            .map(|(name, path, modified)| async move {
+                let read = read_prompt_with_includes(name, path.clone(), None).await;
+                let mut includes = Vec::new();
+                for include in read.iter().flat_map(|(_, included)| included) {
+                    includes.push((include.clone(), modified_time(include).await));
+                }
+                (path, modified, read.map(|(prompt, _)| (prompt, includes)))
+            })
+            .buffer_unordered(PROMPT_READ_CONCURRENCY);
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut cancelled => {
+                    tracing::debug!("prompt discovery in {} cancelled", dir.display());
+                    break;
+                }
+                read = reads.next() => match read {
+                    Some((path, modified, read)) => {
+                        self.reads += 1;
+                        let Some((prompt, includes)) = read else {
+                            self.entries.remove(&path);
+                            continue;
+                        };
+                        // Without a modification time there is nothing to validate
+                        // against, so don't cache the file.
+                        if let Some(modified) = modified {
+                            self.entries.insert(
+                                path,
+                                CachedPrompt {
+                                    modified,
+                                    includes,
+                                    prompt: prompt.clone(),
+                                },
+                            );
+                        }
+                        out.push(prompt);
+                    }
+                    None => break,
+                },
+            }
+        }
+        // Reads finish in any order.
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+
+    /// Like [`discover_all_prompts`], but reading each directory through
+    /// [`PromptCache::discover`].
+    pub async fn discover_all(
+        &mut self,
+        project_root: &Path,
+        exclude: &HashSet<String>,
+        personal_dir: Option<PathBuf>,
+        extra_dirs: &[PathBuf],
+        respect_gitignore: bool,
+        cancel: Option<&CancellationToken>,
+    ) -> Vec<CustomPrompt> {
+        let project_dir = project_prompts_dir(project_root);
+        let dirs = std::iter::once(project_dir.as_path())
+            .chain(personal_dir.as_deref())
+            .chain(extra_dirs.iter().map(PathBuf::as_path));
+        let mut by_name: HashMap<String, CustomPrompt> = HashMap::new();
+
+        // Earlier directories take precedence
+        for dir in dirs {
+            let respect_gitignore = respect_gitignore && dir == project_dir;
+            if cancel.is_some_and(CancellationToken::is_cancelled) {
+                break;
+            }
+            for p in self.discover(dir, exclude, respect_gitignore, cancel).await {
+                by_name.entry(p.name.clone()).or_insert(p);
+            }
+        }
+
+        let mut out: Vec<CustomPrompt> = by_name.into_values().collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+}
+
+/// Arguments typed after a custom prompt's slash command.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PromptArguments {
+    /// Everything typed after the command, trimmed
+    pub all: String,
+    /// Whitespace-separated arguments; quotes keep spaces inside a single argument
+    pub positional: Vec<String>,
+    /// `name=value` arguments, filling `{{name}}` placeholders
+    pub named: HashMap<String, String>,
+}
+
+impl PromptArguments {
+    /// Parse the text typed after the command. Arguments of the form `name=value`, where
+    /// `name` is made of ASCII letters, digits, `_` or `-`, are named; the rest are
+    /// positional.
+    pub fn parse(input: &str) -> Self {
+        let all = input.trim().to_string();
+        let mut positional = Vec::new();
+        let mut named = HashMap::new();
+        for arg in split_prompt_arguments(&all) {
+            match arg.split_once('=') {
+                Some((name, value)) if is_placeholder_name(name) => {
+                    named.insert(name.to_string(), value.to_string());
+                }
+                _ => positional.push(arg),
+            }
+        }
+        Self {
+            all,
+            positional,
+            named,
+        }
+    }
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn split_prompt_arguments(input: &str) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_quote: Option<char> = None;
+
+    for ch in input.chars() {
+        if let Some(quote) = in_quote {
+            if ch == quote {
+                in_quote = None;
+            } else {
+                current.push(ch);
+            }
+            continue;
+        }
+
+        match ch {
+            '"' | '\'' => {
+                in_quote = Some(ch);
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    result.push(current.clone());
+                    current.clear();
+                }
+            }
+            _ => current.push(ch),
+        }
+    }
+
+    if !current.is_empty() {
+        result.push(current);
+    }
+
+    result
+}
+
+/// Expand argument placeholders in a prompt body.
+///
+/// - `$ARGUMENTS` becomes [`PromptArguments::all`].
+/// - `$1`, `$2`, … become the matching positional argument (empty when missing).
+/// - `{{name}}` becomes the named argument `name`; `{{name|default}}` falls back to
+///   `default` when it is missing. Tokens without a value or default are left as is.
+/// - `$$` becomes a literal `$`; any other `$` is left as is.
+///
+/// Positional arguments not consumed by a placeholder are appended to the end, unless the
+/// prompt uses `$ARGUMENTS`.
+pub fn expand_prompt(content: &str, args: &PromptArguments) -> String {
+    const ARGUMENTS_TOKEN: &str = "ARGUMENTS";
+    let mut out = String::with_capacity(content.len() + args.all.len());
+    let mut positional_used = vec![false; args.positional.len()];
+    let mut used_all_arguments = false;
+    let mut rest = content;
+
+    while let Some(pos) = rest.find(['$', '{']) {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if rest[pos..].starts_with('{') {
+            match after.strip_prefix('{').and_then(|a| expand_named(a, args)) {
+                Some((value, tail)) => {
+                    out.push_str(value);
+                    rest = tail;
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        } else if let Some(tail) = after.strip_prefix('$') {
+            out.push('$');
+            rest = tail;
+        } else if let Some(tail) = after.strip_prefix(ARGUMENTS_TOKEN) {
+            out.push_str(&args.all);
+            used_all_arguments = true;
+            rest = tail;
+        } else {
+            let digits = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            match after[..digits].parse::<usize>() {
+                Ok(position) => {
+                    if let Some(index) = position.checked_sub(1)
+                        && let Some(arg) = args.positional.get(index)
+                    {
+                        positional_used[index] = true;
+                        out.push_str(arg);
+                    }
+                    rest = &after[digits..];
+                }
+                Err(_) => {
+                    out.push('$');
+                    rest = after;
+                }
+            }
+        }
+    }
+    out.push_str(rest);
+
+    if !used_all_arguments {
+        let leftovers = args
+            .positional
+            .iter()
+            .zip(positional_used)
+            .filter(|(arg, used)| !used && !arg.is_empty())
+            .map(|(arg, _)| arg.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !leftovers.is_empty() {
+            if out.chars().last().is_some_and(|c| !c.is_whitespace()) {
+                out.push(' ');
+            }
+            out.push_str(&leftovers);
+        }
     }
-    out.sort_by(|a, b| a.name.cmp(&b.name));
     out
 }
 
+/// Resolve a `{{name}}` / `{{name|default}}` token; `after_open` follows the `{{`.
+/// Returns the value and the text after the closing `}}`.
+fn expand_named<'a>(after_open: &'a str, args: &'a PromptArguments) -> Option<(&'a str, &'a str)> {
+    let end = after_open.find("}}")?;
+    let token = &after_open[..end];
+    let (name, default) = match token.split_once('|') {
+        Some((name, default)) => (name.trim(), Some(default.trim())),
+        None => (token.trim(), None),
+    };
+    let value = args.named.get(name).map(String::as_str).or(default)?;
+    Some((value, &after_open[end + 2..]))
+}
+
+/// Opening of an include directive, e.g. `{{> shared/footer.md}}`.
+const INCLUDE_OPEN: &str = "{{>";
+
+/// Replace `{{> path}}` directives with the contents of `path`, resolved relative to
+/// `base_dir` (the prompts directory). Included files may include others, also relative
+/// to `base_dir`.
+///
+/// Directives pointing at missing files or outside `base_dir`, and ones that would form
+/// an include cycle, are left as is with a warning.
+pub fn expand_includes(content: &str, base_dir: &Path) -> String {
+    expand_includes_within(content, base_dir, &mut Vec::new(), &mut Vec::new())
+}
+
+/// [`expand_includes`], with `stack` holding the files currently being included. Every
+/// file that gets included is added to `included`.
+fn expand_includes_within(
+    content: &str,
+    base_dir: &Path,
+    stack: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(INCLUDE_OPEN) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + INCLUDE_OPEN.len()..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let token = &rest[start..start + INCLUDE_OPEN.len() + end + 2];
+        match read_include(after[..end].trim(), base_dir, stack) {
+            Ok((path, body)) => {
+                if !included.contains(&path) {
+                    included.push(path.clone());
+                }
+                stack.push(path);
+                out.push_str(&expand_includes_within(&body, base_dir, stack, included));
+                stack.pop();
+            }
+            Err(e) => {
+                tracing::warn!("leaving prompt include {token} unexpanded: {e}");
+                out.push_str(token);
+            }
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Read the file an include directive points at, returning its canonical path too.
+fn read_include(
+    target: &str,
+    base_dir: &Path,
+    stack: &[PathBuf],
+) -> Result<(PathBuf, String), String> {
+    let path = resolve_within(base_dir, target, &[base_dir.to_path_buf()])
+        .ok_or_else(|| format!("'{target}' does not exist within {}", base_dir.display()))?;
+    if stack.contains(&path) {
+        return Err(format!("'{target}' forms an include cycle"));
+    }
+    let content =
+        std::fs::read_to_string(&path).map_err(|e| format!("cannot read '{target}': {e}"))?;
+    Ok((path, content))
+}
+
+/// Split a leading `---`-delimited YAML front-matter block off `content`.
+/// Returns `Ok(None)` when there is no front-matter and an error when the block is
+/// not terminated or is not valid YAML.
+fn parse_front_matter(content: &str) -> Result<Option<(CustomPromptMeta, &str)>, String> {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return Ok(None);
+    };
+
+    // Find the closing delimiter: a line consisting solely of `---`.
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end_matches(['\r', '\n']) == "---" {
+            let yaml = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            let meta = if yaml.trim().is_empty() {
+                CustomPromptMeta::default()
+            } else {
+                serde_yaml::from_str(yaml).map_err(|e| e.to_string())?
+            };
+            return Ok(Some((meta, body)));
+        }
+        offset += line.len();
+    }
+    Err("front-matter is missing its closing `---`".to_string())
+}
+
 /// Return the project prompts directory for a given project root: `<root>/.codex/prompts`.
 pub fn project_prompts_dir(root: &Path) -> PathBuf {
     root.join(".codex").join("prompts")
 }
 
+/// Environment variable listing extra prompt directories, separated like `PATH`.
+pub const PROMPT_PATH_ENV_VAR: &str = "CODEX_PROMPT_PATH";
+
+/// Return the extra prompt directories listed in `CODEX_PROMPT_PATH`, in order.
+/// Empty entries are skipped; an unset variable yields an empty list.
+pub fn extra_prompt_dirs() -> Vec<PathBuf> {
+    std::env::var_os(PROMPT_PATH_ENV_VAR)
+        .map(|value| {
+            std::env::split_paths(&value)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Discover prompts from the project directory and the personal directory, merging them
 /// with project prompts taking precedence over personal prompts on name collisions.
 /// Results are sorted by name.
@@ -86,17 +778,38 @@ pub async fn discover_project_and_personal_prompts(
     project_root: &Path,
     exclude: &HashSet<String>,
     personal_dir: Option<PathBuf>,
+) -> Vec<CustomPrompt> {
+    discover_all_prompts(project_root, exclude, personal_dir, &[], false, None).await
+}
+
+/// Discover prompts from the project directory, the personal directory and then each of
+/// `extra_dirs`. On name collisions the first source wins: project, then personal, then
+/// the extra directories in order. Results are sorted by name.
+///
+/// With `respect_gitignore`, project prompt files ignored by `.gitignore` (e.g. generated
+/// prompts) are skipped. Cancelling `cancel` stops the scan early, returning only the
+/// prompts read by then.
+pub async fn discover_all_prompts(
+    project_root: &Path,
+    exclude: &HashSet<String>,
+    personal_dir: Option<PathBuf>,
+    extra_dirs: &[PathBuf],
+    respect_gitignore: bool,
+    cancel: Option<&CancellationToken>,
 ) -> Vec<CustomPrompt> {
     let project_dir = project_prompts_dir(project_root);
+    let dirs = std::iter::once(project_dir.as_path())
+        .chain(personal_dir.as_deref())
+        .chain(extra_dirs.iter().map(PathBuf::as_path));
     let mut by_name: HashMap<String, CustomPrompt> = HashMap::new();
 
-    // Project prompts first (higher precedence)
-    for p in discover_prompts_in_excluding(&project_dir, exclude).await {
-        by_name.insert(p.name.clone(), p);
-    }
-    // Then personal prompts, only if not already present
-    if let Some(dir) = personal_dir.as_deref() {
-        for p in discover_prompts_in_excluding(dir, exclude).await {
+    // Earlier directories take precedence
+    for dir in dirs {
+        let respect_gitignore = respect_gitignore && dir == project_dir;
+        if cancel.is_some_and(CancellationToken::is_cancelled) {
+            break;
+        }
+        for p in discover_prompts(dir, exclude, None, respect_gitignore, cancel).await {
             by_name.entry(p.name.clone()).or_insert(p);
         }
     }
@@ -132,6 +845,46 @@ mod tests {
         assert_eq!(names, vec!["a", "b"]);
     }
 
+    #[tokio::test]
+    async fn parallel_reads_stay_sorted_by_name() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        let mut expected: Vec<String> = (0..40).map(|i| format!("prompt-{i:02}")).collect();
+        // Varying sizes so reads don't finish in listing order.
+        for (i, name) in expected.iter().enumerate().rev() {
+            fs::write(dir.join(format!("{name}.md")), "x".repeat(i * 1000)).unwrap();
+        }
+        expected.sort();
+
+        let found = discover_prompts_in_excluding(dir, &HashSet::new(), None, None).await;
+        let names: Vec<String> = found.into_iter().map(|p| p.name).collect();
+        pretty_assertions::assert_eq!(names, expected);
+    }
+
+    #[tokio::test]
+    async fn cancelled_discovery_returns_early() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        for i in 0..20 {
+            fs::write(dir.join(format!("p{i}.md")), b"prompt").unwrap();
+        }
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let found = discover_prompts_in_excluding(dir, &HashSet::new(), None, Some(&cancel)).await;
+        assert!(found.is_empty());
+        let found = discover_all_prompts(
+            dir,
+            &HashSet::new(),
+            Some(dir.to_path_buf()),
+            &[],
+            false,
+            Some(&cancel),
+        )
+        .await;
+        assert!(found.is_empty());
+    }
+
     #[tokio::test]
     async fn excludes_builtins() {
         let tmp = tempdir().expect("create TempDir");
@@ -140,11 +893,54 @@ mod tests {
         fs::write(dir.join("foo.md"), b"ok").unwrap();
         let mut exclude = HashSet::new();
         exclude.insert("init".to_string());
-        let found = discover_prompts_in_excluding(dir, &exclude).await;
+        let found = discover_prompts_in_excluding(dir, &exclude, None, None).await;
         let names: Vec<String> = found.into_iter().map(|e| e.name).collect();
         assert_eq!(names, vec!["foo"]);
     }
 
+    #[tokio::test]
+    async fn prompt_deleted_after_listing_is_skipped() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("kept.md"), b"kept").unwrap();
+        let files = prompt_files_in(dir, &HashSet::new(), false).await;
+        // A path listed earlier that is gone by the time it is read.
+        let gone = dir.join("gone.md");
+        let gone_dir = dir.join("now-a-dir.md");
+        fs::create_dir(&gone_dir).unwrap();
+
+        assert!(read_prompt("gone".to_string(), gone, None).await.is_none());
+        assert!(
+            read_prompt("now-a-dir".to_string(), gone_dir, None)
+                .await
+                .is_none()
+        );
+        let names: Vec<String> = files.into_iter().map(|(name, _)| name).collect();
+        pretty_assertions::assert_eq!(names, vec!["kept"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_to_directories_are_not_prompts() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::create_dir(dir.join("real")).unwrap();
+        fs::write(dir.join("target.txt"), b"linked").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("folder.md")).unwrap();
+        std::os::unix::fs::symlink(dir.join("target.txt"), dir.join("link.md")).unwrap();
+
+        let found = discover_prompts_in(dir).await;
+        let contents: Vec<(String, String)> =
+            found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(
+            contents,
+            vec![
+                ("link".to_string(), "linked".to_string()),
+                ("target".to_string(), "linked".to_string()),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn skips_non_utf8_files() {
         let tmp = tempdir().expect("create TempDir");
@@ -158,6 +954,125 @@ mod tests {
         assert_eq!(names, vec!["good"]);
     }
 
+    #[tokio::test]
+    async fn extra_dirs_merge_with_lowest_precedence() {
+        let tmp = tempdir().expect("create TempDir");
+        let root = tmp.path();
+        let proj_dir = project_prompts_dir(root);
+        std::fs::create_dir_all(&proj_dir).unwrap();
+        fs::write(proj_dir.join("shared.md"), b"project").unwrap();
+
+        let personal = root.join("personal");
+        std::fs::create_dir_all(&personal).unwrap();
+        fs::write(personal.join("mine.md"), b"personal").unwrap();
+
+        // Two extra dirs: the first wins over the second, both lose to project/personal.
+        let first = root.join("first");
+        let second = root.join("second");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("shared.md"), b"first").unwrap();
+        fs::write(first.join("mine.md"), b"first").unwrap();
+        fs::write(first.join("team.md"), b"first").unwrap();
+        fs::write(second.join("team.md"), b"second").unwrap();
+        fs::write(second.join("extra.md"), b"second").unwrap();
+
+        let exclude = HashSet::new();
+        let found = discover_all_prompts(
+            root,
+            &exclude,
+            Some(personal),
+            &[first, root.join("missing"), second],
+            false,
+            None,
+        )
+        .await;
+        let names: Vec<(String, String)> = found.into_iter().map(|e| (e.name, e.content)).collect();
+        pretty_assertions::assert_eq!(
+            names,
+            vec![
+                ("extra".to_string(), "second".to_string()),
+                ("mine".to_string(), "personal".to_string()),
+                ("shared".to_string(), "project".to_string()),
+                ("team".to_string(), "first".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn long_prompts_are_truncated_at_the_limit() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("short.md"), "tiny").unwrap();
+        fs::write(dir.join("long.md"), "x".repeat(64)).unwrap();
+        // "é" is two bytes, so a 5-byte limit has to stop after two of them.
+        fs::write(dir.join("accents.md"), "ééé").unwrap();
+
+        let found = discover_prompts_in_excluding(dir, &HashSet::new(), Some(5), None).await;
+        let summary: Vec<(String, String, usize, bool)> = found
+            .into_iter()
+            .map(|p| (p.name, p.content, p.byte_len, p.truncated))
+            .collect();
+        pretty_assertions::assert_eq!(
+            summary,
+            vec![
+                ("accents".to_string(), "éé".to_string(), 6, true),
+                ("long".to_string(), "xxxxx".to_string(), 64, true),
+                ("short".to_string(), "tiny".to_string(), 4, false),
+            ]
+        );
+
+        let untruncated = discover_prompts_in(dir).await;
+        assert!(untruncated.iter().all(|p| !p.truncated));
+        assert_eq!(untruncated[0].byte_len, "ééé".len());
+    }
+
+    #[tokio::test]
+    async fn counts_prompt_files_without_reading_them() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("a.md"), b"a").unwrap();
+        fs::write(dir.join("b.md"), b"b").unwrap();
+        // Same stem as `b.md`, so it is the same prompt.
+        fs::write(dir.join("b.txt"), b"b").unwrap();
+        fs::write(dir.join("notes.json"), b"{}").unwrap();
+        fs::create_dir(dir.join("sub.md")).unwrap();
+
+        assert_eq!(count_prompts_in(dir).await, 2);
+        assert_eq!(count_prompts_in(&dir.join("missing")).await, 0);
+    }
+
+    #[tokio::test]
+    async fn prompt_names_filter_by_prefix_ignoring_case() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("review.md"), b"r").unwrap();
+        fs::write(dir.join("Release-notes.md"), b"n").unwrap();
+        fs::write(dir.join("refactor.txt"), b"f").unwrap();
+        fs::write(dir.join("test.md"), b"t").unwrap();
+        fs::write(dir.join("readme.json"), b"{}").unwrap();
+
+        assert_eq!(
+            discover_prompt_names_with_prefix(dir, "re").await,
+            vec!["Release-notes", "refactor", "review"]
+        );
+        assert_eq!(
+            discover_prompt_names_with_prefix(dir, "REV").await,
+            vec!["review"]
+        );
+        assert_eq!(discover_prompt_names_with_prefix(dir, "").await.len(), 4);
+        assert!(
+            discover_prompt_names_with_prefix(dir, "zzz")
+                .await
+                .is_empty()
+        );
+        assert!(
+            discover_prompt_names_with_prefix(&dir.join("missing"), "re")
+                .await
+                .is_empty()
+        );
+    }
+
     #[tokio::test]
     async fn project_overrides_personal_and_merges() {
         let tmp = tempdir().expect("create TempDir");
@@ -190,4 +1105,525 @@ mod tests {
             ]
         );
     }
+
+    #[tokio::test]
+    async fn gitignored_project_prompts_are_skipped_when_requested() {
+        let tmp = tempdir().expect("create TempDir");
+        let root = tmp.path();
+        let proj_dir = project_prompts_dir(root);
+        std::fs::create_dir_all(&proj_dir).unwrap();
+        fs::write(root.join(".gitignore"), b"generated.md\ndraft.md\n").unwrap();
+        fs::write(proj_dir.join("generated.md"), b"generated").unwrap();
+        fs::write(proj_dir.join("review.md"), b"review").unwrap();
+        // Only the project directory consults `.gitignore`.
+        let personal = root.join("personal");
+        std::fs::create_dir_all(&personal).unwrap();
+        fs::write(personal.join("draft.md"), b"draft").unwrap();
+
+        let exclude = HashSet::new();
+        let names = |found: Vec<CustomPrompt>| -> Vec<String> {
+            found.into_iter().map(|p| p.name).collect()
+        };
+        let respected =
+            discover_all_prompts(root, &exclude, Some(personal.clone()), &[], true, None).await;
+        pretty_assertions::assert_eq!(names(respected), vec!["draft", "review"]);
+
+        let all =
+            discover_all_prompts(root, &exclude, Some(personal.clone()), &[], false, None).await;
+        pretty_assertions::assert_eq!(names(all), vec!["draft", "generated", "review"]);
+
+        // The session's cache honors the option the same way.
+        let mut cache = PromptCache::new();
+        let respected = cache
+            .discover_all(root, &exclude, Some(personal.clone()), &[], true, None)
+            .await;
+        pretty_assertions::assert_eq!(names(respected), vec!["draft", "review"]);
+        let all = cache
+            .discover_all(root, &exclude, Some(personal), &[], false, None)
+            .await;
+        pretty_assertions::assert_eq!(names(all), vec!["draft", "generated", "review"]);
+    }
+
+    #[tokio::test]
+    async fn parses_and_strips_front_matter() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(
+            dir.join("review.md"),
+            "---\ndescription: Review a file\ntags: [review, quality]\nauthor: Dana\nargument-hint: <file>\n---\nReview $1 carefully.\n",
+        )
+        .unwrap();
+        let found = discover_prompts_in(dir).await;
+        pretty_assertions::assert_eq!(found.len(), 1);
+        pretty_assertions::assert_eq!(found[0].content, "Review $1 carefully.\n");
+        pretty_assertions::assert_eq!(
+            found[0].meta,
+            Some(CustomPromptMeta {
+                description: Some("Review a file".to_string()),
+                tags: vec!["review".to_string(), "quality".to_string()],
+                author: Some("Dana".to_string()),
+                argument_hint: Some("<file>".to_string()),
+                agent: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn argument_hint_comes_from_front_matter() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(
+            dir.join("review.md"),
+            "---\nargument_hint: <file> [focus]\n---\nReview $1.\n",
+        )
+        .unwrap();
+        let found = discover_prompts_in(dir).await;
+        pretty_assertions::assert_eq!(found[0].argument_hint.as_deref(), Some("<file> [focus]"));
+    }
+
+    #[tokio::test]
+    async fn target_agent_comes_from_front_matter() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(
+            dir.join("review.md"),
+            "---\nagent: reviewer\n---\nReview $1.\n",
+        )
+        .unwrap();
+        fs::write(dir.join("blank.md"), "---\nagent: \" \"\n---\nAnything.\n").unwrap();
+        fs::write(dir.join("plain.md"), "Just a prompt\n").unwrap();
+        let found = discover_prompts_in(dir).await;
+        let agents: Vec<(&str, Option<&str>)> = found
+            .iter()
+            .map(|p| (p.name.as_str(), p.agent.as_deref()))
+            .collect();
+        pretty_assertions::assert_eq!(
+            agents,
+            vec![
+                ("blank", None),
+                ("plain", None),
+                ("review", Some("reviewer"))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn argument_hint_comes_from_leading_comment() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(
+            dir.join("fix.md"),
+            "<!-- args: <file> <line> -->\nFix $1 at line $2.\n",
+        )
+        .unwrap();
+        fs::write(dir.join("malformed.md"), "<!-- args: <file>\nBody\n").unwrap();
+        fs::write(dir.join("plain.md"), "Just a prompt\n").unwrap();
+        let found = discover_prompts_in(dir).await;
+        let hints: Vec<(&str, Option<&str>, &str)> = found
+            .iter()
+            .map(|p| {
+                (
+                    p.name.as_str(),
+                    p.argument_hint.as_deref(),
+                    p.content.as_str(),
+                )
+            })
+            .collect();
+        pretty_assertions::assert_eq!(
+            hints,
+            vec![
+                ("fix", Some("<file> <line>"), "Fix $1 at line $2.\n"),
+                ("malformed", None, "<!-- args: <file>\nBody\n"),
+                ("plain", None, "Just a prompt\n"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn file_without_front_matter_is_unchanged() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        let content = "Plain prompt\n---\nwith a horizontal rule\n";
+        fs::write(dir.join("plain.md"), content).unwrap();
+        let found = discover_prompts_in(dir).await;
+        pretty_assertions::assert_eq!(found[0].content, content);
+        pretty_assertions::assert_eq!(found[0].meta, None);
+    }
+
+    #[tokio::test]
+    async fn malformed_front_matter_keeps_whole_file() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        let broken = "---\ntags: [unterminated\n---\nBody\n";
+        let unterminated = "---\ndescription: never closed\nBody\n";
+        fs::write(dir.join("broken.md"), broken).unwrap();
+        fs::write(dir.join("unterminated.md"), unterminated).unwrap();
+        let found = discover_prompts_in(dir).await;
+        let contents: Vec<(&str, Option<&CustomPromptMeta>)> = found
+            .iter()
+            .map(|p| (p.content.as_str(), p.meta.as_ref()))
+            .collect();
+        pretty_assertions::assert_eq!(contents, vec![(broken, None), (unterminated, None)]);
+    }
+
+    #[test]
+    fn prompt_arguments_split_quotes_and_named_values() {
+        let args = PromptArguments::parse("  123 \"high priority\" branch=main a=b=c ");
+        pretty_assertions::assert_eq!(args.all, "123 \"high priority\" branch=main a=b=c");
+        pretty_assertions::assert_eq!(args.positional, vec!["123", "high priority"]);
+        pretty_assertions::assert_eq!(
+            args.named,
+            HashMap::from([
+                ("branch".to_string(), "main".to_string()),
+                ("a".to_string(), "b=c".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn expand_prompt_substitutes_positionals() {
+        pretty_assertions::assert_eq!(
+            expand_prompt("Fix $1 in $2.", &PromptArguments::parse("bug parser.rs")),
+            "Fix bug in parser.rs."
+        );
+    }
+
+    #[test]
+    fn expand_prompt_substitutes_all_arguments() {
+        pretty_assertions::assert_eq!(
+            expand_prompt("Task: $ARGUMENTS!", &PromptArguments::parse("add a test")),
+            "Task: add a test!"
+        );
+    }
+
+    #[test]
+    fn expand_prompt_unescapes_double_dollar() {
+        pretty_assertions::assert_eq!(
+            expand_prompt("Costs $$5, see $$1 and $HOME", &PromptArguments::parse("")),
+            "Costs $5, see $1 and $HOME"
+        );
+    }
+
+    #[test]
+    fn expand_prompt_blanks_missing_positionals() {
+        pretty_assertions::assert_eq!(
+            expand_prompt("[$1][$2][$9]$", &PromptArguments::parse("only")),
+            "[only][][]$"
+        );
+    }
+
+    #[test]
+    fn expand_prompt_appends_unused_positionals() {
+        pretty_assertions::assert_eq!(
+            expand_prompt("Review PR #$2.", &PromptArguments::parse("a 12 b")),
+            "Review PR #12. a b"
+        );
+        pretty_assertions::assert_eq!(
+            expand_prompt("Run: $ARGUMENTS $1", &PromptArguments::parse("a b")),
+            "Run: a b a"
+        );
+    }
+
+    #[test]
+    fn expand_prompt_substitutes_named_arguments() {
+        pretty_assertions::assert_eq!(
+            expand_prompt(
+                "Rebase {{branch}} onto {{ base }}.",
+                &PromptArguments::parse("branch=feature base=main")
+            ),
+            "Rebase feature onto main."
+        );
+    }
+
+    #[test]
+    fn expand_prompt_uses_default_for_missing_named_argument() {
+        pretty_assertions::assert_eq!(
+            expand_prompt(
+                "Diff against {{branch|main}} in {{dir | src}}.",
+                &PromptArguments::parse("dir=core")
+            ),
+            "Diff against main in core."
+        );
+    }
+
+    #[test]
+    fn expand_prompt_preserves_unknown_named_tokens() {
+        pretty_assertions::assert_eq!(
+            expand_prompt(
+                "Keep {{unknown}}, {{> footer.md}} and {{open",
+                &PromptArguments::parse("")
+            ),
+            "Keep {{unknown}}, {{> footer.md}} and {{open"
+        );
+    }
+
+    #[tokio::test]
+    async fn md_prompts_expand_a_single_include() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::create_dir(dir.join("shared")).unwrap();
+        fs::write(dir.join("shared/footer.md"), "Be brief.").unwrap();
+        fs::write(
+            dir.join("review.md"),
+            "Review it.\n{{> shared/footer.md}}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("plain.txt"), "{{> shared/footer.md}}").unwrap();
+
+        let found = discover_prompts_in(dir).await;
+        let contents: Vec<(String, String)> =
+            found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(
+            contents,
+            vec![
+                ("plain".to_string(), "{{> shared/footer.md}}".to_string()),
+                ("review".to_string(), "Review it.\nBe brief.\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_includes_resolves_nested_includes_from_the_base_dir() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::create_dir(dir.join("shared")).unwrap();
+        fs::write(dir.join("shared/footer.md"), "Footer {{> shared/sig.md}}").unwrap();
+        fs::write(dir.join("shared/sig.md"), "-- codex").unwrap();
+
+        pretty_assertions::assert_eq!(
+            expand_includes("Body. {{> shared/footer.md}}", dir),
+            "Body. Footer -- codex"
+        );
+    }
+
+    #[test]
+    fn expand_includes_leaves_missing_and_escaping_includes() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path().join("prompts");
+        fs::create_dir(&dir).unwrap();
+        fs::write(tmp.path().join("secret.md"), "secret").unwrap();
+
+        pretty_assertions::assert_eq!(
+            expand_includes("A {{> missing.md}} B {{> ../secret.md}} C {{> open", &dir),
+            "A {{> missing.md}} B {{> ../secret.md}} C {{> open"
+        );
+    }
+
+    #[test]
+    fn expand_includes_stops_at_cycles() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("a.md"), "A {{> b.md}}").unwrap();
+        fs::write(dir.join("b.md"), "B {{> a.md}}").unwrap();
+
+        pretty_assertions::assert_eq!(expand_includes("{{> a.md}}", dir), "A B {{> a.md}}");
+    }
+
+    #[tokio::test]
+    async fn discovery_stops_include_cycles_at_the_prompt_itself() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("a.md"), "A {{> b.md}}").unwrap();
+        fs::write(dir.join("b.md"), "B {{> a.md}}").unwrap();
+
+        let found = discover_prompts_in(dir).await;
+        let contents: Vec<(String, String)> =
+            found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(
+            contents,
+            vec![
+                ("a".to_string(), "A B {{> a.md}}".to_string()),
+                ("b".to_string(), "B A {{> b.md}}".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn discovers_prompt_and_txt_extensions() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("plan.prompt"), b"plan it").unwrap();
+        fs::write(dir.join("notes.txt"), b"take notes").unwrap();
+        fs::write(dir.join("ignored.rs"), b"fn main() {}").unwrap();
+        let found = discover_prompts_in(dir).await;
+        let names: Vec<(String, String)> = found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(
+            names,
+            vec![
+                ("notes".to_string(), "take notes".to_string()),
+                ("plan".to_string(), "plan it".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn md_wins_when_stems_collide() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("foo.txt"), b"from txt").unwrap();
+        fs::write(dir.join("foo.md"), b"from md").unwrap();
+        fs::write(dir.join("foo.prompt"), b"from prompt").unwrap();
+        fs::write(dir.join("bar.txt"), b"bar txt").unwrap();
+        fs::write(dir.join("bar.prompt"), b"bar prompt").unwrap();
+        let found = discover_prompts_in(dir).await;
+        let names: Vec<(String, String)> = found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(
+            names,
+            vec![
+                ("bar".to_string(), "bar prompt".to_string()),
+                ("foo".to_string(), "from md".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prompt_cache_skips_unchanged_files() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("a.md"), b"a").unwrap();
+        fs::write(dir.join("b.md"), b"b").unwrap();
+        let mut cache = PromptCache::new();
+        let exclude = HashSet::new();
+
+        let first = cache.discover(dir, &exclude, false, None).await;
+        pretty_assertions::assert_eq!(first.len(), 2);
+        pretty_assertions::assert_eq!(cache.reads, 2);
+
+        let second = cache.discover(dir, &exclude, false, None).await;
+        let names: Vec<String> = second.into_iter().map(|p| p.name).collect();
+        pretty_assertions::assert_eq!(names, vec!["a", "b"]);
+        pretty_assertions::assert_eq!(cache.reads, 2);
+    }
+
+    #[tokio::test]
+    async fn prompt_cache_rereads_touched_and_prunes_deleted_files() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("a.md"), b"old").unwrap();
+        fs::write(dir.join("b.md"), b"b").unwrap();
+        let mut cache = PromptCache::new();
+        let exclude = HashSet::new();
+        cache.discover(dir, &exclude, false, None).await;
+
+        fs::write(dir.join("a.md"), b"new").unwrap();
+        // Bump the mtime explicitly so the test doesn't depend on timestamp granularity.
+        let later = SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("a.md"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        fs::remove_file(dir.join("b.md")).unwrap();
+
+        let found = cache.discover(dir, &exclude, false, None).await;
+        let contents: Vec<(String, String)> =
+            found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(contents, vec![("a".to_string(), "new".to_string())]);
+        pretty_assertions::assert_eq!(cache.reads, 3);
+        pretty_assertions::assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn prompt_cache_rereads_prompts_whose_includes_changed() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::create_dir(dir.join("shared")).unwrap();
+        fs::write(dir.join("shared/footer.md"), b"old footer").unwrap();
+        fs::write(dir.join("review.md"), b"Review. {{> shared/footer.md}}").unwrap();
+        fs::write(dir.join("plain.md"), b"plain").unwrap();
+        let mut cache = PromptCache::new();
+        let exclude = HashSet::new();
+        cache.discover(dir, &exclude, false, None).await;
+        pretty_assertions::assert_eq!(cache.reads, 2);
+
+        fs::write(dir.join("shared/footer.md"), b"new footer").unwrap();
+        // Bump the mtime explicitly so the test doesn't depend on timestamp granularity.
+        let later = SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("shared/footer.md"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        let found = cache.discover(dir, &exclude, false, None).await;
+        let contents: Vec<(String, String)> =
+            found.into_iter().map(|p| (p.name, p.content)).collect();
+        pretty_assertions::assert_eq!(
+            contents,
+            vec![
+                ("plain".to_string(), "plain".to_string()),
+                ("review".to_string(), "Review. new footer".to_string()),
+            ]
+        );
+        pretty_assertions::assert_eq!(cache.reads, 3);
+    }
+
+    #[tokio::test]
+    async fn prompt_cache_stops_when_cancelled() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        fs::write(dir.join("a.md"), b"a").unwrap();
+        let mut cache = PromptCache::new();
+        let exclude = HashSet::new();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let found = cache.discover(dir, &exclude, false, Some(&cancel)).await;
+        assert!(found.is_empty());
+        pretty_assertions::assert_eq!(cache.reads, 0);
+
+        // A cancelled scan leaves nothing behind, so the next one reads the file.
+        let found = cache.discover(dir, &exclude, false, None).await;
+        pretty_assertions::assert_eq!(found.len(), 1);
+        pretty_assertions::assert_eq!(cache.reads, 1);
+    }
+
+    #[tokio::test]
+    async fn prompt_cache_results_stay_sorted_under_parallel_reads() {
+        let tmp = tempdir().expect("create TempDir");
+        let dir = tmp.path();
+        let mut expected: Vec<String> = (0..(PROMPT_READ_CONCURRENCY * 3))
+            .map(|i| format!("prompt-{i:02}"))
+            .collect();
+        for name in &expected {
+            fs::write(dir.join(format!("{name}.md")), name).unwrap();
+        }
+        expected.sort();
+        let mut cache = PromptCache::new();
+
+        let found = cache.discover(dir, &HashSet::new(), false, None).await;
+        let names: Vec<String> = found.into_iter().map(|p| p.name).collect();
+        pretty_assertions::assert_eq!(names, expected);
+    }
+
+    #[tokio::test]
+    async fn prompt_cache_discovers_all_dirs_with_project_precedence() {
+        let tmp = tempdir().expect("create TempDir");
+        let root = tmp.path().join("repo");
+        let project = project_prompts_dir(&root);
+        let personal = tmp.path().join("personal");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&personal).unwrap();
+        fs::write(project.join("shared.md"), b"project").unwrap();
+        fs::write(personal.join("shared.md"), b"personal").unwrap();
+        fs::write(personal.join("mine.md"), b"mine").unwrap();
+        let mut cache = PromptCache::new();
+        let exclude = HashSet::new();
+
+        let expected = vec![
+            ("mine".to_string(), "mine".to_string()),
+            ("shared".to_string(), "project".to_string()),
+        ];
+        for _ in 0..2 {
+            let found = cache
+                .discover_all(&root, &exclude, Some(personal.clone()), &[], false, None)
+                .await;
+            let contents: Vec<(String, String)> =
+                found.into_iter().map(|p| (p.name, p.content)).collect();
+            pretty_assertions::assert_eq!(contents, expected);
+        }
+        pretty_assertions::assert_eq!(cache.reads, 3);
+    }
 }
@@ -94,6 +94,14 @@ pub enum CodexErr {
     #[error("exceeded retry limit, last status: {0}")]
     RetryLimit(StatusCode),
 
+    /// An agent turn ended with an error reported by its conversation.
+    #[error("agent failed: {0}")]
+    AgentFailed(String),
+
+    /// An agent turn ran longer than its configured timeout.
+    #[error("agent timed out after {0:?}")]
+    AgentTimeout(Duration),
+
     /// Agent loop died unexpectedly
     #[error("internal error; agent loop died unexpectedly")]
     InternalAgentDied,
@@ -6,6 +6,7 @@
 #![deny(clippy::print_stdout, clippy::print_stderr)]
 
 pub mod agent;
+pub mod agent_history;
 mod apply_patch;
 pub mod auth;
 pub mod bash;
@@ -16,6 +17,7 @@ pub mod codex;
 mod codex_conversation;
 pub mod token_data;
 pub use codex_conversation::CodexConversation;
+pub mod command_menu;
 mod command_safety;
 pub mod config;
 pub mod config_edit;
@@ -37,6 +39,7 @@ mod mcp_tool_call;
 mod message_history;
 mod model_provider_info;
 pub mod parse_command;
+mod path_utils;
 mod truncate;
 mod unified_exec;
 mod user_instructions;
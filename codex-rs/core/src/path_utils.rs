@@ -0,0 +1,75 @@
+//! Helpers for resolving user-supplied paths against a set of allowed roots.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Resolve `path` (relative to `base_dir` unless absolute) to an existing canonical path
+/// inside one of `allowed_roots`. Returns `None` when it doesn't exist or escapes them,
+/// e.g. through `..` or a symlink.
+pub(crate) fn resolve_within(
+    base_dir: &Path,
+    path: &str,
+    allowed_roots: &[PathBuf],
+) -> Option<PathBuf> {
+    let path = if Path::new(path).is_absolute() {
+        PathBuf::from(path)
+    } else {
+        base_dir.join(path)
+    };
+
+    // Canonicalize to resolve ../ and symlinks
+    let canonical = path.canonicalize().ok()?;
+
+    // Security check: path must be within one of the allowed roots (or their children)
+    let allowed = allowed_roots.iter().any(|root| {
+        let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+        canonical.starts_with(root)
+    });
+    allowed.then_some(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn resolves_existing_paths_inside_the_root() {
+        let root = TempDir::new().unwrap();
+        std::fs::create_dir(root.path().join("shared")).unwrap();
+        std::fs::write(root.path().join("shared/footer.md"), "footer").unwrap();
+
+        let roots = [root.path().to_path_buf()];
+        assert_eq!(
+            resolve_within(root.path(), "shared/../shared/footer.md", &roots),
+            Some(root.path().join("shared/footer.md").canonicalize().unwrap())
+        );
+    }
+
+    #[test]
+    fn rejects_missing_and_escaping_paths() {
+        let parent = TempDir::new().unwrap();
+        let root = parent.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(parent.path().join("secret.md"), "secret").unwrap();
+
+        let roots = [root.clone()];
+        assert_eq!(resolve_within(&root, "missing.md", &roots), None);
+        assert_eq!(resolve_within(&root, "../secret.md", &roots), None);
+    }
+
+    #[test]
+    fn absolute_paths_ignore_the_base_dir() {
+        let root = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        std::fs::write(root.path().join("prompt.md"), "prompt").unwrap();
+        let absolute = root.path().join("prompt.md");
+
+        let roots = [root.path().to_path_buf()];
+        assert_eq!(
+            resolve_within(other.path(), &absolute.to_string_lossy(), &roots),
+            Some(absolute.canonicalize().unwrap())
+        );
+    }
+}
@@ -3,9 +3,46 @@ use serde::Serialize;
 use std::path::PathBuf;
 use ts_rs::TS;
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
 pub struct CustomPrompt {
     pub name: String,
     pub path: PathBuf,
     pub content: String,
+    /// Metadata from the file's YAML front-matter, if it had any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<CustomPromptMeta>,
+    /// One-line usage hint for the prompt's arguments, e.g. `<file> <line>`, taken from
+    /// the front-matter or a leading `<!-- args: ... -->` comment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub argument_hint: Option<String>,
+    /// Size of the prompt body in bytes, before any truncation.
+    #[serde(default)]
+    pub byte_len: usize,
+    /// Whether `content` was cut short because the body exceeded the size limit.
+    #[serde(default)]
+    pub truncated: bool,
+    /// Agent the prompt should run with, from the front-matter `agent` key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+}
+
+/// Metadata declared in a `---`-delimited YAML block at the top of a prompt file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, TS)]
+pub struct CustomPromptMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Hint describing the arguments the prompt expects, e.g. `<file> [focus]`.
+    #[serde(
+        default,
+        alias = "argument-hint",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub argument_hint: Option<String>,
+    /// Name of the agent the prompt is meant for, e.g. `reviewer`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
 }
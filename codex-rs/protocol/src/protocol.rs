@@ -1198,13 +1198,50 @@ pub struct ListCustomPromptsResponseEvent {
 #[derive(Debug, Clone, Deserialize, Serialize, TS)]
 pub struct ListAgentsResponseEvent {
     pub agents: Vec<AgentInfo>,
+    /// Agent last used in the session's project, if it still exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, TS)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, TS)]
 pub struct AgentInfo {
     pub name: String,
     pub description: String,
     pub is_builtin: bool,
+    /// The first few lines of the agent's system prompt, for previews.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_preview: Option<String>,
+    /// Tags used to group and filter agents (e.g. `review`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Model the agent runs with when it overrides the session model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Sandbox permissions the agent runs with when it overrides the session's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions_summary: Option<String>,
+    /// Whether the agent's `prompt_file` could not be read, leaving it without a prompt.
+    #[serde(default)]
+    pub is_broken: bool,
+    /// Group the agent is listed under, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Where the agent's definition was loaded from.
+    #[serde(default)]
+    pub source: AgentSource,
+}
+
+/// Where an agent definition came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentSource {
+    /// The built-in "general" agent.
+    #[default]
+    Builtin,
+    /// The project's `.codex` directory.
+    Project,
+    /// The personal `~/.codex` directory.
+    Home,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, TS)]
@@ -1378,6 +1415,17 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn agent_info_without_source_defaults_to_builtin() -> Result<()> {
+        let agent: AgentInfo = serde_json::from_value(json!({
+            "name": "general",
+            "description": "General-purpose agent",
+            "is_builtin": true,
+        }))?;
+        assert_eq!(agent.source, AgentSource::Builtin);
+        Ok(())
+    }
+
     #[test]
     fn vec_u8_as_base64_serialization_and_deserialization() -> Result<()> {
         let event = ExecCommandOutputDeltaEvent {
@@ -11,6 +11,8 @@ use crate::tui::TuiEvent;
 use codex_ansi_escape::ansi_escape_line;
 use codex_core::AuthManager;
 use codex_core::ConversationManager;
+use codex_core::agent::save_last_used_agent;
+use codex_core::agent_history::record_agent_use;
 use codex_core::config::Config;
 use codex_core::config::persist_model_selection;
 use codex_core::model_family::find_family_for_model;
@@ -348,6 +350,17 @@ impl App {
                     }
                 }
             }
+            AppEvent::AgentSelected(name) => {
+                if let Err(err) = save_last_used_agent(&self.config.cwd, &name) {
+                    tracing::warn!(error = %err, "failed to save last used agent");
+                }
+                match record_agent_use(&self.config.codex_home, &name) {
+                    Ok(recent) => self.chat_widget.set_recent_agents(recent),
+                    Err(err) => {
+                        tracing::warn!(error = %err, "failed to record recent agent");
+                    }
+                }
+            }
             AppEvent::UpdateAskForApprovalPolicy(policy) => {
                 self.chat_widget.set_approval_policy(policy);
             }
@@ -38,6 +38,9 @@ pub(crate) enum AppEvent {
         matches: Vec<FileMatch>,
     },
 
+    /// An agent was picked from the @agent popup; record it as recently used.
+    AgentSelected(String),
+
     /// Result of computing a `/diff` command.
     DiffResult(String),
 
@@ -1,144 +1,844 @@
+use codex_common::agent_filter::AgentFilterOptions;
+use codex_common::agent_filter::filter_agents;
 use codex_core::protocol::AgentInfo;
 use ratatui::buffer::Buffer;
 use ratatui::layout::Rect;
+use ratatui::style::Stylize;
+use ratatui::text::Span;
 use ratatui::widgets::WidgetRef;
 
 use super::popup_consts::MAX_POPUP_ROWS;
 use super::scroll_state::ScrollState;
 use super::selection_popup_common::GenericDisplayRow;
-use super::selection_popup_common::measure_rows_height;
-use super::selection_popup_common::render_rows;
+use super::selection_popup_common::measure_rows_height_with_line_limit;
+use super::selection_popup_common::render_preview;
+use super::selection_popup_common::render_rows_with_line_limit;
+
+/// Most prompt lines shown in the preview below the agent list.
+const MAX_PREVIEW_LINES: usize = 10;
+
+/// Most lines a single agent row wraps onto before its description is cut off.
+const MAX_ROW_LINES: usize = 2;
+
+/// Query prefix that lists agents unless [`AgentPopup::set_prefix`] changes it.
+const DEFAULT_QUERY_PREFIX: &str = "agent";
 
 /// Visual state for the agent-suggestion popup.
 pub(crate) struct AgentPopup {
     /// Last query used to compute matches
     query: String,
+    /// Filter text after the `agent` prefix, quoted when nothing matches
+    filter: String,
+    /// Number of agents known before filtering
+    total_agents: usize,
     /// Filtered agents rendered as rows
     rows: Vec<GenericDisplayRow>,
+    /// Agents behind `rows`, index-aligned with them
+    matches: Vec<AgentInfo>,
+    /// Recently used agent names, most recent first
+    recent: Vec<String>,
     /// Shared selection/scroll state.
     state: ScrollState,
+    /// Whether Up/Down wrap around at the ends of the list
+    wrap: bool,
+    /// Lowercase prefix a query starts with before the agent filter
+    prefix: String,
+    /// Most agents shown at once before the list scrolls
+    max_rows: usize,
+    /// Whether agents are still being (re)loaded
+    loading: bool,
+    /// Whether unfiltered rows are numbered for selecting them by index
+    show_index: bool,
+    /// Footer shown when the query selects an agent number that isn't listed
+    index_hint: Option<String>,
 }
 
 impl AgentPopup {
     pub(crate) fn new() -> Self {
         Self {
             query: String::new(),
+            filter: String::new(),
+            total_agents: 0,
             rows: Vec::new(),
+            matches: Vec::new(),
+            recent: Vec::new(),
             state: ScrollState::new(),
+            wrap: true,
+            prefix: DEFAULT_QUERY_PREFIX.to_string(),
+            max_rows: MAX_POPUP_ROWS,
+            loading: false,
+            show_index: false,
+            index_hint: None,
         }
     }
 
-    /// Update the query and compute matches from `agents`.
-    pub(crate) fn set_query(&mut self, query: &str, agents: &[AgentInfo]) {
-        if self.query == query {
-            return;
-        }
+    /// Set how many agents are shown at once before the list scrolls
+    /// ([`MAX_POPUP_ROWS`] by default, and never fewer than one).
+    pub(crate) fn set_max_rows(&mut self, max_rows: usize) {
+        self.max_rows = max_rows.max(1);
+        let len = self.rows.len();
+        self.state.ensure_visible(len, len.min(self.max_rows));
+    }
+
+    /// Mark agents as being (re)loaded, so an empty list reads "loading agents…"
+    /// instead of looking like nothing is configured.
+    pub(crate) fn set_loading(&mut self, loading: bool) {
+        self.loading = loading;
+    }
+
+    /// Set the query prefix that precedes the agent filter (`agent` by default). It is
+    /// matched case-insensitively.
+    pub(crate) fn set_prefix(&mut self, prefix: &str) {
+        self.prefix = prefix.to_lowercase();
+    }
+
+    /// Choose whether rows are numbered for selecting them by index (off by default).
+    /// Numbers are only shown while no filter is typed, since that is the list
+    /// `agent <number>` selects from; without them a number filters names like any other
+    /// text. Takes effect on the next [`Self::set_query`].
+    pub(crate) fn set_show_index(&mut self, show_index: bool) {
+        self.show_index = show_index;
+    }
+
+    /// Choose whether Up/Down wrap around at the ends of the list (on by default).
+    pub(crate) fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Set the recently used agents (most recent first) that are listed ahead of the
+    /// rest when no filter is typed. Takes effect on the next [`Self::set_query`].
+    pub(crate) fn set_recent(&mut self, recent: &[String]) {
+        self.recent = recent.to_vec();
+    }
+
+    /// Update the query and compute matches from `agents`. With `builtin_last`, the
+    /// builtin agent sorts after user agents that rank the same.
+    pub(crate) fn set_query(&mut self, query: &str, agents: &[AgentInfo], builtin_last: bool) {
         self.query.clear();
         self.query.push_str(query);
 
-        // Accept both "agent", "agent:", and "agent " prefixes to list all agents.
+        // Accept the bare prefix ("agent"), "agent:", and "agent " to list all agents.
         // If there is additional text after the prefix, filter by that remainder.
         let q_lower = query.to_lowercase();
-        let remainder = if let Some(rest) = q_lower.strip_prefix("agent") {
+        let remainder = if let Some(rest) = q_lower.strip_prefix(self.prefix.as_str()) {
             let rest = rest.trim_start_matches([':', ' ']);
             Some(rest)
         } else {
             None
         };
 
-        let mut rows: Vec<GenericDisplayRow> = match remainder {
-            // No remainder or empty remainder => show all agents
-            Some("") | None => agents
-                .iter()
-                .map(|a| GenericDisplayRow {
-                    name: a.name.clone(),
-                    match_indices: None,
-                    is_current: false,
-                    description: Some(a.description.clone()),
-                })
-                .collect(),
-            // Filter by the remainder after the literal "agent" prefix
-            Some(rem) => {
-                let needle = rem.to_string();
-                agents
-                    .iter()
-                    .filter(|a| a.name.to_lowercase().contains(&needle))
-                    .map(|a| GenericDisplayRow {
-                        name: a.name.clone(),
-                        match_indices: highlight_indices(&a.name, &needle),
-                        is_current: false,
-                        description: Some(a.description.clone()),
-                    })
-                    .collect()
-            }
+        self.filter = remainder.unwrap_or("").trim().to_string();
+        self.total_agents = agents.len();
+        // With numbered rows, a number picks that (1-based) row of the unfiltered list.
+        let index = (self.show_index
+            && !self.filter.is_empty()
+            && self.filter.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| self.filter.parse::<usize>().unwrap_or(usize::MAX));
+        let filter = if index.is_some() { "" } else { &self.filter };
+        let opts = AgentFilterOptions {
+            builtin_last,
+            recent: self.recent.clone(),
         };
-
-        // Stable alphabetical ordering
-        rows.sort_by(|a, b| a.name.cmp(&b.name));
-        self.rows = rows;
-        self.state.clamp_selection(self.rows.len());
+        let matches = filter_agents(filter, agents, &opts);
+        let numbered = self.show_index && filter.is_empty();
+        self.rows = matches
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                let agent = &agents[m.index];
+                let description = if m.is_recent {
+                    format!("recent · {}", describe_agent(agent))
+                } else {
+                    describe_agent(agent)
+                };
+                let name = if numbered {
+                    format!("{}. {}", i + 1, m.name)
+                } else {
+                    m.name.clone()
+                };
+                GenericDisplayRow {
+                    name,
+                    match_indices: (!m.match_indices.is_empty()).then(|| m.match_indices.clone()),
+                    description: Some(description),
+                }
+            })
+            .collect();
+        self.matches = matches.iter().map(|m| agents[m.index].clone()).collect();
+        self.index_hint = None;
+        match index {
+            Some(n) if (1..=self.rows.len()).contains(&n) => self.state.selected_idx = Some(n - 1),
+            Some(_) if !self.rows.is_empty() => {
+                self.state.selected_idx = None;
+                self.index_hint = Some(format!(
+                    "no agent #{} · pick 1–{}",
+                    self.filter,
+                    self.rows.len()
+                ));
+            }
+            _ => self.state.clamp_selection(self.rows.len()),
+        }
         self.state
-            .ensure_visible(self.rows.len(), self.rows.len().min(MAX_POPUP_ROWS));
+            .ensure_visible(self.rows.len(), self.rows.len().min(self.max_rows));
     }
 
     pub(crate) fn move_up(&mut self) {
         let len = self.rows.len();
-        self.state.move_up_wrap(len);
-        self.state.ensure_visible(len, len.min(MAX_POPUP_ROWS));
+        if self.wrap {
+            self.state.move_up_wrap(len);
+        } else {
+            self.state.move_up_clamped(len);
+        }
+        self.state.ensure_visible(len, len.min(self.max_rows));
     }
 
     pub(crate) fn move_down(&mut self) {
         let len = self.rows.len();
-        self.state.move_down_wrap(len);
-        self.state.ensure_visible(len, len.min(MAX_POPUP_ROWS));
+        if self.wrap {
+            self.state.move_down_wrap(len);
+        } else {
+            self.state.move_down_clamped(len);
+        }
+        self.state.ensure_visible(len, len.min(self.max_rows));
+    }
+
+    /// Move the selection up a full page of visible rows, stopping at the top.
+    pub(crate) fn page_up(&mut self) {
+        let len = self.rows.len();
+        self.state.page_up(len, self.max_rows);
+        self.state.ensure_visible(len, len.min(self.max_rows));
+    }
+
+    /// Move the selection down a full page of visible rows, stopping at the bottom.
+    pub(crate) fn page_down(&mut self) {
+        let len = self.rows.len();
+        self.state.page_down(len, self.max_rows);
+        self.state.ensure_visible(len, len.min(self.max_rows));
+    }
+
+    /// Select the first agent.
+    pub(crate) fn move_to_first(&mut self) {
+        let len = self.rows.len();
+        self.state.select_first(len);
+        self.state.ensure_visible(len, len.min(self.max_rows));
+    }
+
+    /// Select the last agent.
+    pub(crate) fn move_to_last(&mut self) {
+        let len = self.rows.len();
+        self.state.select_last(len);
+        self.state.ensure_visible(len, len.min(self.max_rows));
     }
 
     pub(crate) fn selected_agent(&self) -> Option<&str> {
+        self.selected_agent_info().map(|agent| agent.name.as_str())
+    }
+
+    /// Full details of the selected agent, as passed to the last [`Self::set_query`].
+    pub(crate) fn selected_agent_info(&self) -> Option<&AgentInfo> {
         self.state
             .selected_idx
-            .and_then(|idx| self.rows.get(idx))
-            .map(|row| row.name.as_str())
+            .and_then(|idx| self.matches.get(idx))
     }
 
-    pub(crate) fn calculate_required_height(&self, width: u16) -> u16 {
-        measure_rows_height(&self.rows, &self.state, MAX_POPUP_ROWS, width)
+    /// Prompt preview for the selected agent, limited to [`MAX_PREVIEW_LINES`] lines.
+    fn selected_preview(&self) -> Option<String> {
+        let preview = self.selected_agent_info()?.prompt_preview.as_deref()?;
+        let lines: Vec<&str> = preview.lines().take(MAX_PREVIEW_LINES).collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
     }
-}
 
-fn highlight_indices(name: &str, query_lower: &str) -> Option<Vec<usize>> {
-    if query_lower.is_empty() {
-        return None;
+    /// Height of the preview section, including its separator line.
+    fn preview_height(&self) -> u16 {
+        self.selected_preview()
+            .map_or(0, |preview| preview.lines().count() as u16 + 1)
     }
-    let name_lower = name.to_lowercase();
-    let mut indices = Vec::new();
-    let mut i_name = 0usize;
-    for ch in name_lower.chars() {
-        if query_lower.contains(ch) {
-            indices.push(i_name);
+
+    /// Placeholder shown when no rows match.
+    fn empty_message(&self) -> String {
+        if self.loading {
+            "loading agents…".to_string()
+        } else if self.total_agents == 0 {
+            "no agents configured".to_string()
+        } else if self.filter.is_empty() {
+            "type an agent name".to_string()
+        } else {
+            format!("no matches for '{}'", self.filter)
         }
-        i_name += 1;
     }
-    if indices.is_empty() {
-        None
-    } else {
-        Some(indices)
+
+    /// Whether agents are scrolled out of view above and below the list, as
+    /// `(above, below)`.
+    fn scroll_indicators(&self) -> (bool, bool) {
+        let len = self.rows.len();
+        self.state.hidden_above_below(len, len.min(self.max_rows))
+    }
+
+    /// Rows plus the preview and index hint. Each agent wraps onto up to
+    /// [`MAX_ROW_LINES`] lines.
+    pub(crate) fn calculate_required_height(&self, width: u16) -> u16 {
+        self.rows_height(width) + self.preview_height() + u16::from(self.index_hint.is_some())
+    }
+
+    fn rows_height(&self, width: u16) -> u16 {
+        measure_rows_height_with_line_limit(
+            &self.rows,
+            &self.state,
+            self.max_rows,
+            MAX_ROW_LINES,
+            width,
+        )
+    }
+}
+
+/// Row description: the agent's description followed by its model and permission
+/// overrides, when it has any.
+fn describe_agent(agent: &AgentInfo) -> String {
+    let mut description = agent.description.clone();
+    for detail in [&agent.model, &agent.permissions_summary]
+        .into_iter()
+        .flatten()
+    {
+        description.push_str(" · ");
+        description.push_str(detail);
     }
+    description
 }
 
 impl WidgetRef for AgentPopup {
     fn render_ref(&self, area: Rect, buf: &mut Buffer) {
-        render_rows(
-            area,
+        // The list keeps the space it needs; the preview takes whatever is left below it.
+        let rows_height = self.rows_height(area.width).min(area.height);
+        let list_area = Rect {
+            height: rows_height,
+            ..area
+        };
+        if let Some(preview) = self.selected_preview() {
+            // Skip one line to separate the preview from the list.
+            let preview_top = rows_height.saturating_add(1);
+            if area.height > preview_top {
+                let preview_area = Rect {
+                    y: area.y + preview_top,
+                    height: area.height - preview_top,
+                    ..area
+                };
+                render_preview(preview_area, buf, &preview);
+            }
+        }
+        render_rows_with_line_limit(
+            list_area,
             buf,
             &self.rows,
             &self.state,
-            MAX_POPUP_ROWS,
+            self.max_rows,
+            MAX_ROW_LINES,
             false,
-            if self.query.is_empty() {
-                "type an agent name"
-            } else {
-                "no agents"
+            &self.empty_message(),
+        );
+
+        // An out-of-range index selects nothing, so the hint takes the preview's place.
+        if let Some(hint) = &self.index_hint
+            && area.height > rows_height
+        {
+            buf.set_span(
+                area.x,
+                area.y + rows_height,
+                &Span::from(hint.as_str()).dim(),
+                area.width,
+            );
+        }
+
+        // Mark hidden agents with arrows in the list's last column.
+        let (above, below) = self.scroll_indicators();
+        if list_area.height > 0 && list_area.width > 0 {
+            let x = list_area.right() - 1;
+            if above {
+                buf.set_span(x, list_area.y, &Span::from("▲").dim(), 1);
+            }
+            if below {
+                buf.set_span(x, list_area.bottom() - 1, &Span::from("▼").dim(), 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codex_common::fuzzy_match::fuzzy_indices;
+    use codex_core::protocol::AgentSource;
+    use pretty_assertions::assert_eq;
+
+    fn agent(name: &str) -> AgentInfo {
+        AgentInfo {
+            name: name.to_string(),
+            source: AgentSource::Project,
+            ..Default::default()
+        }
+    }
+
+    fn tagged(name: &str, tags: &[&str]) -> AgentInfo {
+        AgentInfo {
+            tags: tags.iter().map(|tag| (*tag).to_string()).collect(),
+            ..agent(name)
+        }
+    }
+
+    fn tagged_agents() -> Vec<AgentInfo> {
+        vec![
+            tagged("code-reviewer", &["review", "rust"]),
+            tagged("doc-reviewer", &["review", "docs"]),
+            tagged("researcher", &["research"]),
+        ]
+    }
+
+    #[test]
+    fn rows_show_model_and_permissions_after_description() {
+        let agents = vec![
+            AgentInfo {
+                description: "Builds things".to_string(),
+                model: Some("gpt-5".to_string()),
+                permissions_summary: Some("workspace-write+network".to_string()),
+                ..agent("builder")
             },
+            AgentInfo {
+                description: "Plain".to_string(),
+                ..agent("plain")
+            },
+        ];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+
+        let descriptions: Vec<Option<&str>> = popup
+            .rows
+            .iter()
+            .map(|row| row.description.as_deref())
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                Some("Builds things · gpt-5 · workspace-write+network"),
+                Some("Plain")
+            ]
         );
     }
+
+    #[test]
+    fn tag_query_filters_to_tagged_agents() {
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent #review", &tagged_agents(), true);
+
+        assert_eq!(row_names(&popup), vec!["code-reviewer", "doc-reviewer"]);
+    }
+
+    #[test]
+    fn tag_query_combines_with_name_fragment() {
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent doc #review", &tagged_agents(), true);
+        assert_eq!(row_names(&popup), vec!["doc-reviewer"]);
+
+        popup.set_query("agent #REVIEW #rust rev", &tagged_agents(), true);
+        assert_eq!(row_names(&popup), vec!["code-reviewer"]);
+    }
+
+    #[test]
+    fn unknown_tag_matches_no_agents() {
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent #deploy", &tagged_agents(), true);
+
+        assert!(popup.rows.is_empty());
+        assert_eq!(popup.selected_agent(), None);
+    }
+
+    fn row_names(popup: &AgentPopup) -> Vec<&str> {
+        popup.rows.iter().map(|row| row.name.as_str()).collect()
+    }
+
+    #[test]
+    fn fuzzy_query_matches_scattered_subsequence() {
+        let agents = vec![agent("code-reviewer"), agent("general")];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent cdr", &agents, true);
+
+        assert_eq!(row_names(&popup), vec!["code-reviewer"]);
+        assert_eq!(fuzzy_indices("code-reviewer", "cdr"), Some(vec![0, 2, 5]));
+    }
+
+    #[test]
+    fn fuzzy_indices_follow_matched_characters_in_order() {
+        assert_eq!(fuzzy_indices("reviewer", "re"), Some(vec![0, 1]));
+        assert_eq!(fuzzy_indices("reviewer", "xyz"), None);
+        // Repeated characters in the name are only highlighted where they matched.
+        assert_eq!(fuzzy_indices("reviewer", "rr"), Some(vec![0, 7]));
+    }
+
+    #[test]
+    fn prefix_match_ranks_above_scattered_match() {
+        let agents = vec![agent("graph-evaluator"), agent("reviewer")];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent rev", &agents, true);
+
+        assert_eq!(row_names(&popup), vec!["reviewer", "graph-evaluator"]);
+        assert_eq!(popup.selected_agent(), Some("reviewer"));
+    }
+
+    #[test]
+    fn empty_query_lists_all_agents_alphabetically() {
+        let agents = vec![agent("zeta"), agent("general"), agent("alpha")];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+
+        assert_eq!(row_names(&popup), vec!["alpha", "general", "zeta"]);
+        assert!(popup.rows.iter().all(|row| row.match_indices.is_none()));
+    }
+
+    #[test]
+    fn recent_agents_are_listed_first_when_query_is_empty() {
+        let agents = vec![
+            agent("alpha"),
+            agent("beta"),
+            agent("general"),
+            agent("zeta"),
+        ];
+        let mut popup = AgentPopup::new();
+        popup.set_recent(&["zeta".to_string(), "beta".to_string()]);
+        popup.set_query("agent", &agents, true);
+
+        assert_eq!(row_names(&popup), vec!["zeta", "beta", "alpha", "general"]);
+        assert_eq!(popup.rows[0].description.as_deref(), Some("recent · "));
+        assert_eq!(popup.rows[2].description.as_deref(), Some(""));
+
+        // Recents don't affect ranking once a filter is typed.
+        popup.set_query("agent a", &agents, true);
+        assert_eq!(row_names(&popup)[0], "alpha");
+    }
+
+    #[test]
+    fn selected_agent_drives_preview() {
+        let with_preview = |name: &str, preview: &str| AgentInfo {
+            prompt_preview: Some(preview.to_string()),
+            ..agent(name)
+        };
+        let agents = vec![
+            with_preview("alpha", "Alpha prompt line"),
+            with_preview("beta", "Beta prompt line\nsecond line"),
+        ];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+        assert_eq!(
+            popup.selected_preview().as_deref(),
+            Some("Alpha prompt line")
+        );
+
+        popup.move_down();
+        assert_eq!(popup.selected_agent(), Some("beta"));
+        assert_eq!(
+            popup.selected_preview().as_deref(),
+            Some("Beta prompt line\nsecond line")
+        );
+
+        let width = 40;
+        let height = popup.calculate_required_height(width);
+        // Two rows, a separator line, and two preview lines.
+        assert_eq!(height, 5);
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        popup.render_ref(area, &mut buf);
+        let rendered: Vec<String> = (0..height)
+            .map(|y| (0..width).map(|x| buf[(x, y)].symbol()).collect())
+            .collect();
+        assert!(rendered[3].contains("Beta prompt line"), "{rendered:?}");
+        assert!(
+            !rendered.iter().any(|line| line.contains("Alpha prompt")),
+            "{rendered:?}"
+        );
+    }
+
+    #[test]
+    fn long_description_wraps_onto_at_most_two_lines() {
+        let agents = vec![
+            AgentInfo {
+                description: "Reviews pull requests for correctness, style, and missing tests across the whole repository".to_string(),
+                ..agent("reviewer")
+            },
+            agent("alpha"),
+        ];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+
+        // Wide enough for every description on a single line.
+        assert_eq!(popup.calculate_required_height(200), 2);
+        // Narrow widths wrap the long description, but only onto one extra line.
+        assert_eq!(popup.calculate_required_height(50), 3);
+        assert_eq!(popup.calculate_required_height(30), 3);
+
+        // Selection still moves one agent at a time.
+        popup.move_down();
+        assert_eq!(popup.selected_agent(), Some("reviewer"));
+    }
+
+    #[test]
+    fn paging_jumps_a_full_page_and_clamps() {
+        let agents: Vec<AgentInfo> = (0..(MAX_POPUP_ROWS * 2 + 3))
+            .map(|i| agent(&format!("agent-{i:02}")))
+            .collect();
+        let last = format!("agent-{:02}", agents.len() - 1);
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+
+        popup.page_down();
+        let page = format!("agent-{MAX_POPUP_ROWS:02}");
+        assert_eq!(popup.selected_agent(), Some(page.as_str()));
+        assert_eq!(popup.state.scroll_top, 1);
+
+        popup.page_down();
+        popup.page_down();
+        assert_eq!(popup.selected_agent(), Some(last.as_str()));
+        popup.page_down();
+        assert_eq!(popup.selected_agent(), Some(last.as_str()));
+
+        popup.page_up();
+        popup.page_up();
+        popup.page_up();
+        assert_eq!(popup.selected_agent(), Some("agent-00"));
+        assert_eq!(popup.state.scroll_top, 0);
+
+        popup.move_to_last();
+        assert_eq!(popup.selected_agent(), Some(last.as_str()));
+        assert_eq!(popup.state.scroll_top, agents.len() - MAX_POPUP_ROWS);
+        popup.move_to_first();
+        assert_eq!(popup.selected_agent(), Some("agent-00"));
+    }
+
+    #[test]
+    fn navigation_stops_at_the_ends_when_wrap_is_off() {
+        let agents = vec![agent("alpha"), agent("beta"), agent("gamma")];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+
+        popup.move_up();
+        assert_eq!(popup.selected_agent(), Some("gamma"));
+
+        popup.set_wrap(false);
+        popup.move_down();
+        assert_eq!(popup.selected_agent(), Some("gamma"));
+
+        popup.move_to_first();
+        popup.move_up();
+        assert_eq!(popup.selected_agent(), Some("alpha"));
+        popup.move_down();
+        assert_eq!(popup.selected_agent(), Some("beta"));
+    }
+
+    #[test]
+    fn builtin_agent_sorts_after_user_agents() {
+        let general = AgentInfo {
+            is_builtin: true,
+            ..agent("general")
+        };
+        let agents = vec![general, agent("reviewer"), agent("alpha")];
+        let mut popup = AgentPopup::new();
+
+        popup.set_query("agent", &agents, true);
+        assert_eq!(row_names(&popup), vec!["alpha", "reviewer", "general"]);
+        popup.set_query("agent", &agents, false);
+        assert_eq!(row_names(&popup), vec!["alpha", "general", "reviewer"]);
+
+        popup.set_query("agent rev", &agents, true);
+        assert_eq!(row_names(&popup), vec!["reviewer"]);
+    }
+
+    #[test]
+    fn custom_prefix_lists_and_filters_agents() {
+        let agents = vec![agent("reviewer"), agent("researcher"), agent("alpha")];
+        let mut popup = AgentPopup::new();
+        popup.set_prefix("@");
+
+        popup.set_query("@", &agents, true);
+        assert_eq!(row_names(&popup), vec!["alpha", "researcher", "reviewer"]);
+
+        popup.set_query("@rev", &agents, true);
+        assert_eq!(row_names(&popup), vec!["reviewer"]);
+        popup.set_query("@: alp", &agents, true);
+        assert_eq!(row_names(&popup), vec!["alpha"]);
+    }
+
+    #[test]
+    fn scroll_indicators_track_hidden_agents() {
+        let agents: Vec<AgentInfo> = (0..(MAX_POPUP_ROWS + 2))
+            .map(|i| agent(&format!("agent-{i:02}")))
+            .collect();
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+        assert_eq!(popup.scroll_indicators(), (false, true));
+
+        // One row past the first page scrolls by one, leaving one agent below.
+        popup.page_down();
+        assert_eq!(popup.state.scroll_top, 1);
+        assert_eq!(popup.scroll_indicators(), (true, true));
+
+        popup.move_to_last();
+        assert_eq!(popup.scroll_indicators(), (true, false));
+
+        popup.set_query("agent agent-01", &agents, true);
+        assert_eq!(popup.scroll_indicators(), (false, false));
+
+        let width = 40;
+        popup.set_query("agent", &agents, true);
+        let area = Rect::new(0, 0, width, popup.calculate_required_height(width));
+        let mut buf = Buffer::empty(area);
+        popup.render_ref(area, &mut buf);
+        assert_eq!(buf[(width - 1, area.height - 1)].symbol(), "▼");
+        assert_ne!(buf[(width - 1, 0)].symbol(), "▲");
+    }
+
+    #[test]
+    fn selected_agent_info_follows_filter_and_selection() {
+        let agents = vec![
+            AgentInfo {
+                description: "Reviews code".to_string(),
+                model: Some("gpt-5".to_string()),
+                ..tagged("reviewer", &["review"])
+            },
+            tagged("researcher", &["research"]),
+            agent("alpha"),
+        ];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent re", &agents, true);
+        assert_eq!(row_names(&popup), vec!["researcher", "reviewer"]);
+
+        popup.move_down();
+        assert_eq!(popup.selected_agent_info(), Some(&agents[0]));
+
+        popup.set_query("agent #research", &agents, true);
+        assert_eq!(popup.selected_agent_info(), Some(&agents[1]));
+
+        popup.set_query("agent zzz", &agents, true);
+        assert_eq!(popup.selected_agent_info(), None);
+    }
+
+    #[test]
+    fn empty_message_distinguishes_no_agents_from_no_matches() {
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &[], true);
+        assert_eq!(popup.empty_message(), "no agents configured");
+        popup.set_query("agent rev", &[], true);
+        assert_eq!(popup.empty_message(), "no agents configured");
+
+        let agents = vec![agent("alpha")];
+        popup.set_query("agent zzz", &agents, true);
+        assert_eq!(popup.empty_message(), "no matches for 'zzz'");
+        popup.set_query("agent #missing", &agents, true);
+        assert_eq!(popup.empty_message(), "no matches for '#missing'");
+
+        let width = 40;
+        let area = Rect::new(0, 0, width, popup.calculate_required_height(width));
+        let mut buf = Buffer::empty(area);
+        popup.render_ref(area, &mut buf);
+        let rendered: String = (0..width).map(|x| buf[(x, 0)].symbol()).collect();
+        assert!(
+            rendered.contains("no matches for '#missing'"),
+            "{rendered:?}"
+        );
+    }
+
+    #[test]
+    fn loading_message_overrides_empty_and_no_match_messages() {
+        let mut popup = AgentPopup::new();
+        popup.set_loading(true);
+        popup.set_query("agent", &[], true);
+        assert_eq!(popup.empty_message(), "loading agents…");
+
+        let agents = vec![agent("alpha")];
+        popup.set_query("agent", &agents, true);
+        popup.set_query("agent zzz", &agents, true);
+        assert_eq!(popup.empty_message(), "loading agents…");
+
+        let width = 40;
+        let area = Rect::new(0, 0, width, popup.calculate_required_height(width));
+        let mut buf = Buffer::empty(area);
+        popup.render_ref(area, &mut buf);
+        let rendered: String = (0..width).map(|x| buf[(x, 0)].symbol()).collect();
+        assert!(rendered.contains("loading agents…"), "{rendered:?}");
+
+        popup.set_loading(false);
+        assert_eq!(popup.empty_message(), "no matches for 'zzz'");
+    }
+
+    #[test]
+    fn max_rows_controls_height_and_visible_window() {
+        let agents: Vec<AgentInfo> = (0..(MAX_POPUP_ROWS * 2))
+            .map(|i| agent(&format!("agent-{i:02}")))
+            .collect();
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent", &agents, true);
+        let default_height = popup.calculate_required_height(80);
+        assert_eq!(default_height, MAX_POPUP_ROWS as u16);
+
+        popup.set_max_rows(MAX_POPUP_ROWS + 4);
+        assert_eq!(popup.calculate_required_height(80), default_height + 4);
+        for _ in 0..(MAX_POPUP_ROWS + 3) {
+            popup.move_down();
+        }
+        // The last visible row is selected without scrolling.
+        assert_eq!(popup.state.scroll_top, 0);
+        popup.move_down();
+        assert_eq!(popup.state.scroll_top, 1);
+
+        popup.set_max_rows(0);
+        assert_eq!(popup.calculate_required_height(80), 1);
+    }
+
+    #[test]
+    fn number_selects_that_row_of_the_numbered_list() {
+        let agents = vec![agent("gamma"), agent("alpha"), agent("beta")];
+        let mut popup = AgentPopup::new();
+        popup.set_show_index(true);
+        popup.set_query("agent", &agents, true);
+        assert_eq!(row_names(&popup), vec!["1. alpha", "2. beta", "3. gamma"]);
+        assert_eq!(popup.selected_agent(), Some("alpha"));
+
+        popup.set_query("agent 3", &agents, true);
+        assert_eq!(row_names(&popup), vec!["1. alpha", "2. beta", "3. gamma"]);
+        assert_eq!(popup.selected_agent(), Some("gamma"));
+
+        // Filtered rows aren't numbered, as numbers refer to the unfiltered list.
+        popup.set_query("agent bet", &agents, true);
+        assert_eq!(row_names(&popup), vec!["beta"]);
+        assert_eq!(popup.selected_agent(), Some("beta"));
+    }
+
+    #[test]
+    fn out_of_range_number_selects_nothing_and_shows_a_hint() {
+        let agents = vec![agent("alpha"), agent("beta")];
+        let mut popup = AgentPopup::new();
+        popup.set_show_index(true);
+        popup.set_query("agent 7", &agents, true);
+        assert_eq!(row_names(&popup), vec!["1. alpha", "2. beta"]);
+        assert_eq!(popup.selected_agent(), None);
+
+        let width = 40;
+        let height = popup.calculate_required_height(width);
+        assert_eq!(height, 3);
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        popup.render_ref(area, &mut buf);
+        let footer: String = (0..width).map(|x| buf[(x, 2)].symbol()).collect();
+        assert!(footer.contains("no agent #7 · pick 1–2"), "{footer:?}");
+
+        popup.set_query("agent 0", &agents, true);
+        assert_eq!(popup.selected_agent(), None);
+        popup.set_query("agent 2", &agents, true);
+        assert_eq!(popup.selected_agent(), Some("beta"));
+        assert_eq!(popup.calculate_required_height(width), 2);
+    }
+
+    #[test]
+    fn numbers_filter_names_when_rows_are_not_numbered() {
+        let agents = vec![agent("alpha"), agent("agent-007"), agent("beta")];
+        let mut popup = AgentPopup::new();
+        popup.set_query("agent 007", &agents, true);
+        assert_eq!(row_names(&popup), vec!["agent-007"]);
+        assert_eq!(popup.selected_agent(), Some("agent-007"));
+        assert_eq!(popup.calculate_required_height(40), 1);
+    }
 }
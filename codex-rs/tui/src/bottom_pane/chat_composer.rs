@@ -1,3 +1,6 @@
+use codex_core::config_types::AgentPopupSettings;
+use codex_core::custom_prompts::PromptArguments;
+use codex_core::custom_prompts::expand_prompt;
 use codex_core::protocol::TokenUsageInfo;
 use codex_protocol::num_format::format_si_suffix;
 use crossterm::event::KeyCode;
@@ -61,19 +64,6 @@ pub enum InputResult {
     None,
 }
 
-#[derive(Debug, Default, Clone)]
-struct PromptArguments {
-    all: String,
-    positional: Vec<String>,
-}
-
-#[derive(Debug)]
-struct AppliedPrompt {
-    text: String,
-    used_all_arguments: bool,
-    positional_used: Vec<bool>,
-}
-
 #[derive(Clone, Debug, PartialEq)]
 struct AttachedImage {
     placeholder: String,
@@ -103,6 +93,11 @@ pub(crate) struct ChatComposer {
     disable_paste_burst: bool,
     custom_prompts: Vec<CustomPrompt>,
     agents: Vec<codex_core::protocol::AgentInfo>,
+    /// Recently used agent names, most recent first.
+    recent_agents: Vec<String>,
+    /// Whether a request for the agent list is still pending.
+    agents_loading: bool,
+    agent_popup_settings: AgentPopupSettings,
 }
 
 /// Popup state – at most one can be visible at any time.
@@ -148,6 +143,9 @@ impl ChatComposer {
             disable_paste_burst: false,
             custom_prompts: Vec::new(),
             agents: Vec::new(),
+            recent_agents: Vec::new(),
+            agents_loading: false,
+            agent_popup_settings: AgentPopupSettings::default(),
         };
         // Apply configuration via the setter to keep side-effects centralized.
         this.set_disable_paste_burst(disable_paste_burst);
@@ -337,9 +335,53 @@ impl ChatComposer {
 
     pub(crate) fn set_agents(&mut self, agents: Vec<codex_core::protocol::AgentInfo>) {
         self.agents = agents;
+        match &mut self.active_popup {
+            ActivePopup::Agent(popup) => {
+                if let Some(current) = Self::current_at_token(&self.textarea) {
+                    popup.set_query(&current, &self.agents, true);
+                }
+            }
+            ActivePopup::Command(popup) => popup.set_agents(self.agents.clone()),
+            _ => {}
+        }
+    }
+
+    /// Configure the @agent popup, including the token prefix that opens it.
+    pub(crate) fn set_agent_popup_settings(&mut self, settings: AgentPopupSettings) {
+        self.agent_popup_settings = settings;
+    }
+
+    /// Mark agents as being (re)loaded, so the @agent popup says so instead of looking
+    /// empty.
+    pub(crate) fn set_agents_loading(&mut self, loading: bool) {
+        self.agents_loading = loading;
         if let ActivePopup::Agent(popup) = &mut self.active_popup {
+            popup.set_loading(loading);
+        }
+    }
+
+    /// An empty @agent popup set up from the configured settings.
+    fn new_agent_popup(&self) -> AgentPopup {
+        let settings = &self.agent_popup_settings;
+        let mut popup = AgentPopup::new();
+        popup.set_prefix(&settings.prefix);
+        popup.set_wrap(settings.wrap);
+        popup.set_show_index(settings.show_index);
+        popup.set_loading(self.agents_loading);
+        if let Some(max_rows) = settings.max_rows {
+            popup.set_max_rows(max_rows);
+        }
+        popup.set_recent(&self.recent_agents);
+        popup
+    }
+
+    /// Update the recently used agents listed first in the @agent popup.
+    pub(crate) fn set_recent_agents(&mut self, recent: Vec<String>) {
+        self.recent_agents = recent;
+        if let ActivePopup::Agent(popup) = &mut self.active_popup {
+            popup.set_recent(&self.recent_agents);
             if let Some(current) = Self::current_at_token(&self.textarea) {
-                popup.set_query(&current, &self.agents);
+                popup.set_query(&current, &self.agents, true);
             }
         }
     }
@@ -437,6 +479,16 @@ impl ChatComposer {
                                 }
                             }
                         }
+                        CommandItem::Agent(idx) => {
+                            // Completing an agent turns the command into an agent mention.
+                            if let Some(name) = popup.agent_name(idx).map(str::to_string) {
+                                self.active_popup = ActivePopup::None;
+                                self.textarea.set_text("");
+                                self.insert_selected_agent(&name);
+                                self.app_event_tx.send(AppEvent::AgentSelected(name));
+                                return (InputResult::None, true);
+                            }
+                        }
                     }
                     // After completing the command, move cursor to the end.
                     if !self.textarea.text().is_empty() {
@@ -459,22 +511,14 @@ impl ChatComposer {
                     .unwrap_or("")
                     .to_string();
                 if let Some(sel) = popup.selected_item() {
-                    let (prompt_name, prompt_content) = match sel {
-                        CommandItem::UserPrompt(idx) => (
-                            popup.prompt_name(idx).map(str::to_string),
-                            popup.prompt_content(idx).map(str::to_string),
-                        ),
-                        _ => (None, None),
+                    // Capture the selected prompt or agent before the popup is cleared.
+                    let prompt = popup.selected_prompt().cloned();
+                    let agent = match sel {
+                        CommandItem::Agent(idx) => popup.agent_name(idx).map(str::to_string),
+                        CommandItem::Builtin(_) | CommandItem::UserPrompt(_) => None,
                     };
                     // Clear textarea so no residual text remains.
                     self.textarea.set_text("");
-                    // Capture any needed data from popup before clearing it.
-                    let prompt_content = match sel {
-                        CommandItem::UserPrompt(idx) => {
-                            prompt_content.or_else(|| popup.prompt_content(idx).map(str::to_string))
-                        }
-                        _ => None,
-                    };
                     // Hide popup since an action has been dispatched.
                     self.active_popup = ActivePopup::None;
 
@@ -483,51 +527,23 @@ impl ChatComposer {
                             return (InputResult::Command(cmd), true);
                         }
                         CommandItem::UserPrompt(_) => {
-                            if let Some(contents) = prompt_content {
-                                let args = PromptArguments::from_command_line(
+                            if let Some(prompt) = prompt {
+                                let args = prompt_arguments_from_command_line(
                                     &command_line,
-                                    prompt_name.as_deref(),
+                                    Some(&prompt.name),
                                 );
-                                let applied = apply_prompt_arguments(&contents, &args);
-                                let mut filled = applied.text;
-
-                                if !applied.used_all_arguments {
-                                    let leftovers = args
-                                        .positional
-                                        .iter()
-                                        .enumerate()
-                                        .filter_map(|(idx, token)| {
-                                            let used = applied
-                                                .positional_used
-                                                .get(idx)
-                                                .copied()
-                                                .unwrap_or(false);
-                                            if used || token.is_empty() {
-                                                None
-                                            } else {
-                                                Some(token.as_str())
-                                            }
-                                        })
-                                        .collect::<Vec<_>>()
-                                        .join(" ");
-
-                                    if !leftovers.is_empty() {
-                                        let needs_space = filled
-                                            .chars()
-                                            .last()
-                                            .map(|c| !c.is_whitespace())
-                                            .unwrap_or(false);
-                                        if needs_space && !filled.is_empty() {
-                                            filled.push(' ');
-                                        }
-                                        filled.push_str(&leftovers);
-                                    }
-                                }
-
+                                let filled = expand_prompt(&prompt.content, &args);
                                 return (InputResult::Submitted(filled), true);
                             }
                             return (InputResult::None, true);
                         }
+                        CommandItem::Agent(_) => {
+                            if let Some(name) = agent {
+                                self.insert_selected_agent(&name);
+                                self.app_event_tx.send(AppEvent::AgentSelected(name));
+                            }
+                            return (InputResult::None, true);
+                        }
                     }
                 }
                 // Fallback to default newline handling if no command selected.
@@ -684,6 +700,33 @@ impl ChatComposer {
                 popup.move_down();
                 (InputResult::None, true)
             }
+            KeyEvent {
+                code: KeyCode::PageUp,
+                ..
+            } => {
+                popup.page_up();
+                (InputResult::None, true)
+            }
+            KeyEvent {
+                code: KeyCode::PageDown,
+                ..
+            } => {
+                popup.page_down();
+                (InputResult::None, true)
+            }
+            KeyEvent {
+                code: KeyCode::Home,
+                ..
+            } => {
+                popup.move_to_first();
+                (InputResult::None, true)
+            }
+            KeyEvent {
+                code: KeyCode::End, ..
+            } => {
+                popup.move_to_last();
+                (InputResult::None, true)
+            }
             KeyEvent {
                 code: KeyCode::Esc, ..
             } => {
@@ -704,6 +747,7 @@ impl ChatComposer {
                 self.active_popup = ActivePopup::None;
                 if let Some(name) = selected {
                     self.insert_selected_agent(&name);
+                    self.app_event_tx.send(AppEvent::AgentSelected(name));
                     return (InputResult::None, true);
                 }
                 (InputResult::None, true)
@@ -1327,7 +1371,10 @@ impl ChatComposer {
             }
             _ => {
                 if input_starts_with_slash {
+                    // Agents already loaded (e.g. by /agents or the @agent popup) are
+                    // listed next to the prompts.
                     let mut command_popup = CommandPopup::new(self.custom_prompts.clone());
+                    command_popup.set_agents(self.agents.clone());
                     command_popup.on_composer_text_change(first_line.to_string());
                     self.active_popup = ActivePopup::Command(command_popup);
                 }
@@ -1361,9 +1408,11 @@ impl ChatComposer {
         }
 
         // Behavior:
-        // - Plain "@" or any non-"agent" token => file search popup
-        // - "@agent" prefix => agent suggestions popup
-        let looks_like_agent = query.to_ascii_lowercase().starts_with("agent");
+        // - Plain "@" or any token without the agent prefix => file search popup
+        // - "@agent" (or the configured prefix) => agent suggestions popup
+        let looks_like_agent = query
+            .to_lowercase()
+            .starts_with(&self.agent_popup_settings.prefix.to_lowercase());
         if !looks_like_agent {
             if !query.is_empty() {
                 self.app_event_tx
@@ -1394,11 +1443,11 @@ impl ChatComposer {
             }
             match &mut self.active_popup {
                 ActivePopup::Agent(popup) => {
-                    popup.set_query(&query, &self.agents);
+                    popup.set_query(&query, &self.agents, true);
                 }
                 _ => {
-                    let mut popup = AgentPopup::new();
-                    popup.set_query(&query, &self.agents);
+                    let mut popup = self.new_agent_popup();
+                    popup.set_query(&query, &self.agents, true);
                     self.active_popup = ActivePopup::Agent(popup);
                 }
             }
@@ -1421,153 +1470,37 @@ impl ChatComposer {
     }
 }
 
-impl PromptArguments {
-    fn from_command_line(line: &str, prompt_name: Option<&str>) -> Self {
-        if line.is_empty() {
-            return Self::default();
-        }
-
-        let trimmed = line.trim();
-        let without_slash = trimmed.strip_prefix('/').unwrap_or(trimmed);
-        let after_slash = without_slash.trim_start();
-
-        let mut first_space_idx = None;
-        for (idx, ch) in after_slash.char_indices() {
-            if ch.is_whitespace() {
-                first_space_idx = Some(idx);
-                break;
-            }
-        }
-
-        let (_typed_command, remainder) = match first_space_idx {
-            Some(idx) => (&after_slash[..idx], &after_slash[idx..]),
-            None => (after_slash, ""),
-        };
-
-        let args_slice = if let Some(name) = prompt_name {
-            if let Some(rest) = after_slash.strip_prefix(name) {
-                rest
-            } else {
-                remainder
-            }
-        } else {
-            remainder
-        };
-
-        let args_trimmed_start = args_slice.trim_start();
-        let all_args = args_trimmed_start.trim_end().to_string();
-        if all_args.is_empty() {
-            return Self::default();
-        }
-
-        let positional = split_prompt_arguments(args_trimmed_start);
-        Self {
-            all: all_args,
-            positional,
-        }
-    }
-}
+/// Parse the arguments typed after a custom prompt's command on `line`.
+fn prompt_arguments_from_command_line(line: &str, prompt_name: Option<&str>) -> PromptArguments {
+    let trimmed = line.trim();
+    let without_slash = trimmed.strip_prefix('/').unwrap_or(trimmed);
+    let after_slash = without_slash.trim_start();
 
-fn split_prompt_arguments(input: &str) -> Vec<String> {
-    let mut result: Vec<String> = Vec::new();
-    let mut current = String::new();
-    let mut chars = input.chars().peekable();
-    let mut in_quote: Option<char> = None;
-
-    while let Some(ch) = chars.next() {
-        if let Some(quote) = in_quote {
-            if ch == quote {
-                in_quote = None;
-            } else {
-                current.push(ch);
-            }
-            continue;
-        }
-
-        match ch {
-            '"' | '\'' => {
-                in_quote = Some(ch);
-            }
-            c if c.is_whitespace() => {
-                if !current.is_empty() {
-                    result.push(current.clone());
-                    current.clear();
-                }
-            }
-            _ => current.push(ch),
+    let mut first_space_idx = None;
+    for (idx, ch) in after_slash.char_indices() {
+        if ch.is_whitespace() {
+            first_space_idx = Some(idx);
+            break;
         }
     }
 
-    if !current.is_empty() {
-        result.push(current);
-    }
-
-    result
-}
-
-fn apply_prompt_arguments(template: &str, args: &PromptArguments) -> AppliedPrompt {
-    const ARGUMENTS_TOKEN: &str = "ARGUMENTS";
-    let mut output = String::with_capacity(template.len() + args.all.len());
-    let mut positional_used = vec![false; args.positional.len()];
-    let mut used_all_arguments = false;
-    let bytes = template.as_bytes();
-    let mut idx = 0;
-
-    while idx < bytes.len() {
-        if bytes[idx] == b'$' {
-            let mut handled = false;
-            if idx + 1 + ARGUMENTS_TOKEN.len() <= bytes.len()
-                && &template[idx + 1..idx + 1 + ARGUMENTS_TOKEN.len()] == ARGUMENTS_TOKEN
-            {
-                output.push_str(&args.all);
-                idx += 1 + ARGUMENTS_TOKEN.len();
-                used_all_arguments = true;
-                handled = true;
-            } else {
-                let mut digit_end = idx + 1;
-                while digit_end < bytes.len() && bytes[digit_end].is_ascii_digit() {
-                    digit_end += 1;
-                }
-                if digit_end > idx + 1 {
-                    if let Ok(position) = template[idx + 1..digit_end].parse::<usize>() {
-                        let replacement = if position == 0 {
-                            ""
-                        } else {
-                            let token_index = position.saturating_sub(1);
-                            if let Some(token) = args.positional.get(token_index) {
-                                positional_used[token_index] = true;
-                                token.as_str()
-                            } else {
-                                ""
-                            }
-                        };
-                        output.push_str(replacement);
-                        idx = digit_end;
-                        handled = true;
-                    }
-                }
-            }
+    let (_typed_command, remainder) = match first_space_idx {
+        Some(idx) => (&after_slash[..idx], &after_slash[idx..]),
+        None => (after_slash, ""),
+    };
 
-            if handled {
-                continue;
-            }
-
-            output.push('$');
-            idx += 1;
+    let args_slice = if let Some(name) = prompt_name {
+        if let Some(rest) = after_slash.strip_prefix(name) {
+            rest
         } else {
-            let ch = template[idx..].chars().next().unwrap();
-            output.push(ch);
-            idx += ch.len_utf8();
+            remainder
         }
-    }
+    } else {
+        remainder
+    };
 
-    AppliedPrompt {
-        text: output,
-        used_all_arguments,
-        positional_used,
-    }
+    PromptArguments::parse(args_slice)
 }
-
 impl WidgetRef for ChatComposer {
     fn render_ref(&self, area: Rect, buf: &mut Buffer) {
         let (popup_constraint, hint_spacing) = match &self.active_popup {
@@ -1711,6 +1644,7 @@ impl WidgetRef for ChatComposer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use codex_core::protocol::AgentInfo;
     use image::ImageBuffer;
     use image::Rgba;
     use pretty_assertions::assert_eq;
@@ -2157,6 +2091,9 @@ mod tests {
                 Some(CommandItem::UserPrompt(_)) => {
                     panic!("unexpected prompt selected for '/mo'")
                 }
+                Some(CommandItem::Agent(_)) => {
+                    panic!("unexpected agent selected for '/mo'")
+                }
                 None => panic!("no selected command for '/mo'"),
             },
             _ => panic!("slash popup not active after typing '/mo'"),
@@ -2292,6 +2229,7 @@ mod tests {
             name: "fix-issue".to_string(),
             path: PathBuf::from("/tmp/fix-issue.md"),
             content: "Fix issue #$ARGUMENTS".to_string(),
+            ..Default::default()
         }]);
 
         type_chars_humanlike(
@@ -2338,6 +2276,7 @@ mod tests {
             path: PathBuf::from("/tmp/review-pr.md"),
             content: "Review PR #$1 with priority $2 and assign to $3. Remaining: $ARGUMENTS"
                 .to_string(),
+            ..Default::default()
         }]);
 
         type_chars_humanlike(
@@ -2382,6 +2321,7 @@ mod tests {
             name: "review-pr".to_string(),
             path: PathBuf::from("/tmp/review-pr.md"),
             content: "Review PR #$1 with priority $2 and assign to $3.".to_string(),
+            ..Default::default()
         }]);
 
         type_chars_humanlike(
@@ -2407,6 +2347,137 @@ mod tests {
         }
     }
 
+    #[test]
+    fn configured_agent_prefix_opens_the_agent_popup() {
+        let (tx, _rx) = unbounded_channel::<AppEvent>();
+        let sender = AppEventSender::new(tx);
+        let mut composer = ChatComposer::new(
+            true,
+            sender,
+            false,
+            "Ask Codex to do anything".to_string(),
+            false,
+        );
+        composer.set_agent_popup_settings(AgentPopupSettings {
+            prefix: "Bot".to_string(),
+            ..Default::default()
+        });
+
+        type_chars_humanlike(&mut composer, &['@', 'b', 'o', 't']);
+        assert!(matches!(composer.active_popup, ActivePopup::Agent(_)));
+
+        composer.textarea.set_text("");
+        type_chars_humanlike(&mut composer, &['@', 'a', 'g', 'e', 'n', 't']);
+        assert!(matches!(composer.active_popup, ActivePopup::File(_)));
+    }
+
+    #[test]
+    fn agent_popup_shows_loading_while_agents_are_requested() {
+        let (tx, _rx) = unbounded_channel::<AppEvent>();
+        let sender = AppEventSender::new(tx);
+        let mut composer = ChatComposer::new(
+            true,
+            sender,
+            false,
+            "Ask Codex to do anything".to_string(),
+            false,
+        );
+        let popup_text = |composer: &ChatComposer| {
+            let area = Rect::new(0, 0, 40, 6);
+            let mut buf = Buffer::empty(area);
+            composer.render_ref(area, &mut buf);
+            (0..area.height)
+                .map(|y| (0..area.width).map(|x| buf[(x, y)].symbol()).collect())
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        composer.set_agents_loading(true);
+        type_chars_humanlike(&mut composer, &['@', 'a', 'g', 'e', 'n', 't']);
+        assert!(popup_text(&composer).contains("loading agents…"));
+
+        composer.set_agents_loading(false);
+        assert!(popup_text(&composer).contains("no agents configured"));
+    }
+
+    #[test]
+    fn home_and_end_jump_to_the_ends_of_the_agent_popup() {
+        use crossterm::event::KeyCode;
+        use crossterm::event::KeyEvent;
+        use crossterm::event::KeyModifiers;
+
+        let (tx, _rx) = unbounded_channel::<AppEvent>();
+        let sender = AppEventSender::new(tx);
+        let mut composer = ChatComposer::new(
+            true,
+            sender,
+            false,
+            "Ask Codex to do anything".to_string(),
+            false,
+        );
+        composer.set_agents(
+            ["alpha", "beta", "gamma"]
+                .into_iter()
+                .map(|name| AgentInfo {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        type_chars_humanlike(&mut composer, &['@', 'a', 'g', 'e', 'n', 't']);
+
+        let selected = |composer: &ChatComposer| match &composer.active_popup {
+            ActivePopup::Agent(popup) => popup.selected_agent().map(str::to_string),
+            _ => panic!("expected the agent popup"),
+        };
+        composer.handle_key_event(KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+        assert_eq!(selected(&composer).as_deref(), Some("gamma"));
+        composer.handle_key_event(KeyEvent::new(KeyCode::Home, KeyModifiers::NONE));
+        assert_eq!(selected(&composer).as_deref(), Some("alpha"));
+    }
+
+    #[test]
+    fn slash_prompt_substitutes_named_arguments() {
+        use crossterm::event::KeyCode;
+        use crossterm::event::KeyEvent;
+        use crossterm::event::KeyModifiers;
+
+        let (tx, _rx) = unbounded_channel::<AppEvent>();
+        let sender = AppEventSender::new(tx);
+        let mut composer = ChatComposer::new(
+            true,
+            sender,
+            false,
+            "Ask Codex to do anything".to_string(),
+            false,
+        );
+
+        composer.set_custom_prompts(vec![CustomPrompt {
+            name: "rebase".to_string(),
+            path: PathBuf::from("/tmp/rebase.md"),
+            content: "Rebase {{branch}} onto {{base|main}}.".to_string(),
+            ..Default::default()
+        }]);
+
+        type_chars_humanlike(
+            &mut composer,
+            &[
+                '/', 'r', 'e', 'b', 'a', 's', 'e', ' ', 'b', 'r', 'a', 'n', 'c', 'h', '=', 'f',
+                'i', 'x',
+            ],
+        );
+
+        let (result, _needs_redraw) =
+            composer.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+
+        match result {
+            InputResult::Submitted(text) => {
+                assert_eq!(text, "Rebase fix onto main.");
+            }
+            other => panic!("expected submitted prompt, got {other:?}"),
+        }
+    }
+
     #[test]
     fn slash_prompt_without_placeholders_appends_arguments() {
         use crossterm::event::KeyCode;
@@ -2427,6 +2498,7 @@ mod tests {
             name: "summary".to_string(),
             path: PathBuf::from("/tmp/summary.md"),
             content: "Please summarize the following:".to_string(),
+            ..Default::default()
         }]);
 
         type_chars_humanlike(
@@ -2853,6 +2925,7 @@ mod tests {
             name: "my-prompt".to_string(),
             path: "/tmp/my-prompt.md".to_string().into(),
             content: prompt_text.to_string(),
+            ..Default::default()
         }]);
 
         type_chars_humanlike(
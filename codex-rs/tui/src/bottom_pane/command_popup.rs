@@ -9,56 +9,98 @@ use super::selection_popup_common::render_rows;
 use crate::slash_command::SlashCommand;
 use crate::slash_command::built_in_slash_commands;
 use codex_common::fuzzy_match::fuzzy_match;
+use codex_core::command_menu::CommandMenu;
+use codex_core::command_menu::MenuEntry;
+use codex_core::command_menu::merge_menu_entries;
+use codex_core::protocol::AgentInfo;
 use codex_protocol::custom_prompts::CustomPrompt;
 use std::collections::HashSet;
 
-/// A selectable item in the popup: either a built-in command or a user prompt.
+/// A selectable item in the popup: a built-in command, a user prompt or an agent.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum CommandItem {
     Builtin(SlashCommand),
-    // Index into `prompts`
+    // Index into `menu.entries`
     UserPrompt(usize),
+    // Index into `menu.entries`
+    Agent(usize),
 }
 
 pub(crate) struct CommandPopup {
     command_filter: String,
     builtins: Vec<(&'static str, SlashCommand)>,
     prompts: Vec<CustomPrompt>,
+    agents: Vec<AgentInfo>,
+    /// Prompts and agents merged into one list sorted by name
+    menu: CommandMenu,
     state: ScrollState,
 }
 
 impl CommandPopup {
-    pub(crate) fn new(mut prompts: Vec<CustomPrompt>) -> Self {
-        let builtins = built_in_slash_commands();
-        // Exclude prompts that collide with builtin command names and sort by name.
-        let exclude: HashSet<String> = builtins.iter().map(|(n, _)| (*n).to_string()).collect();
-        prompts.retain(|p| !exclude.contains(&p.name));
-        prompts.sort_by(|a, b| a.name.cmp(&b.name));
-        Self {
+    pub(crate) fn new(prompts: Vec<CustomPrompt>) -> Self {
+        let mut popup = Self {
             command_filter: String::new(),
-            builtins,
-            prompts,
+            builtins: built_in_slash_commands(),
+            prompts: Vec::new(),
+            agents: Vec::new(),
+            menu: CommandMenu::default(),
             state: ScrollState::new(),
-        }
+        };
+        popup.set_prompts(prompts);
+        popup
     }
 
     pub(crate) fn set_prompts(&mut self, mut prompts: Vec<CustomPrompt>) {
-        let exclude: HashSet<String> = self
-            .builtins
-            .iter()
-            .map(|(n, _)| (*n).to_string())
-            .collect();
+        // Exclude prompts that collide with builtin command names.
+        let exclude = self.builtin_names();
         prompts.retain(|p| !exclude.contains(&p.name));
-        prompts.sort_by(|a, b| a.name.cmp(&b.name));
         self.prompts = prompts;
+        self.rebuild_menu();
+    }
+
+    /// List `agents` next to the prompts; an agent named like a prompt hides the prompt.
+    pub(crate) fn set_agents(&mut self, mut agents: Vec<AgentInfo>) {
+        let exclude = self.builtin_names();
+        agents.retain(|a| !exclude.contains(&a.name));
+        self.agents = agents;
+        self.rebuild_menu();
+    }
+
+    fn builtin_names(&self) -> HashSet<String> {
+        self.builtins
+            .iter()
+            .map(|(n, _)| (*n).to_string())
+            .collect()
+    }
+
+    fn rebuild_menu(&mut self) {
+        self.menu = merge_menu_entries(&self.agents, &self.prompts);
     }
 
     pub(crate) fn prompt_name(&self, idx: usize) -> Option<&str> {
-        self.prompts.get(idx).map(|p| p.name.as_str())
+        match self.menu.entries.get(idx)? {
+            MenuEntry::Prompt(prompt) => Some(prompt.name.as_str()),
+            MenuEntry::Agent(_) => None,
+        }
+    }
+
+    pub(crate) fn agent_name(&self, idx: usize) -> Option<&str> {
+        match self.menu.entries.get(idx)? {
+            MenuEntry::Agent(agent) => Some(agent.name.as_str()),
+            MenuEntry::Prompt(_) => None,
+        }
     }
 
-    pub(crate) fn prompt_content(&self, idx: usize) -> Option<&str> {
-        self.prompts.get(idx).map(|p| p.content.as_str())
+    /// The selected custom prompt, including its full content; `None` when a built-in
+    /// command, an agent (or nothing) is selected.
+    pub(crate) fn selected_prompt(&self) -> Option<&CustomPrompt> {
+        match self.selected_item()? {
+            CommandItem::UserPrompt(idx) => {
+                let name = self.prompt_name(idx)?;
+                self.prompts.iter().find(|p| p.name == name)
+            }
+            CommandItem::Builtin(_) | CommandItem::Agent(_) => None,
+        }
     }
 
     /// Update the filter string based on the current composer text. The text
@@ -101,8 +143,8 @@ impl CommandPopup {
         measure_rows_height(&rows, &self.state, MAX_POPUP_ROWS, width)
     }
 
-    /// Compute fuzzy-filtered matches over built-in commands and user prompts,
-    /// paired with optional highlight indices and score. Sorted by ascending
+    /// Compute fuzzy-filtered matches over built-in commands, user prompts and agents,
+    /// paired with optional highlight indices and score. Sorted by descending
     /// score, then by name for stability.
     fn filtered(&self) -> Vec<(CommandItem, Option<Vec<usize>>, i32)> {
         let filter = self.command_filter.trim();
@@ -112,33 +154,37 @@ impl CommandPopup {
             for (_, cmd) in self.builtins.iter() {
                 out.push((CommandItem::Builtin(*cmd), None, 0));
             }
-            // Then prompts, already sorted by name.
-            for idx in 0..self.prompts.len() {
-                out.push((CommandItem::UserPrompt(idx), None, 0));
+            // Then prompts and agents, already sorted by name.
+            for idx in 0..self.menu.entries.len() {
+                out.push((self.entry_item(idx), None, 0));
             }
             return out;
         }
 
         for (_, cmd) in self.builtins.iter() {
-            if let Some((indices, score)) = fuzzy_match(cmd.command(), filter) {
+            if let Some((score, indices)) = fuzzy_match(cmd.command(), filter) {
                 out.push((CommandItem::Builtin(*cmd), Some(indices), score));
             }
         }
-        for (idx, p) in self.prompts.iter().enumerate() {
-            if let Some((indices, score)) = fuzzy_match(&p.name, filter) {
-                out.push((CommandItem::UserPrompt(idx), Some(indices), score));
+        for (idx, entry) in self.menu.entries.iter().enumerate() {
+            if let Some((score, indices)) = fuzzy_match(entry.name(), filter) {
+                out.push((self.entry_item(idx), Some(indices), score));
             }
         }
-        // When filtering, sort by ascending score and then by name for stability.
+        // When filtering, sort by descending score and then by name for stability.
         out.sort_by(|a, b| {
-            a.2.cmp(&b.2).then_with(|| {
+            b.2.cmp(&a.2).then_with(|| {
                 let an = match a.0 {
                     CommandItem::Builtin(c) => c.command(),
-                    CommandItem::UserPrompt(i) => &self.prompts[i].name,
+                    CommandItem::UserPrompt(i) | CommandItem::Agent(i) => {
+                        self.menu.entries[i].name()
+                    }
                 };
                 let bn = match b.0 {
                     CommandItem::Builtin(c) => c.command(),
-                    CommandItem::UserPrompt(i) => &self.prompts[i].name,
+                    CommandItem::UserPrompt(i) | CommandItem::Agent(i) => {
+                        self.menu.entries[i].name()
+                    }
                 };
                 an.cmp(bn)
             })
@@ -146,6 +192,13 @@ impl CommandPopup {
         out
     }
 
+    fn entry_item(&self, idx: usize) -> CommandItem {
+        match self.menu.entries[idx] {
+            MenuEntry::Agent(_) => CommandItem::Agent(idx),
+            MenuEntry::Prompt(_) => CommandItem::UserPrompt(idx),
+        }
+    }
+
     fn filtered_items(&self) -> Vec<CommandItem> {
         self.filtered().into_iter().map(|(c, _, _)| c).collect()
     }
@@ -161,15 +214,24 @@ impl CommandPopup {
                     CommandItem::Builtin(cmd) => {
                         (format!("/{}", cmd.command()), cmd.description().to_string())
                     }
-                    CommandItem::UserPrompt(i) => (
-                        format!("/{}", self.prompts[i].name),
-                        "send saved prompt".to_string(),
-                    ),
+                    CommandItem::UserPrompt(i) | CommandItem::Agent(i) => {
+                        let entry = &self.menu.entries[i];
+                        let description = match entry {
+                            MenuEntry::Prompt(prompt) => match prompt.argument_hint.as_deref() {
+                                Some(hint) => format!("send saved prompt · {hint}"),
+                                None => "send saved prompt".to_string(),
+                            },
+                            MenuEntry::Agent(agent) if !agent.description.is_empty() => {
+                                format!("{} · {}", entry.kind_label(), agent.description)
+                            }
+                            MenuEntry::Agent(_) => entry.kind_label().to_string(),
+                        };
+                        (format!("/{}", entry.name()), description)
+                    }
                 };
                 GenericDisplayRow {
                     name,
                     match_indices: indices.map(|v| v.into_iter().map(|i| i + 1).collect()),
-                    is_current: false,
                     description: Some(description),
                 }
             })
@@ -218,6 +280,7 @@ impl WidgetRef for CommandPopup {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pretty_assertions::assert_eq;
     use std::string::ToString;
 
     #[test]
@@ -232,7 +295,7 @@ mod tests {
         let matches = popup.filtered_items();
         let has_init = matches.iter().any(|item| match item {
             CommandItem::Builtin(cmd) => cmd.command() == "init",
-            CommandItem::UserPrompt(_) => false,
+            CommandItem::UserPrompt(_) | CommandItem::Agent(_) => false,
         });
         assert!(
             has_init,
@@ -251,6 +314,7 @@ mod tests {
         match selected {
             Some(CommandItem::Builtin(cmd)) => assert_eq!(cmd.command(), "init"),
             Some(CommandItem::UserPrompt(_)) => panic!("unexpected prompt selected for '/init'"),
+            Some(CommandItem::Agent(_)) => panic!("unexpected agent selected for '/init'"),
             None => panic!("expected a selected command for exact match"),
         }
     }
@@ -265,6 +329,9 @@ mod tests {
             Some(CommandItem::UserPrompt(_)) => {
                 panic!("unexpected prompt ranked before '/model' for '/mo'")
             }
+            Some(CommandItem::Agent(_)) => {
+                panic!("unexpected agent ranked before '/model' for '/mo'")
+            }
             None => panic!("expected at least one match for '/mo'"),
         }
     }
@@ -276,11 +343,13 @@ mod tests {
                 name: "foo".to_string(),
                 path: "/tmp/foo.md".to_string().into(),
                 content: "hello from foo".to_string(),
+                ..Default::default()
             },
             CustomPrompt {
                 name: "bar".to_string(),
                 path: "/tmp/bar.md".to_string().into(),
                 content: "hello from bar".to_string(),
+                ..Default::default()
             },
         ];
         let popup = CommandPopup::new(prompts);
@@ -303,6 +372,7 @@ mod tests {
             name: "init".to_string(),
             path: "/tmp/init.md".to_string().into(),
             content: "should be ignored".to_string(),
+            ..Default::default()
         }]);
         let items = popup.filtered_items();
         let has_collision_prompt = items.into_iter().any(|it| match it {
@@ -314,4 +384,141 @@ mod tests {
             "prompt with builtin name should be ignored"
         );
     }
+
+    #[test]
+    fn prompt_argument_hint_is_shown_in_description() {
+        let mut popup = CommandPopup::new(vec![CustomPrompt {
+            name: "fix".to_string(),
+            path: "/tmp/fix.md".to_string().into(),
+            content: "Fix $1".to_string(),
+            argument_hint: Some("<file> <line>".to_string()),
+            ..Default::default()
+        }]);
+        popup.on_composer_text_change("/fix".to_string());
+        let rows = popup.rows_from_matches(popup.filtered());
+        let descriptions: Vec<Option<&str>> = rows
+            .iter()
+            .filter(|row| row.name == "/fix")
+            .map(|row| row.description.as_deref())
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![Some("send saved prompt · <file> <line>")]
+        );
+    }
+
+    #[test]
+    fn selected_prompt_returns_the_filtered_prompt() {
+        let mut popup = CommandPopup::new(vec![
+            CustomPrompt {
+                name: "review-pr".to_string(),
+                content: "Review this PR".to_string(),
+                ..Default::default()
+            },
+            CustomPrompt {
+                name: "summarize".to_string(),
+                content: "Summarize".to_string(),
+                ..Default::default()
+            },
+        ]);
+        popup.on_composer_text_change("/summ".to_string());
+        assert_eq!(
+            popup.selected_prompt().map(|p| p.content.as_str()),
+            Some("Summarize")
+        );
+
+        popup.on_composer_text_change("/mo".to_string());
+        assert!(popup.selected_prompt().is_none());
+    }
+
+    fn agent(name: &str, description: &str) -> AgentInfo {
+        AgentInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn menu_rows(popup: &CommandPopup) -> Vec<(String, Option<String>)> {
+        popup
+            .rows_from_matches(popup.filtered())
+            .into_iter()
+            .filter(|row| {
+                popup
+                    .builtins
+                    .iter()
+                    .all(|(n, _)| row.name != format!("/{n}"))
+            })
+            .map(|row| (row.name, row.description))
+            .collect()
+    }
+
+    #[test]
+    fn agents_are_listed_with_prompts_sorted_by_name() {
+        let mut popup = CommandPopup::new(vec![
+            CustomPrompt {
+                name: "summarize".to_string(),
+                ..Default::default()
+            },
+            CustomPrompt {
+                name: "changelog".to_string(),
+                ..Default::default()
+            },
+        ]);
+        popup.set_agents(vec![
+            agent("reviewer", "Reviews diffs"),
+            agent("tester", ""),
+        ]);
+
+        assert_eq!(
+            menu_rows(&popup),
+            vec![
+                (
+                    "/changelog".to_string(),
+                    Some("send saved prompt".to_string())
+                ),
+                (
+                    "/reviewer".to_string(),
+                    Some("agent · Reviews diffs".to_string())
+                ),
+                (
+                    "/summarize".to_string(),
+                    Some("send saved prompt".to_string())
+                ),
+                ("/tester".to_string(), Some("agent".to_string())),
+            ]
+        );
+
+        popup.on_composer_text_change("/reviewer".to_string());
+        match popup.selected_item() {
+            Some(CommandItem::Agent(idx)) => assert_eq!(popup.agent_name(idx), Some("reviewer")),
+            other => panic!("expected the reviewer agent to be selected, got {other:?}"),
+        }
+        assert!(popup.selected_prompt().is_none());
+    }
+
+    #[test]
+    fn agent_hides_a_prompt_with_the_same_name() {
+        let mut popup = CommandPopup::new(vec![CustomPrompt {
+            name: "reviewer".to_string(),
+            content: "Review this".to_string(),
+            ..Default::default()
+        }]);
+        popup.set_agents(vec![agent("reviewer", "Reviews diffs")]);
+
+        assert_eq!(
+            menu_rows(&popup),
+            vec![(
+                "/reviewer".to_string(),
+                Some("agent · Reviews diffs".to_string())
+            )]
+        );
+        let shadowed: Vec<&str> = popup
+            .menu
+            .shadowed_prompts
+            .iter()
+            .map(|prompt| prompt.name.as_str())
+            .collect();
+        assert_eq!(shadowed, vec!["reviewer"]);
+    }
 }
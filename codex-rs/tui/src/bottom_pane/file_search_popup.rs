@@ -126,7 +126,6 @@ impl WidgetRef for &FileSearchPopup {
                         .indices
                         .as_ref()
                         .map(|v| v.iter().map(|&i| i as usize).collect()),
-                    is_current: false,
                     description: None,
                 })
                 .collect()
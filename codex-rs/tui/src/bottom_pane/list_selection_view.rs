@@ -171,7 +171,6 @@ impl ListSelectionView {
                     GenericDisplayRow {
                         name: display_name,
                         match_indices: None,
-                        is_current: item.is_current,
                         description: item.description.clone(),
                     }
                 })
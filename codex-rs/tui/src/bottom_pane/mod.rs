@@ -5,6 +5,7 @@ use crate::app_event_sender::AppEventSender;
 use crate::tui::FrameRequester;
 use crate::user_approval_widget::ApprovalRequest;
 use bottom_pane_view::BottomPaneView;
+use codex_core::config_types::AgentPopupSettings;
 use codex_core::protocol::TokenUsageInfo;
 use codex_file_search::FileMatch;
 use crossterm::event::KeyCode;
@@ -77,20 +78,23 @@ pub(crate) struct BottomPaneParams {
     pub(crate) enhanced_keys_supported: bool,
     pub(crate) placeholder_text: String,
     pub(crate) disable_paste_burst: bool,
+    pub(crate) agent_popup: AgentPopupSettings,
 }
 
 impl BottomPane {
     const BOTTOM_PAD_LINES: u16 = 1;
     pub fn new(params: BottomPaneParams) -> Self {
         let enhanced_keys_supported = params.enhanced_keys_supported;
+        let mut composer = ChatComposer::new(
+            params.has_input_focus,
+            params.app_event_tx.clone(),
+            enhanced_keys_supported,
+            params.placeholder_text,
+            params.disable_paste_burst,
+        );
+        composer.set_agent_popup_settings(params.agent_popup);
         Self {
-            composer: ChatComposer::new(
-                params.has_input_focus,
-                params.app_event_tx.clone(),
-                enhanced_keys_supported,
-                params.placeholder_text,
-                params.disable_paste_burst,
-            ),
+            composer,
             view_stack: Vec::new(),
             app_event_tx: params.app_event_tx,
             frame_requester: params.frame_requester,
@@ -363,6 +367,18 @@ impl BottomPane {
         self.request_redraw();
     }
 
+    /// Mark agents as being (re)loaded for the @agent popup.
+    pub(crate) fn set_agents_loading(&mut self, loading: bool) {
+        self.composer.set_agents_loading(loading);
+        self.request_redraw();
+    }
+
+    /// Update the recently used agents shown first in the @agent popup.
+    pub(crate) fn set_recent_agents(&mut self, recent: Vec<String>) {
+        self.composer.set_recent_agents(recent);
+        self.request_redraw();
+    }
+
     /// List known agent names (for intent detection).
     pub(crate) fn agent_names(&self) -> Vec<String> {
         self.composer.agent_names()
@@ -539,6 +555,7 @@ mod tests {
             enhanced_keys_supported: false,
             placeholder_text: "Ask Codex to do anything".to_string(),
             disable_paste_burst: false,
+            agent_popup: AgentPopupSettings::default(),
         });
         pane.push_approval_request(exec_request());
         assert_eq!(CancellationEvent::Handled, pane.on_ctrl_c());
@@ -559,6 +576,7 @@ mod tests {
             enhanced_keys_supported: false,
             placeholder_text: "Ask Codex to do anything".to_string(),
             disable_paste_burst: false,
+            agent_popup: AgentPopupSettings::default(),
         });
 
         // Create an approval modal (active view).
@@ -590,6 +608,7 @@ mod tests {
             enhanced_keys_supported: false,
             placeholder_text: "Ask Codex to do anything".to_string(),
             disable_paste_burst: false,
+            agent_popup: AgentPopupSettings::default(),
         });
 
         // Start a running task so the status indicator is active above the composer.
@@ -658,6 +677,7 @@ mod tests {
             enhanced_keys_supported: false,
             placeholder_text: "Ask Codex to do anything".to_string(),
             disable_paste_burst: false,
+            agent_popup: AgentPopupSettings::default(),
         });
 
         // Begin a task: show initial status.
@@ -689,6 +709,7 @@ mod tests {
             enhanced_keys_supported: false,
             placeholder_text: "Ask Codex to do anything".to_string(),
             disable_paste_burst: false,
+            agent_popup: AgentPopupSettings::default(),
         });
 
         // Activate spinner (status view replaces composer) with no live ring.
@@ -740,6 +761,7 @@ mod tests {
             enhanced_keys_supported: false,
             placeholder_text: "Ask Codex to do anything".to_string(),
             disable_paste_burst: false,
+            agent_popup: AgentPopupSettings::default(),
         });
 
         pane.set_task_running(true);
@@ -62,6 +62,50 @@ impl ScrollState {
         });
     }
 
+    /// Move selection up by one, stopping at the first item.
+    pub fn move_up_clamped(&mut self, len: usize) {
+        self.page_up(len, 1);
+    }
+
+    /// Move selection down by one, stopping at the last item.
+    pub fn move_down_clamped(&mut self, len: usize) {
+        self.page_down(len, 1);
+    }
+
+    /// Move selection up by `rows`, stopping at the first item.
+    pub fn page_up(&mut self, len: usize, rows: usize) {
+        self.clamp_selection(len);
+        if let Some(idx) = self.selected_idx.as_mut() {
+            *idx = idx.saturating_sub(rows);
+        }
+    }
+
+    /// Move selection down by `rows`, stopping at the last item.
+    pub fn page_down(&mut self, len: usize, rows: usize) {
+        self.clamp_selection(len);
+        if let Some(idx) = self.selected_idx.as_mut() {
+            *idx = idx.saturating_add(rows).min(len - 1);
+        }
+    }
+
+    /// Select the first item, or nothing when empty.
+    pub fn select_first(&mut self, len: usize) {
+        self.selected_idx = (len > 0).then_some(0);
+    }
+
+    /// Select the last item, or nothing when empty.
+    pub fn select_last(&mut self, len: usize) {
+        self.selected_idx = len.checked_sub(1);
+    }
+
+    /// Whether items are hidden above and below the window of `visible_rows` items
+    /// that starts at `scroll_top`, as `(above, below)`.
+    pub fn hidden_above_below(&self, len: usize, visible_rows: usize) -> (bool, bool) {
+        let above = len > 0 && self.scroll_top > 0;
+        let below = self.scroll_top.saturating_add(visible_rows) < len;
+        (above, below)
+    }
+
     /// Adjust `scroll_top` so that the current `selected_idx` is visible within
     /// the window of `visible_rows`.
     pub fn ensure_visible(&mut self, len: usize, visible_rows: usize) {
@@ -112,4 +156,27 @@ mod tests {
         assert_eq!(s.selected_idx, Some(0));
         assert_eq!(s.scroll_top, 0);
     }
+
+    #[test]
+    fn paging_clamps_without_wrapping() {
+        let mut s = ScrollState::new();
+        let len = 10;
+
+        s.page_down(len, 4);
+        assert_eq!(s.selected_idx, Some(4));
+        s.page_down(len, 4);
+        s.page_down(len, 4);
+        assert_eq!(s.selected_idx, Some(len - 1));
+        s.page_up(len, 4);
+        assert_eq!(s.selected_idx, Some(5));
+        s.page_up(len, 100);
+        assert_eq!(s.selected_idx, Some(0));
+
+        s.select_last(len);
+        assert_eq!(s.selected_idx, Some(len - 1));
+        s.select_first(len);
+        assert_eq!(s.selected_idx, Some(0));
+        s.select_last(0);
+        assert_eq!(s.selected_idx, None);
+    }
 }
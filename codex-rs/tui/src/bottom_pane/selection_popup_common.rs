@@ -16,13 +16,15 @@ use ratatui::widgets::Widget;
 use unicode_width::UnicodeWidthChar;
 
 use super::scroll_state::ScrollState;
+use crate::render::line_utils::line_to_static;
 use crate::ui_consts::LIVE_PREFIX_COLS;
+use crate::wrapping::RtOptions;
+use crate::wrapping::word_wrap_line;
 
 /// A generic representation of a display row for selection popups.
 pub(crate) struct GenericDisplayRow {
     pub name: String,
     pub match_indices: Option<Vec<usize>>, // indices to bold (char positions)
-    pub is_current: bool,
     pub description: Option<String>, // optional grey text after the name
 }
 
@@ -111,17 +113,35 @@ fn build_full_line(row: &GenericDisplayRow, desc_col: usize) -> Line<'static> {
     Line::from(full_spans)
 }
 
-/// Render a list of rows using the provided ScrollState, with shared styling
-/// and behavior for selection popups.
-pub(crate) fn render_rows(
-    area: Rect,
-    buf: &mut Buffer,
-    rows_all: &[GenericDisplayRow],
-    state: &ScrollState,
-    max_results: usize,
-    _dim_non_selected: bool,
-    empty_message: &str,
-) {
+/// Wrap a row to `width`, aligning continuation lines under the description column.
+/// Keeps at most `max_lines` lines, ending the last one with an ellipsis when text was
+/// cut.
+fn wrap_row(
+    row: &GenericDisplayRow,
+    desc_col: usize,
+    width: u16,
+    max_lines: usize,
+) -> Vec<Line<'static>> {
+    let full_line = build_full_line(row, desc_col);
+    let options = RtOptions::new(width as usize)
+        .initial_indent(Line::from(""))
+        .subsequent_indent(Line::from(" ".repeat(desc_col)));
+    let wrapped = word_wrap_line(&full_line, options);
+    let truncated = wrapped.len() > max_lines.max(1);
+    let mut lines: Vec<Line<'static>> = wrapped
+        .iter()
+        .take(max_lines.max(1))
+        .map(line_to_static)
+        .collect();
+    if truncated && let Some(last) = lines.last_mut() {
+        last.push_span("…".dim());
+    }
+    lines
+}
+
+/// Draw the dim left border shared by selection popups and return the area to the
+/// right of it where the popup content goes.
+fn render_popup_frame(area: Rect, buf: &mut Buffer) -> Rect {
     // Always draw a dim left border to match other popups.
     let block = Block::default()
         .borders(Borders::LEFT)
@@ -156,6 +176,56 @@ pub(crate) fn render_rows(
             }
         }
     }
+    content_area
+}
+
+/// Render dimmed preview text (e.g. details of the selected item) inside the same
+/// frame used by [`render_rows`]. Long lines are clipped rather than wrapped.
+pub(crate) fn render_preview(area: Rect, buf: &mut Buffer, text: &str) {
+    let content_area = render_popup_frame(area, buf);
+    let lines: Vec<Line> = text
+        .lines()
+        .map(|line| Line::from(line.to_string().dim()))
+        .collect();
+    Paragraph::new(lines).render(content_area, buf);
+}
+
+/// Render a list of rows using the provided ScrollState, with shared styling
+/// and behavior for selection popups.
+pub(crate) fn render_rows(
+    area: Rect,
+    buf: &mut Buffer,
+    rows_all: &[GenericDisplayRow],
+    state: &ScrollState,
+    max_results: usize,
+    dim_non_selected: bool,
+    empty_message: &str,
+) {
+    render_rows_with_line_limit(
+        area,
+        buf,
+        rows_all,
+        state,
+        max_results,
+        usize::MAX,
+        dim_non_selected,
+        empty_message,
+    );
+}
+
+/// Like [`render_rows`], but wraps each row onto at most `max_lines_per_row` lines.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn render_rows_with_line_limit(
+    area: Rect,
+    buf: &mut Buffer,
+    rows_all: &[GenericDisplayRow],
+    state: &ScrollState,
+    max_results: usize,
+    max_lines_per_row: usize,
+    _dim_non_selected: bool,
+    empty_message: &str,
+) {
+    let content_area = render_popup_frame(area, buf);
 
     if rows_all.is_empty() {
         if content_area.height > 0 {
@@ -207,30 +277,7 @@ pub(crate) fn render_rows(
             break;
         }
 
-        let GenericDisplayRow {
-            name,
-            match_indices,
-            is_current: _is_current,
-            description,
-        } = row;
-
-        let full_line = build_full_line(
-            &GenericDisplayRow {
-                name: name.clone(),
-                match_indices: match_indices.clone(),
-                is_current: *_is_current,
-                description: description.clone(),
-            },
-            desc_col,
-        );
-
-        // Wrap with subsequent indent aligned to the description column.
-        use crate::wrapping::RtOptions;
-        use crate::wrapping::word_wrap_line;
-        let options = RtOptions::new(content_area.width as usize)
-            .initial_indent(Line::from(""))
-            .subsequent_indent(Line::from(" ".repeat(desc_col)));
-        let wrapped = word_wrap_line(&full_line, options);
+        let wrapped = wrap_row(row, desc_col, content_area.width, max_lines_per_row);
 
         // Render the wrapped lines.
         for mut line in wrapped {
@@ -267,6 +314,17 @@ pub(crate) fn measure_rows_height(
     state: &ScrollState,
     max_results: usize,
     width: u16,
+) -> u16 {
+    measure_rows_height_with_line_limit(rows_all, state, max_results, usize::MAX, width)
+}
+
+/// Like [`measure_rows_height`], counting at most `max_lines_per_row` lines per row.
+pub(crate) fn measure_rows_height_with_line_limit(
+    rows_all: &[GenericDisplayRow],
+    state: &ScrollState,
+    max_results: usize,
+    max_lines_per_row: usize,
+    width: u16,
 ) -> u16 {
     if rows_all.is_empty() {
         return 1; // placeholder "no matches" line
@@ -289,8 +347,6 @@ pub(crate) fn measure_rows_height(
 
     let desc_col = compute_desc_col(rows_all, start_idx, visible_items, content_width);
 
-    use crate::wrapping::RtOptions;
-    use crate::wrapping::word_wrap_line;
     let mut total: u16 = 0;
     for row in rows_all
         .iter()
@@ -299,11 +355,8 @@ pub(crate) fn measure_rows_height(
         .take(visible_items)
         .map(|(_, r)| r)
     {
-        let full_line = build_full_line(row, desc_col);
-        let opts = RtOptions::new(content_width as usize)
-            .initial_indent(Line::from(""))
-            .subsequent_indent(Line::from(" ".repeat(desc_col)));
-        total = total.saturating_add(word_wrap_line(&full_line, opts).len() as u16);
+        let lines = wrap_row(row, desc_col, content_width, max_lines_per_row).len();
+        total = total.saturating_add(lines as u16);
     }
     total.max(1)
 }
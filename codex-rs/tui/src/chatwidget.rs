@@ -4,6 +4,7 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::LazyLock;
 
+use codex_core::agent_history::load_recent_agents;
 use codex_core::config::Config;
 use codex_core::config_types::Notifications;
 use codex_core::git_info::current_branch_name;
@@ -838,6 +839,7 @@ impl ChatWidget {
                 enhanced_keys_supported,
                 placeholder_text: placeholder,
                 disable_paste_burst: config.disable_paste_burst,
+                agent_popup: config.tui_agent_popup.clone(),
             }),
             active_cell: None,
             config: config.clone(),
@@ -899,6 +901,7 @@ impl ChatWidget {
                 enhanced_keys_supported,
                 placeholder_text: placeholder,
                 disable_paste_burst: config.disable_paste_burst,
+                agent_popup: config.tui_agent_popup.clone(),
             }),
             active_cell: None,
             config: config.clone(),
@@ -1353,7 +1356,15 @@ impl ChatWidget {
             EventMsg::ListCustomPromptsResponse(ev) => self.on_list_custom_prompts(ev),
             EventMsg::ListAgentsResponse(ev) => {
                 // Cache agents for @agent typeahead and add a transcript cell.
+                self.bottom_pane.set_agents_loading(false);
                 self.bottom_pane.set_agents(ev.agents.clone());
+                // The agent last used in this project leads the recent list.
+                let mut recent = load_recent_agents(&self.config.codex_home);
+                if let Some(last_used) = ev.last_used {
+                    recent.retain(|name| *name != last_used);
+                    recent.insert(0, last_used);
+                }
+                self.bottom_pane.set_recent_agents(recent);
                 self.add_to_history(history_cell::new_agents_list(ev.agents));
             }
             EventMsg::AgentBegin(ev) => {
@@ -1639,6 +1650,11 @@ impl ChatWidget {
         self.config.approval_policy = policy;
     }
 
+    /// Update the recently used agents shown first in the @agent popup.
+    pub(crate) fn set_recent_agents(&mut self, recent: Vec<String>) {
+        self.bottom_pane.set_recent_agents(recent);
+    }
+
     /// Set the sandbox policy in the widget's config copy.
     pub(crate) fn set_sandbox_policy(&mut self, policy: SandboxPolicy) {
         self.config.sandbox_policy = policy;
@@ -1725,9 +1741,13 @@ impl ChatWidget {
         self.bottom_pane.clear_esc_backtrack_hint();
     }
     /// Forward an `Op` directly to codex.
-    pub(crate) fn submit_op(&self, op: Op) {
+    pub(crate) fn submit_op(&mut self, op: Op) {
         // Record outbound operation for session replay fidelity.
         crate::session_log::log_outbound_op(&op);
+        if matches!(op, Op::ListAgents) {
+            // Until the response arrives an empty agent popup means "still loading".
+            self.bottom_pane.set_agents_loading(true);
+        }
         if let Err(e) = self.codex_op_tx.send(op) {
             tracing::error!("failed to submit op: {e}");
         }
@@ -1962,6 +1982,7 @@ impl ChatWidget {
             canon.insert(n.to_lowercase(), n.clone());
         }
 
+        #[allow(clippy::unwrap_used)]
         static RE_PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
             vec![
                 Regex::new(